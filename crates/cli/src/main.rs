@@ -0,0 +1,335 @@
+//! The `ambients` command line.
+//!
+//! ```text
+//! ambients parse [--format json|debug] [FILE]
+//! ambients fmt [--check | --write] [--compact] [FILE...]
+//! ambients check [--json] [FILE...]
+//! ambients reduce [--trace] [--json] [--max-steps N] [FILE]
+//! ```
+//!
+//! Every command reads standard input when it's given no file, or `-`. Commands exit with 0 on
+//! success, 1 when a program doesn't parse, with `fmt --check` isn't formatted, or with
+//! `reduce --max-steps` doesn't reach its normal form in time, and 2 when the command line is
+//! wrong or a file can't be read or written.
+
+use std::fs;
+use std::io::{ self, Read, Write };
+use std::path::PathBuf;
+use std::process;
+use std::str::FromStr;
+
+use ambients::reduction::{ self, Redex, Step };
+use ambients_parser::ast::{ Exec, OwnedExec };
+use ambients_parser::{ parse, ParseError };
+use serde_json::json;
+use structopt::clap::ErrorKind;
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+#[structopt(name = "ambients", about = "Parses, formats, checks and reduces ambient programs.")]
+enum Command {
+    /// Prints the syntax tree of a program.
+    Parse {
+        /// How to print the tree: `json` or `debug`.
+        #[structopt(long, default_value = "json")]
+        format: Format,
+        /// The program, or `-` for standard input.
+        #[structopt(default_value = "-")]
+        file: Input,
+    },
+    /// Prints programs in canonical form.
+    Fmt {
+        /// Prints the programs that aren't formatted instead, failing if there are any.
+        #[structopt(long, conflicts_with = "write")]
+        check: bool,
+        /// Rewrites the files in place instead of printing them.
+        #[structopt(short, long)]
+        write: bool,
+        /// Prints every program on one line.
+        #[structopt(long)]
+        compact: bool,
+        /// The programs, or `-` for standard input.
+        files: Vec<Input>,
+    },
+    /// Reports the parse errors of programs.
+    Check {
+        /// Prints one JSON object per error on standard output.
+        #[structopt(long)]
+        json: bool,
+        /// The programs, or `-` for standard input.
+        files: Vec<Input>,
+    },
+    /// Reduces a program to its normal form.
+    Reduce {
+        /// Prints every intermediate term, along with the redex that produced it.
+        #[structopt(long)]
+        trace: bool,
+        /// Prints one JSON object per term on standard output.
+        #[structopt(long)]
+        json: bool,
+        /// Fails if the program hasn't reached its normal form after this many steps.
+        #[structopt(long, value_name = "N")]
+        max_steps: Option<usize>,
+        /// The program, or `-` for standard input.
+        #[structopt(default_value = "-")]
+        file: Input,
+    },
+}
+
+/// How a command ended, ordered from best to worst.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Status {
+    Success = 0,
+    /// A program doesn't parse, isn't formatted or doesn't reduce in time.
+    Failure = 1,
+    /// The command line is wrong, or a file can't be read or written.
+    Error = 2,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Format {
+    Json,
+    Debug,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Format, String> {
+        match s {
+            "json" => Ok(Format::Json),
+            "debug" => Ok(Format::Debug),
+            _ => Err(format!("unknown format `{}`, expected `json` or `debug`", s)),
+        }
+    }
+}
+
+/// Where a program is read from.
+#[derive(Debug, Clone)]
+enum Input {
+    Stdin,
+    File(PathBuf),
+}
+
+impl FromStr for Input {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Input, String> {
+        Ok(if s == "-" { Input::Stdin } else { Input::File(PathBuf::from(s)) })
+    }
+}
+
+impl Input {
+    /// The name of the input in messages.
+    fn name(&self) -> String {
+        match self {
+            Input::Stdin => "<stdin>".to_string(),
+            Input::File(path) => path.display().to_string(),
+        }
+    }
+
+    fn read(&self) -> io::Result<String> {
+        match self {
+            Input::Stdin => {
+                let mut source = String::new();
+                io::stdin().read_to_string(&mut source)?;
+                Ok(source)
+            },
+            Input::File(path) => fs::read_to_string(path),
+        }
+    }
+}
+
+fn main() {
+    let command = match Command::from_iter_safe(std::env::args_os()) {
+        Ok(command) => command,
+        Err(e) if e.kind == ErrorKind::HelpDisplayed || e.kind == ErrorKind::VersionDisplayed => {
+            println!("{}", e.message);
+            process::exit(Status::Success as i32);
+        },
+        Err(e) => {
+            eprintln!("{}", e.message);
+            process::exit(Status::Error as i32);
+        },
+    };
+    let status = match command {
+        Command::Parse { format, file } => each(&[file], |out, input, source| parse_command(out, input, source, format)),
+        Command::Fmt { check, write, compact, files } =>
+            each(&files, |out, input, source| fmt_command(out, input, source, check, write, compact)),
+        Command::Check { json, files } => each(&files, |out, input, source| check_command(out, input, source, json)),
+        Command::Reduce { trace, json, max_steps, file } =>
+            each(&[file], |out, input, source| reduce_command(out, input, source, trace, json, max_steps)),
+    };
+    process::exit(status as i32);
+}
+
+/// Runs `command` on the source of every input, or of standard input if there are none,
+/// returning the worst status. Commands write their output to `out`; once it's closed, e.g. by
+/// `head`, the remaining inputs are skipped.
+fn each(inputs: &[Input], mut command: impl FnMut(&mut dyn Write, &Input, &str) -> io::Result<Status>) -> Status {
+    let stdin = [Input::Stdin];
+    let inputs = if inputs.is_empty() { &stdin[..] } else { inputs };
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    let mut status = Status::Success;
+    for input in inputs {
+        let source = match input.read() {
+            Ok(source) => source,
+            Err(e) => {
+                eprintln!("error: can't read {}: {}", input.name(), e);
+                status = status.max(Status::Error);
+                continue;
+            },
+        };
+        match command(&mut out, input, &source) {
+            Ok(done) => status = status.max(done),
+            Err(e) if e.kind() == io::ErrorKind::BrokenPipe => break,
+            Err(e) => {
+                eprintln!("error: can't write the output: {}", e);
+                return Status::Error;
+            },
+        }
+    }
+    status
+}
+
+/// Parses `source`, reporting any error on standard error.
+fn parsed<'a>(input: &Input, source: &'a str) -> Result<Exec<'a>, Status> {
+    parse(source).map_err(|e| {
+        eprint!("{}", e.render_in(&input.name(), source));
+        Status::Failure
+    })
+}
+
+fn parse_command(out: &mut dyn Write, input: &Input, source: &str, format: Format) -> io::Result<Status> {
+    let exec = match parsed(input, source) {
+        Ok(exec) => exec,
+        Err(status) => return Ok(status),
+    };
+    match format {
+        Format::Json => {
+            let json = serde_json::to_string_pretty(&OwnedExec::from(&exec)).expect("trees serialize");
+            writeln!(out, "{}", json)?;
+        },
+        Format::Debug => writeln!(out, "{:#?}", exec)?,
+    }
+    Ok(Status::Success)
+}
+
+fn fmt_command(out: &mut dyn Write, input: &Input, source: &str, check: bool, write: bool, compact: bool) -> io::Result<Status> {
+    let exec = match parsed(input, source) {
+        Ok(exec) => exec,
+        Err(status) => return Ok(status),
+    };
+    let formatted = if compact { format!("{}\n", exec) } else { format!("{:#}\n", exec) };
+    match input {
+        _ if check && formatted == source => Ok(Status::Success),
+        _ if check => {
+            writeln!(out, "{}", input.name())?;
+            Ok(Status::Failure)
+        },
+        Input::File(_) if write && formatted == source => Ok(Status::Success),
+        Input::File(path) if write => match fs::write(path, formatted) {
+            Ok(()) => Ok(Status::Success),
+            Err(e) => {
+                eprintln!("error: can't write {}: {}", input.name(), e);
+                Ok(Status::Error)
+            },
+        },
+        _ => {
+            write!(out, "{}", formatted)?;
+            Ok(Status::Success)
+        },
+    }
+}
+
+fn check_command(out: &mut dyn Write, input: &Input, source: &str, json: bool) -> io::Result<Status> {
+    match parse(source) {
+        Ok(_) => Ok(Status::Success),
+        Err(e) if json => {
+            writeln!(out, "{}", error_json(input, &e))?;
+            Ok(Status::Failure)
+        },
+        Err(e) => {
+            eprint!("{}", e.render_in(&input.name(), source));
+            Ok(Status::Failure)
+        },
+    }
+}
+
+/// A parse error as a single line of JSON.
+fn error_json(input: &Input, e: &ParseError) -> serde_json::Value {
+    json!({
+        "file": input.name(),
+        "message": e.to_string(),
+        "kind": format!("{:?}", e.kind),
+        "line": e.line,
+        "column": e.column,
+        "span": { "start": e.span.start, "end": e.span.end },
+        "token": e.token,
+        "expected": e.expected,
+    })
+}
+
+fn reduce_command(
+    out: &mut dyn Write,
+    input: &Input,
+    source: &str,
+    trace: bool,
+    json: bool,
+    max_steps: Option<usize>,
+) -> io::Result<Status> {
+    let exec = match parsed(input, source) {
+        Ok(exec) => exec,
+        Err(status) => return Ok(status),
+    };
+    let mut steps: Vec<Step> = Vec::new();
+    let mut reduction = reduction::trace(exec.clone());
+    let normal = loop {
+        match reduction.next() {
+            None => break true,
+            Some(_) if Some(steps.len()) == max_steps => break false,
+            Some(step) => steps.push(step),
+        }
+    };
+
+    let terms = steps.iter().map(|step| (Some(&step.redex), &step.exec));
+    let terms: Vec<_> = Some((None, &exec)).into_iter().chain(terms).enumerate().collect();
+    let shown = if trace { &terms[..] } else { &terms[terms.len() - 1..] };
+    if json {
+        for (n, (redex, exec)) in shown {
+            let mut line = json!({ "step": n, "term": exec.to_string() });
+            if let Some(redex) = redex {
+                line["redex"] = redex_json(redex);
+            }
+            writeln!(out, "{}", line)?;
+        }
+    } else if trace {
+        // Laid out like the traces in the docs, with the redexes lined up on the right.
+        let lines: Vec<_> = shown.iter().map(|(n, (redex, exec))| {
+            let arrow = if *n == 0 { " " } else { "→ " };
+            (format!("{}{}", arrow, exec), redex)
+        }).collect();
+        let width = lines.iter().filter(|(_, redex)| redex.is_some()).map(|(term, _)| term.chars().count()).max().unwrap_or(0);
+        for (term, redex) in lines {
+            match redex {
+                Some(redex) => writeln!(out, "{:width$}    {}", term, redex, width = width)?,
+                None => writeln!(out, "{}", term)?,
+            }
+        }
+    } else {
+        let (_, (_, last)) = shown[0];
+        writeln!(out, "{}", last)?;
+    }
+
+    if !normal {
+        let plural = if steps.len() == 1 { "" } else { "s" };
+        eprintln!("error: {} didn't reach its normal form in {} step{}", input.name(), steps.len(), plural);
+        return Ok(Status::Failure);
+    }
+    Ok(Status::Success)
+}
+
+fn redex_json(redex: &Redex) -> serde_json::Value {
+    json!({ "rule": redex.rule.as_str(), "ambient": redex.ambient, "target": redex.target, "path": redex.path })
+}
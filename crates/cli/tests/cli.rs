@@ -0,0 +1,247 @@
+//! Runs the `ambients` binary the way scripts do.
+
+use std::fs;
+use std::io::Write;
+use std::process::{ Command, Output, Stdio };
+
+/// Runs `ambients args...` with `stdin` as standard input.
+fn ambients(args: &[&str], stdin: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_ambients"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    // Commands that fail early exit without reading their input.
+    let _ = child.stdin.take().unwrap().write_all(stdin.as_bytes());
+    child.wait_with_output().unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8(output.stdout.clone()).unwrap()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8(output.stderr.clone()).unwrap()
+}
+
+const MESSAGE: &str = "message[in func.open_|func[x[in_ arg.open arg.in message.open_]|message[in_ x.open x]|in_ arg.open_]]";
+
+const FORMATTED: &str = "\
+message[
+  in func.open_|
+  func[
+    x[in_ arg.open arg.in message.open_]|
+    message[in_ x.open x]|
+    in_ arg.open_
+  ]
+]
+";
+
+#[test]
+fn parse_json() {
+    let output = ambients(&["parse"], "a[in b] | b[in_ a]");
+    assert_eq!(output.status.code(), Some(0));
+    let json: serde_json::Value = serde_json::from_str(&stdout(&output)).unwrap();
+    assert_eq!(json, serde_json::json!({
+        "Parallel": [
+            { "Ambient": ["a", { "In": "b" }] },
+            { "Ambient": ["b", { "In_": "a" }] },
+        ]
+    }));
+}
+
+#[test]
+fn parse_debug() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("a.amb");
+    fs::write(&path, "a[]").unwrap();
+    let output = ambients(&["parse", "--format", "debug", path.to_str().unwrap()], "");
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output), "Noop(\n    \"a\",\n)\n");
+
+    let output = ambients(&["parse", "--format", "yaml"], "a[]");
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stderr(&output).contains("unknown format `yaml`"));
+}
+
+#[test]
+fn parse_error() {
+    let output = ambients(&["parse", "-"], "a[in ]");
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(stdout(&output), "");
+    assert_eq!(stderr(&output), "\
+error: unexpected `]`, expected name
+ --> <stdin>:1:6
+  |
+1 | a[in ]
+  |      ^
+");
+}
+
+#[test]
+fn fmt_stdin() {
+    let output = ambients(&["fmt"], MESSAGE);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output), FORMATTED);
+
+    // Formatting is idempotent.
+    assert_eq!(stdout(&ambients(&["fmt"], FORMATTED)), FORMATTED);
+
+    let output = ambients(&["fmt", "--compact"], FORMATTED);
+    assert_eq!(stdout(&output), format!("{}\n", MESSAGE));
+}
+
+#[test]
+fn fmt_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let (message, formatted) = (dir.path().join("message.amb"), dir.path().join("formatted.amb"));
+    fs::write(&message, MESSAGE).unwrap();
+    fs::write(&formatted, FORMATTED).unwrap();
+    let (message_arg, formatted_arg) = (message.to_str().unwrap(), formatted.to_str().unwrap());
+
+    let output = ambients(&["fmt", "--check", message_arg, formatted_arg], "");
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(stdout(&output), format!("{}\n", message_arg));
+
+    let output = ambients(&["fmt", "--write", message_arg, formatted_arg], "");
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output), "");
+    assert_eq!(fs::read_to_string(&message).unwrap(), FORMATTED);
+
+    let output = ambients(&["fmt", "--check", message_arg, formatted_arg], "");
+    assert_eq!(output.status.code(), Some(0));
+
+    let output = ambients(&["fmt", "--check", "--write", message_arg], "");
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn fmt_keeps_broken_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("broken.amb");
+    fs::write(&path, "a[in b").unwrap();
+    let output = ambients(&["fmt", "-w", path.to_str().unwrap()], "");
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr(&output).contains("unexpected end of input"));
+    assert_eq!(fs::read_to_string(&path).unwrap(), "a[in b");
+}
+
+#[test]
+fn check() {
+    let dir = tempfile::tempdir().unwrap();
+    let (good, bad) = (dir.path().join("good.amb"), dir.path().join("bad.amb"));
+    fs::write(&good, MESSAGE).unwrap();
+    fs::write(&bad, "a[in b] |\nb[in_ a.#]").unwrap();
+    let (good_arg, bad_arg) = (good.to_str().unwrap(), bad.to_str().unwrap());
+
+    let output = ambients(&["check", good_arg], "");
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!((stdout(&output).as_str(), stderr(&output).as_str()), ("", ""));
+
+    let output = ambients(&["check", good_arg, bad_arg], "");
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(stderr(&output), format!("\
+error: invalid token `#`
+ --> {}:2:9
+  |
+2 | b[in_ a.#]
+  |         ^
+", bad_arg));
+
+    let output = ambients(&["check", "--json", bad_arg, "-"], "a[in b");
+    assert_eq!(output.status.code(), Some(1));
+    let errors: Vec<serde_json::Value> = stdout(&output).lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+    assert_eq!(errors.len(), 2);
+    assert_eq!(errors[0]["file"], bad_arg);
+    assert_eq!(errors[0]["kind"], "InvalidToken");
+    assert_eq!((errors[0]["line"].as_u64(), errors[0]["column"].as_u64()), (Some(2), Some(9)));
+    assert_eq!(errors[0]["span"], serde_json::json!({ "start": 18, "end": 19 }));
+    assert_eq!(errors[1]["file"], "<stdin>");
+    assert_eq!(errors[1]["message"], "unexpected end of input, expected `]`");
+}
+
+#[test]
+fn usage_errors() {
+    let output = ambients(&["check", "/nonexistent/a.amb"], "");
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stderr(&output).starts_with("error: can't read /nonexistent/a.amb"));
+
+    assert_eq!(ambients(&["frobnicate"], "").status.code(), Some(2));
+    assert_eq!(ambients(&[], "").status.code(), Some(2));
+
+    let output = ambients(&["--help"], "");
+    assert_eq!(output.status.code(), Some(0));
+    assert!(stdout(&output).contains("parse"));
+}
+
+const FUNC: &str = "func[in_ x.open x.open_] | x[in func.open_|result[]] | open func";
+
+#[test]
+fn reduce() {
+    let output = ambients(&["reduce"], FUNC);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output), "result[]\n");
+
+    // The `call` and `return` examples of the docs.
+    let call = "x[call[out x.in y.open_|payload[]] | out_ call] | y[in_ call.open call]";
+    assert_eq!(stdout(&ambients(&["reduce"], call)), "x[] | y[payload[]]\n");
+    let call_return = "x[call[out x.in y.open_|return[open_.in x]]|out_ call.in_ y] | y[in_ call.open call.open return]";
+    assert_eq!(stdout(&ambients(&["reduce"], call_return)), "x[y[]]\n");
+
+    let output = ambients(&["reduce"], "a[in b");
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr(&output).contains("unexpected end of input"));
+}
+
+#[test]
+fn reduce_trace() {
+    let output = ambients(&["reduce", "--trace"], FUNC);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output), &"
+ func[in_ x.open x.open_] | x[in func.open_|result[]] | open func
+→ func[x[open_|result[]]|open x.open_] | open func    x in func
+→ func[result[]|open_] | open func                    func: open x
+→ result[]                                            open func
+"[1..]);
+}
+
+#[test]
+fn reduce_trace_json() {
+    // The `arg` example of the docs.
+    let arg = "arg[in_ x.open x.in y.open_] | x[in arg.open_|input[]] | y[in_ arg.open arg.in func.open_] | func[in_ y.open y.open_]";
+    let output = ambients(&["reduce", "--trace", "--json"], arg);
+    assert_eq!(output.status.code(), Some(0));
+    let steps: Vec<serde_json::Value> = stdout(&output).lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+    assert_eq!(steps.len(), 7);
+    assert_eq!(steps[0], serde_json::json!({ "step": 0, "term": arg }));
+    assert_eq!(steps[1]["redex"], serde_json::json!({ "rule": "in", "ambient": "x", "target": "arg", "path": [] }));
+    assert_eq!(steps[2]["redex"], serde_json::json!({ "rule": "open", "ambient": "x", "target": null, "path": ["arg"] }));
+    assert_eq!(steps[6], serde_json::json!({
+        "step": 6,
+        "term": "func[input[]|open_]",
+        "redex": { "rule": "open", "ambient": "y", "target": null, "path": ["func"] },
+    }));
+
+    let output = ambients(&["reduce", "--json"], arg);
+    let last: serde_json::Value = serde_json::from_str(&stdout(&output)).unwrap();
+    assert_eq!(last, steps[6]);
+}
+
+#[test]
+fn reduce_max_steps() {
+    let output = ambients(&["reduce", "--max-steps", "1"], FUNC);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(stdout(&output), "func[x[open_|result[]]|open x.open_] | open func\n");
+    assert_eq!(stderr(&output), "error: <stdin> didn't reach its normal form in 1 step\n");
+
+    let output = ambients(&["reduce", "--trace", "--max-steps", "3"], FUNC);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output).lines().count(), 4);
+
+    let output = ambients(&["reduce", "--max-steps", "0"], "a[]");
+    assert_eq!((output.status.code(), stdout(&output)), (Some(0), "a[]\n".to_string()));
+
+    assert_eq!(ambients(&["reduce", "--max-steps", "many"], FUNC).status.code(), Some(2));
+}
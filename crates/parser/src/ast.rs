@@ -1,21 +1,151 @@
-#[derive(Debug, Clone)]
+use std::fmt;
+
+use serde::{ Deserialize, Serialize };
+
+/// An ambient expression. Every node carries the `Span` of the source it was parsed from.
+///
+/// Spans are left out of `PartialEq` and `Debug`, so a parsed tree compares equal to, and prints
+/// the same as, one built by hand with `Span::default()`.
+#[derive(Clone)]
 pub enum Exec<'input> {
-    Parallel(Vec<Exec<'input>>),
-    Serial(Vec<Exec<'input>>),
-    Noop(ID<'input>),
-    Ambient(ID<'input>, Box<Exec<'input>>),
-    Group(Box<Exec<'input>>),
-
-    Open(ID<'input>),
-    Open_(ID<'input>),
-    In(ID<'input>),
-    In_(ID<'input>),
-    Out(ID<'input>),
-    Out_(ID<'input>),
+    Parallel(Vec<Exec<'input>>, Span),
+    Serial(Vec<Exec<'input>>, Span),
+    Noop(ID<'input>, Span),
+    Ambient(ID<'input>, Box<Exec<'input>>, Span),
+    Group(Box<Exec<'input>>, Span),
+
+    Open(ID<'input>, Span),
+    Open_(ID<'input>, Span),
+    In(ID<'input>, Span),
+    In_(ID<'input>, Span),
+    Out(ID<'input>, Span),
+    Out_(ID<'input>, Span),
 
     // STRING(Box<Exec<'input>>)
 }
 
+impl<'input> Exec<'input> {
+    /// Where in the source this node came from.
+    pub fn span(&self) -> Span {
+        match self {
+            Exec::Parallel(_, span) | Exec::Serial(_, span) | Exec::Noop(_, span) |
+            Exec::Ambient(_, _, span) | Exec::Group(_, span) |
+            Exec::Open(_, span) | Exec::Open_(_, span) | Exec::In(_, span) |
+            Exec::In_(_, span) | Exec::Out(_, span) | Exec::Out_(_, span) => *span,
+        }
+    }
+}
+
+impl<'input> PartialEq for Exec<'input> {
+    fn eq(&self, other: &Exec<'input>) -> bool {
+        use Exec::*;
+        match (self, other) {
+            (Parallel(a, _), Parallel(b, _)) | (Serial(a, _), Serial(b, _)) => a == b,
+            (Ambient(a, x, _), Ambient(b, y, _)) => a == b && x == y,
+            (Group(x, _), Group(y, _)) => x == y,
+            (Noop(a, _), Noop(b, _)) |
+            (Open(a, _), Open(b, _)) | (Open_(a, _), Open_(b, _)) |
+            (In(a, _), In(b, _)) | (In_(a, _), In_(b, _)) |
+            (Out(a, _), Out(b, _)) | (Out_(a, _), Out_(b, _)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl<'input> fmt::Debug for Exec<'input> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Exec::Parallel(v, _) => f.debug_tuple("Parallel").field(v).finish(),
+            Exec::Serial(v, _) => f.debug_tuple("Serial").field(v).finish(),
+            Exec::Noop(id, _) => f.debug_tuple("Noop").field(id).finish(),
+            Exec::Ambient(id, e, _) => f.debug_tuple("Ambient").field(id).field(e).finish(),
+            Exec::Group(e, _) => f.debug_tuple("Group").field(e).finish(),
+            Exec::Open(id, _) => f.debug_tuple("Open").field(id).finish(),
+            Exec::Open_(id, _) => f.debug_tuple("Open_").field(id).finish(),
+            Exec::In(id, _) => f.debug_tuple("In").field(id).finish(),
+            Exec::In_(id, _) => f.debug_tuple("In_").field(id).finish(),
+            Exec::Out(id, _) => f.debug_tuple("Out").field(id).finish(),
+            Exec::Out_(id, _) => f.debug_tuple("Out_").field(id).finish(),
+        }
+    }
+}
+
+/// An `Exec` that owns its names, so it can outlive the source it was parsed from, be sent
+/// between threads, hashed and serialized.
+///
+/// Spans only make sense next to the source, so they are dropped in the conversion and
+/// `as_exec` gives back a tree with `Span::default()` everywhere.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum OwnedExec {
+    Parallel(Vec<OwnedExec>),
+    Serial(Vec<OwnedExec>),
+    Noop(String),
+    Ambient(String, Box<OwnedExec>),
+    Group(Box<OwnedExec>),
+
+    Open(String),
+    Open_(String),
+    In(String),
+    In_(String),
+    Out(String),
+    Out_(String),
+}
+
+impl OwnedExec {
+    /// Borrows the tree back as an `Exec`, e.g. to reduce or print it.
+    pub fn as_exec(&self) -> Exec<'_> {
+        fn all(v: &[OwnedExec]) -> Vec<Exec<'_>> {
+            v.iter().map(OwnedExec::as_exec).collect()
+        }
+
+        let s = Span::default();
+        match self {
+            OwnedExec::Parallel(v) => Exec::Parallel(all(v), s),
+            OwnedExec::Serial(v) => Exec::Serial(all(v), s),
+            OwnedExec::Noop(id) => Exec::Noop(id, s),
+            OwnedExec::Ambient(id, e) => Exec::Ambient(id, Box::new(e.as_exec()), s),
+            OwnedExec::Group(e) => Exec::Group(Box::new(e.as_exec()), s),
+            OwnedExec::Open(id) => Exec::Open(id, s),
+            OwnedExec::Open_(id) => Exec::Open_(id, s),
+            OwnedExec::In(id) => Exec::In(id, s),
+            OwnedExec::In_(id) => Exec::In_(id, s),
+            OwnedExec::Out(id) => Exec::Out(id, s),
+            OwnedExec::Out_(id) => Exec::Out_(id, s),
+        }
+    }
+}
+
+impl<'a, 'input> From<&'a Exec<'input>> for OwnedExec {
+    fn from(exec: &'a Exec<'input>) -> OwnedExec {
+        let all = |v: &[Exec]| v.iter().map(OwnedExec::from).collect();
+        match exec {
+            Exec::Parallel(v, _) => OwnedExec::Parallel(all(v)),
+            Exec::Serial(v, _) => OwnedExec::Serial(all(v)),
+            Exec::Noop(id, _) => OwnedExec::Noop(id.to_string()),
+            Exec::Ambient(id, e, _) => OwnedExec::Ambient(id.to_string(), Box::new(OwnedExec::from(&**e))),
+            Exec::Group(e, _) => OwnedExec::Group(Box::new(OwnedExec::from(&**e))),
+            Exec::Open(id, _) => OwnedExec::Open(id.to_string()),
+            Exec::Open_(id, _) => OwnedExec::Open_(id.to_string()),
+            Exec::In(id, _) => OwnedExec::In(id.to_string()),
+            Exec::In_(id, _) => OwnedExec::In_(id.to_string()),
+            Exec::Out(id, _) => OwnedExec::Out(id.to_string()),
+            Exec::Out_(id, _) => OwnedExec::Out_(id.to_string()),
+        }
+    }
+}
+
+impl<'input> From<Exec<'input>> for OwnedExec {
+    fn from(exec: Exec<'input>) -> OwnedExec {
+        OwnedExec::from(&exec)
+    }
+}
+
+impl fmt::Display for OwnedExec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.as_exec(), f)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum Expr<'input> {
     // Capabilities and Co-Capabilities
@@ -25,3 +155,65 @@ pub enum Expr<'input> {
 
 // "Atom" types are just basic Rust types
 type ID<'input> = &'input str;
+
+/// A byte range in the source text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Span {
+        Span { start, end }
+    }
+
+    /// The smallest span containing both `self` and `other`.
+    pub fn to(self, other: Span) -> Span {
+        Span::new(self.start.min(other.start), self.end.max(other.end))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::thread;
+    use pretty_assertions::{ assert_eq };
+    use crate::parse;
+    use super::*;
+
+    #[test]
+    fn owned_from_borrowed() {
+        let owned = {
+            let source = String::from("a[in b.open_] | b[in_ a]");
+            OwnedExec::from(parse(&source).unwrap())
+        };
+        assert_eq!(owned, OwnedExec::Parallel(vec![
+            OwnedExec::Ambient("a".into(), Box::new(OwnedExec::Serial(vec![
+                OwnedExec::In("b".into()), OwnedExec::Open_("*".into())
+            ]))),
+            OwnedExec::Ambient("b".into(), Box::new(OwnedExec::In_("a".into()))),
+        ]));
+        assert_eq!(owned.as_exec(), parse("a[in b.open_] | b[in_ a]").unwrap());
+        assert_eq!(owned.to_string(), "a[in b.open_] | b[in_ a]");
+
+        let handle = thread::spawn(move || owned.to_string());
+        assert_eq!(handle.join().unwrap(), "a[in b.open_] | b[in_ a]");
+    }
+
+    #[test]
+    fn owned_hash() {
+        let programs: HashSet<OwnedExec> = ["a[]", "b[]", "a[]", "  a[]\n", "(a[]|b[])"].iter()
+            .map(|p| parse(p).unwrap().into())
+            .collect();
+        assert_eq!(programs.len(), 3);
+    }
+
+    #[test]
+    fn owned_serde() {
+        let owned = OwnedExec::from(parse("func[in_ x.open x.(open_|0)]").unwrap());
+        let json = serde_json::to_string(&owned).unwrap();
+        assert_eq!(json, r#"{"Ambient":["func",{"Serial":[{"In_":"x"},{"Open":"x"},{"Group":{"Parallel":[{"Open_":"*"},{"Parallel":[]}]}}]}]}"#);
+        assert_eq!(serde_json::from_str::<OwnedExec>(&json).unwrap(), owned);
+    }
+}
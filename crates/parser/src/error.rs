@@ -0,0 +1,207 @@
+use std::error::Error;
+use std::fmt;
+
+use lalrpop_util::ParseError as LalrpopError;
+
+use crate::ast::Span;
+
+/// What went wrong while parsing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    /// A character sequence that isn't a token of the language, e.g. `#`.
+    InvalidToken,
+    /// A valid token in a position where the grammar doesn't allow it.
+    UnrecognizedToken,
+    /// The input ended in the middle of an expression.
+    UnexpectedEof,
+    /// A complete expression was followed by more input.
+    ExtraToken,
+}
+
+/// A parse error, located in the source it came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    /// What went wrong.
+    pub kind: ParseErrorKind,
+    /// The byte range of the offending token, empty for errors at a single position.
+    pub span: Span,
+    /// The 1-based line `span` starts on.
+    pub line: usize,
+    /// The 1-based column, in characters, `span` starts at.
+    pub column: usize,
+    /// The offending token, if there is one.
+    pub token: Option<String>,
+    /// The tokens the grammar would have accepted instead.
+    pub expected: Vec<String>,
+}
+
+impl ParseError {
+    /// Locates a raw LALRPOP error in `source`. Only errors from the generated parser, which
+    /// has no user errors, can be located.
+    pub(crate) fn new<T: fmt::Display, E>(source: &str, error: LalrpopError<usize, T, E>) -> ParseError {
+        let (kind, span, token, expected) = match error {
+            LalrpopError::InvalidToken { location } => {
+                let end = source[location..].chars().next().map_or(location, |c| location + c.len_utf8());
+                (ParseErrorKind::InvalidToken, Span::new(location, end), Some(source[location..end].to_string()), vec![])
+            },
+            LalrpopError::UnrecognizedEOF { location, expected } =>
+                (ParseErrorKind::UnexpectedEof, Span::new(location, location), None, expected),
+            LalrpopError::UnrecognizedToken { token: (start, token, end), expected } =>
+                (ParseErrorKind::UnrecognizedToken, Span::new(start, end), Some(token.to_string()), expected),
+            LalrpopError::ExtraToken { token: (start, token, end) } =>
+                (ParseErrorKind::ExtraToken, Span::new(start, end), Some(token.to_string()), vec![]),
+            LalrpopError::User { .. } => unreachable!("the grammar has no user errors"),
+        };
+        let (line, column) = line_column(source, span.start);
+        // `0` is a name too, so it goes without saying wherever a name is expected.
+        let names = expected.iter().any(|e| e == "ID");
+        let expected = expected.iter()
+            .filter(|e| !(names && *e == "\"0\""))
+            .map(|e| describe_terminal(e))
+            .collect();
+        ParseError { kind, span, line, column, token, expected }
+    }
+
+    /// Renders the error with the offending line of `source`, underlining the span with carets:
+    ///
+    /// ```text
+    /// error: unexpected `]`, expected name
+    ///  --> 1:6
+    ///   |
+    /// 1 | a[in ]
+    ///   |      ^
+    /// ```
+    pub fn render(&self, source: &str) -> String {
+        self.render_at(&format!("{}:{}", self.line, self.column), source)
+    }
+
+    /// Renders the error like `render`, locating it in the file `path`, e.g. `--> a.amb:1:6`.
+    pub fn render_in(&self, path: &str, source: &str) -> String {
+        self.render_at(&format!("{}:{}:{}", path, self.line, self.column), source)
+    }
+
+    fn render_at(&self, location: &str, source: &str) -> String {
+        let text = source.lines().nth(self.line - 1).unwrap_or("");
+        let gutter = " ".repeat(self.line.to_string().len());
+        let start = self.column - 1;
+        let width = source[self.span.start..self.span.end].chars()
+            .take_while(|c| *c != '\n')
+            .count()
+            .max(1);
+
+        format!(
+            "error: {}\n{}--> {}\n{} |\n{} | {}\n{} | {}{}\n",
+            self,
+            gutter, location,
+            gutter,
+            self.line, text,
+            gutter, " ".repeat(start), "^".repeat(width)
+        )
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.kind, &self.token) {
+            (ParseErrorKind::InvalidToken, Some(token)) => write!(f, "invalid token `{}`", token)?,
+            (ParseErrorKind::ExtraToken, Some(token)) => write!(f, "unexpected `{}` after the end of the expression", token)?,
+            (_, Some(token)) => write!(f, "unexpected `{}`", token)?,
+            (_, None) => write!(f, "unexpected end of input")?,
+        }
+        match self.expected.as_slice() {
+            [] => Ok(()),
+            [one] => write!(f, ", expected {}", one),
+            many => write!(f, ", expected one of {}", many.join(", ")),
+        }
+    }
+}
+
+impl Error for ParseError {}
+
+/// 1-based line and column of byte offset `at` in `source`.
+fn line_column(source: &str, at: usize) -> (usize, usize) {
+    let before = &source[..at];
+    let line = before.matches('\n').count() + 1;
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    (line, before[line_start..].chars().count() + 1)
+}
+
+/// Turns LALRPOP's terminal names, e.g. `"["` or `ID`, into something readable.
+fn describe_terminal(terminal: &str) -> String {
+    if terminal == "ID" {
+        "name".to_string()
+    } else {
+        format!("`{}`", terminal.trim_matches('"'))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::{ assert_eq };
+    use crate::parse;
+    use super::*;
+
+    #[test]
+    fn unrecognized_token() {
+        let source = "a[in b] | b[in ]";
+        let err = parse(source).unwrap_err();
+        assert_eq!(err.kind, ParseErrorKind::UnrecognizedToken);
+        assert_eq!(err.span, Span::new(15, 16));
+        assert_eq!((err.line, err.column), (1, 16));
+        assert_eq!(err.token.as_deref(), Some("]"));
+        assert_eq!(err.expected, vec!["name"]);
+        assert_eq!(err.to_string(), "unexpected `]`, expected name");
+    }
+
+    #[test]
+    fn unexpected_eof() {
+        let source = "a[in b";
+        let err = parse(source).unwrap_err();
+        assert_eq!(err.kind, ParseErrorKind::UnexpectedEof);
+        assert_eq!(err.span, Span::new(6, 6));
+        assert_eq!((err.line, err.column), (1, 7));
+        assert_eq!(err.token, None);
+        assert_eq!(err.to_string(), "unexpected end of input, expected `]`");
+    }
+
+    #[test]
+    fn invalid_token() {
+        let err = parse("a[] | #").unwrap_err();
+        assert_eq!(err.kind, ParseErrorKind::InvalidToken);
+        assert_eq!(err.span, Span::new(6, 7));
+        assert_eq!(err.to_string(), "invalid token `#`");
+    }
+
+    #[test]
+    fn render_multiline() {
+        let source = "
+string_concat[
+  in_ call.open call.(
+    func[open_]|
+    open return.open_ open_
+  )
+]";
+        let err = parse(source).unwrap_err();
+        assert_eq!((err.line, err.column), (5, 23));
+        assert_eq!(err.render(source), "\
+error: unexpected `open_`, expected one of `)`, `.`, `]`, `|`, name
+ --> 5:23
+  |
+5 |     open return.open_ open_
+  |                       ^^^^^
+");
+    }
+
+    #[test]
+    fn render_in_file() {
+        let source = "a[in ]";
+        let err = parse(source).unwrap_err();
+        assert_eq!(err.render_in("a.amb", source), "\
+error: unexpected `]`, expected name
+ --> a.amb:1:6
+  |
+1 | a[in ]
+  |      ^
+");
+    }
+}
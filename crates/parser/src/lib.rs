@@ -1,27 +1,53 @@
 #![deny(warnings)]
 
-mod ast;
+pub mod ast;
+mod error;
+mod printer;
+
+pub use error::{ ParseError, ParseErrorKind };
+pub use printer::Pretty;
 
 #[macro_use] extern crate lalrpop_util;
-lalrpop_mod!(pub ambients); // synthesized by LALRPOP
+lalrpop_mod!(#[allow(clippy::all)] pub ambients); // synthesized by LALRPOP
+
+/// Parses `source` into an `Exec` tree, locating any error in the source.
+pub fn parse(source: &str) -> Result<ast::Exec<'_>, ParseError> {
+    ambients::ExecutionParser::new().parse(source).map_err(|e| ParseError::new(source, e))
+}
 
 #[cfg(test)]
 mod test {
     use pretty_assertions::{ assert_eq };
     use super::ambients::{ ExecutionParser as Parser };
-    use super::ast::{ Exec::* };
+    use super::ast::{ Exec::*, Span };
+
+    /// Spans are ignored when comparing trees, so hand-built ones can do without.
+    const S: Span = Span { start: 0, end: 0 };
 
     #[test]
     fn ambients_values() {
         let expr = Parser::new().parse("a[]").unwrap();
-        let expected = Noop("a");
+        let expected = Noop("a", S);
         assert_eq!(&format!("{:?}", expr), &format!("{:?}", expected));
 
         let expr = Parser::new().parse("hello[]").unwrap();
-        let expected = Noop("hello");
+        let expected = Noop("hello", S);
         assert_eq!(&format!("{:?}", expr), &format!("{:?}", expected));
     }
 
+    #[test]
+    fn ambients_zero() {
+        assert_eq!(Parser::new().parse("0").unwrap(), Parallel(vec![], S));
+        assert_eq!(Parser::new().parse("0[]").unwrap(), Noop("0", S));
+        let expr = Parser::new().parse("0[in 0.open_] | 0 | a[out_ 0]").unwrap();
+        let expected = Parallel(vec![
+            Ambient("0", Box::new(Serial(vec![In("0", S), Open_("*", S)], S)), S),
+            Parallel(vec![], S),
+            Ambient("a", Box::new(Out_("0", S)), S),
+        ], S);
+        assert_eq!(expr, expected);
+    }
+
     // #[test]
     // fn ambients_typed() {
     //     let expr = Parser::new().parse("string[hello[]]").unwrap();
@@ -29,22 +55,56 @@ mod test {
     //     assert_eq!(&format!("{:?}", expr), &format!("{:?}", expected));
     // }
 
+    #[test]
+    fn ambients_spans() {
+        let source = "a[in b] | b[in_ a.(open_|c[])]";
+        let expr = Parser::new().parse(source).unwrap();
+        assert_eq!(expr.span(), Span::new(0, 30));
+
+        let (a, b) = match &expr {
+            Parallel(v, _) => (&v[0], &v[1]),
+            e => panic!("unexpected {:?}", e),
+        };
+        assert_eq!(&source[a.span().start..a.span().end], "a[in b]");
+        assert_eq!(&source[b.span().start..b.span().end], "b[in_ a.(open_|c[])]");
+
+        let (serial, group) = match b {
+            Ambient(_, e, _) => match &**e {
+                Serial(v, span) => (*span, &v[1]),
+                e => panic!("unexpected {:?}", e),
+            },
+            e => panic!("unexpected {:?}", e),
+        };
+        assert_eq!(&source[serial.start..serial.end], "in_ a.(open_|c[])");
+        assert_eq!(&source[group.span().start..group.span().end], "(open_|c[])");
+
+        let (open_, c) = match group {
+            Group(e, _) => match &**e {
+                Parallel(v, _) => (&v[0], &v[1]),
+                e => panic!("unexpected {:?}", e),
+            },
+            e => panic!("unexpected {:?}", e),
+        };
+        assert_eq!(open_.span(), Span::new(19, 24));
+        assert_eq!(c.span(), Span::new(25, 28));
+    }
+
     #[test]
     fn ambients_parallel() {
         let expr = Parser::new().parse("a[] | b[]").unwrap();
 
-        let a = Noop("a");
-        let b = Noop("b");
+        let a = Noop("a", S);
+        let b = Noop("b", S);
 
-        let expected = Parallel(vec![a,b]);
+        let expected = Parallel(vec![a,b], S);
         assert_eq!(&format!("{:?}", expr), &format!("{:?}", expected));
 
         let expr = Parser::new().parse("a[ b[] ] | c[]").unwrap();
 
-        let b = Noop("b");
-        let a = Ambient("a", Box::new(b));
-        let c = Noop("c");
-        let expected = Parallel(vec![a,c]);
+        let b = Noop("b", S);
+        let a = Ambient("a", Box::new(b), S);
+        let c = Noop("c", S);
+        let expected = Parallel(vec![a,c], S);
 
         assert_eq!(&format!("{:?}", expr), &format!("{:?}", expected));
     }
@@ -53,30 +113,30 @@ mod test {
     fn ambient_capabilities() {
         let expr = Parser::new().parse("a[b[open_|c[]]|open b]").unwrap();
         let expected = Ambient("a", Box::new(Parallel(vec![
-                    Ambient("b", Box::new(Parallel(vec![Open_("*"),Noop("c")]))),
-                    Open("b")
-        ])));
+                    Ambient("b", Box::new(Parallel(vec![Open_("*", S),Noop("c", S)], S)), S),
+                    Open("b", S)
+        ], S)), S);
         assert_eq!(&format!("{:?}", expr), &format!("{:?}", expected));
 
        let expr = Parser::new().parse("a[in b] | b[in_ a]").unwrap();
        let expected = Parallel(vec![
-           Ambient("a", Box::new(In("b"))),
-           Ambient("b", Box::new(In_("a")))
-       ]);
+           Ambient("a", Box::new(In("b", S)), S),
+           Ambient("b", Box::new(In_("a", S)), S)
+       ], S);
        assert_eq!(&format!("{:?}", expr), &format!("{:?}", expected));
 
        let expr = Parser::new().parse("b[a[out b]|out_ a]").unwrap();
        let expected = Ambient("b", Box::new(Parallel(vec![
-                   Ambient("a", Box::new(Out("b"))),
-                   Out_("a")
-       ])));
+                   Ambient("a", Box::new(Out("b", S)), S),
+                   Out_("a", S)
+       ], S)), S);
        assert_eq!(&format!("{:?}", expr), &format!("{:?}", expected));
 
        let expr = Parser::new().parse("a[b[open_|c[]]|open b]").unwrap();
        let expected = Ambient("a", Box::new(Parallel(vec![
-                   Ambient("b", Box::new(Parallel(vec![Open_("*"), Noop("c")]))),
-                   Open("b")
-       ])));
+                   Ambient("b", Box::new(Parallel(vec![Open_("*", S), Noop("c", S)], S)), S),
+                   Open("b", S)
+       ], S)), S);
        assert_eq!(&format!("{:?}", expr), &format!("{:?}", expected));
     }
 
@@ -84,18 +144,18 @@ mod test {
     fn ambient_paths() {
         let expr = Parser::new().parse("a[in c] | b[in c] | c[in_ a.in_ b.in d] | d[in_ c]").unwrap();
         let expected = Parallel(vec![
-            Ambient("a", Box::new(In("c"))),
-            Ambient("b", Box::new(In("c"))),
-            Ambient("c", Box::new(Serial(vec![In_("a"), In_("b"), In("d")]))),
-            Ambient("d", Box::new(In_("c"))),
-        ]);
+            Ambient("a", Box::new(In("c", S)), S),
+            Ambient("b", Box::new(In("c", S)), S),
+            Ambient("c", Box::new(Serial(vec![In_("a", S), In_("b", S), In("d", S)], S)), S),
+            Ambient("d", Box::new(In_("c", S)), S),
+        ], S);
         assert_eq!(&format!("{:?}", expr), &format!("{:?}", expected));
 
         let expr = Parser::new().parse("a[in b.in_ |b[]]").unwrap();
         let expected = Ambient("a", Box::new(Parallel(vec![
-                Serial(vec![In("b"), In_("*")]),
-                Noop("b")
-        ])));
+                Serial(vec![In("b", S), In_("*", S)], S),
+                Noop("b", S)
+        ], S)), S);
         assert_eq!(&format!("{:?}", expr), &format!("{:?}", expected));
     }
 
@@ -104,19 +164,19 @@ mod test {
     fn ambient_func() {
         let expr = Parser::new().parse("func[in_ x.open x.open_]").unwrap();
         let expected = Ambient("func", Box::new(
-                Serial(vec![In_("x"), Open("x"), Open_("*")])
-        ));
+                Serial(vec![In_("x", S), Open("x", S), Open_("*", S)], S)
+        ), S);
         assert_eq!(&format!("{:?}", expr), &format!("{:?}", expected));
 
         let expr = Parser::new().parse("func[in_ x.open x.open_] | x[in func.open_|result[]] |open func").unwrap();
         let expected = Parallel(vec![
-            Ambient("func", Box::new(Serial(vec![In_("x"), Open("x"), Open_("*")]))),
+            Ambient("func", Box::new(Serial(vec![In_("x", S), Open("x", S), Open_("*", S)], S)), S),
             Ambient("x", Box::new(Parallel(vec![
-                        Serial(vec![In("func"), Open_("*")]),
-                        Noop("result")
-            ]))),
-            Open("func")
-        ]);
+                        Serial(vec![In("func", S), Open_("*", S)], S),
+                        Noop("result", S)
+            ], S)), S),
+            Open("func", S)
+        ], S);
         assert_eq!(&format!("{:?}", expr), &format!("{:?}", expected));
     }
 
@@ -125,12 +185,12 @@ mod test {
         let expr = Parser::new().parse("arg[in_ x.open x.in y.open_] | y[in_ arg.open arg.in func.open_]").unwrap();
         let expected = Parallel(vec![
             Ambient("arg", Box::new(Serial(vec![
-                        In_("x"), Open("x"), In("y"), Open_("*")
-            ]))),
+                        In_("x", S), Open("x", S), In("y", S), Open_("*", S)
+            ], S)), S),
             Ambient("y", Box::new(Serial(vec![
-                        In_("arg"), Open("arg"), In("func"), Open_("*")
-            ])))
-        ]);
+                        In_("arg", S), Open("arg", S), In("func", S), Open_("*", S)
+            ], S)), S)
+        ], S);
         assert_eq!(&format!("{:?}", expr), &format!("{:?}", expected));
 
         let program = "
@@ -140,11 +200,11 @@ func[in_ y.open y.open_]
 ";
         let expr = Parser::new().parse(program).unwrap();
         let expected = Parallel(vec![
-            Ambient("arg", Box::new(Serial(vec![In_("x"), Open("x"), In("y"), Open_("*")]))),
-            Ambient("x", Box::new(Parallel(vec![Serial(vec![In("arg"), Open_("*")]), Noop("input")]))),
-            Ambient("y", Box::new(Serial(vec![In_("arg"), Open("arg"), In("func"), Open_("*")]))),
-            Ambient("func", Box::new(Serial(vec![In_("y"), Open("y"), Open_("*")])))
-        ]);
+            Ambient("arg", Box::new(Serial(vec![In_("x", S), Open("x", S), In("y", S), Open_("*", S)], S)), S),
+            Ambient("x", Box::new(Parallel(vec![Serial(vec![In("arg", S), Open_("*", S)], S), Noop("input", S)], S)), S),
+            Ambient("y", Box::new(Serial(vec![In_("arg", S), Open("arg", S), In("func", S), Open_("*", S)], S)), S),
+            Ambient("func", Box::new(Serial(vec![In_("y", S), Open("y", S), Open_("*", S)], S)), S)
+        ], S);
         assert_eq!(&format!("{:?}", expr), &format!("{:?}", expected));
     }
 
@@ -171,24 +231,24 @@ open func
         let expr = Parser::new().parse(program).unwrap();
         let expected = Parallel(vec![
             Ambient("message", Box::new(Parallel(vec![
-                Serial(vec![In("func"), Open_("*")]),
+                Serial(vec![In("func", S), Open_("*", S)], S),
                 Ambient("func", Box::new(Parallel(vec![
                     Ambient("x", Box::new(
-                        Serial(vec![In_("arg"), Open("arg"), In("message"), Open_("*")]))
+                        Serial(vec![In_("arg", S), Open("arg", S), In("message", S), Open_("*", S)], S)), S
                     ),
-                    Ambient("message", Box::new(Serial(vec![In_("x"), Open("x")]))),
-                    Serial(vec![In_("arg"), Open_("*")])
-                ])))
-            ]))),
+                    Ambient("message", Box::new(Serial(vec![In_("x", S), Open("x", S)], S)), S),
+                    Serial(vec![In_("arg", S), Open_("*", S)], S)
+                ], S)), S)
+            ], S)), S),
             Ambient("func", Box::new(Parallel(vec![
-                Serial(vec![In_("message"), Open("message"), Open("func"), Open_("*")]),
+                Serial(vec![In_("message", S), Open("message", S), Open("func", S), Open_("*", S)], S),
                 Ambient("arg", Box::new(Parallel(vec![
-                    Serial(vec![In("func"), In("x"), Open_("*")]),
-                    Ambient("string", Box::new(Noop("hello")))
-                ])))
-            ]))),
-            Open("func")
-        ]);
+                    Serial(vec![In("func", S), In("x", S), Open_("*", S)], S),
+                    Ambient("string", Box::new(Noop("hello", S)), S)
+                ], S)), S)
+            ], S)), S),
+            Open("func", S)
+        ], S);
         assert_eq!(&format!("{:?}", expr), &format!("{:?}", expected));
     }
 
@@ -197,8 +257,8 @@ open func
         let program = "call[out x.in y.open_]";
         let expr = Parser::new().parse(program).unwrap();
         let expected = Ambient("call", Box::new(Serial(vec![
-            Out("x"), In("y"), Open_("*")
-        ])));
+            Out("x", S), In("y", S), Open_("*", S)
+        ], S)), S);
         assert_eq!(&format!("{:?}", expr), &format!("{:?}", expected));
 
         let program = "
@@ -209,13 +269,13 @@ y[in_ call.open call]
         let expected = Parallel(vec![
             Ambient("x", Box::new(Parallel(vec![
                 Ambient("call", Box::new(Parallel(vec![
-                    Serial(vec![Out("x"), In("y"), Open_("*")]),
-                    Noop("payload")
-                ]))),
-                Out_("call")
-            ]))),
-            Ambient("y", Box::new(Serial(vec![In_("call"), Open("call")])))
-        ]);
+                    Serial(vec![Out("x", S), In("y", S), Open_("*", S)], S),
+                    Noop("payload", S)
+                ], S)), S),
+                Out_("call", S)
+            ], S)), S),
+            Ambient("y", Box::new(Serial(vec![In_("call", S), Open("call", S)], S)), S)
+        ], S);
         assert_eq!(&format!("{:?}", expr), &format!("{:?}", expected));
     }
 
@@ -223,7 +283,7 @@ y[in_ call.open call]
     fn ambient_return() {
         let program = "return[open_.in x]";
         let expr = Parser::new().parse(program).unwrap();
-        let expected = Ambient("return", Box::new(Serial(vec![Open_("*"), In("x")])));
+        let expected = Ambient("return", Box::new(Serial(vec![Open_("*", S), In("x", S)], S)), S);
         assert_eq!(&format!("{:?}", expr), &format!("{:?}", expected));
 
         let program = "
@@ -237,13 +297,13 @@ y[in_ call.open call.open return]
         let expected = Parallel(vec![
             Ambient("x", Box::new(Parallel(vec![
                 Ambient("call", Box::new(Parallel(vec![
-                    Serial(vec![Out("x"), In("y"), Open_("*")]),
-                    Ambient("return", Box::new(Serial(vec![Open_("*"), In("x")])))
-                ]))),
-                Serial(vec![Out_("call"), In_("y")])
-            ]))),
-            Ambient("y", Box::new(Serial(vec![In_("call"), Open("call"), Open("return")])))
-        ]);
+                    Serial(vec![Out("x", S), In("y", S), Open_("*", S)], S),
+                    Ambient("return", Box::new(Serial(vec![Open_("*", S), In("x", S)], S)), S)
+                ], S)), S),
+                Serial(vec![Out_("call", S), In_("y", S)], S)
+            ], S)), S),
+            Ambient("y", Box::new(Serial(vec![In_("call", S), Open("call", S), Open("return", S)], S)), S)
+        ], S);
         assert_eq!(&format!("{:?}", expr), &format!("{:?}", expected));
     }
 
@@ -270,25 +330,25 @@ string_concat[
 ]";
         let expr = Parser::new().parse(program).unwrap();
         let expected = Ambient("string_concat", Box::new(
-            Serial(vec![In_("call"), Open("call"), Group(Box::new(
+            Serial(vec![In_("call", S), Open("call", S), Group(Box::new(
                 Parallel(vec![
                     Ambient("func", Box::new(Parallel(vec![
                         Ambient("left", Box::new(
-                            Serial(vec![In_("arg"), Open("arg"), In("string"), In("concat")])
-                        )),
+                            Serial(vec![In_("arg", S), Open("arg", S), In("string", S), In("concat", S)], S)
+                        ), S),
                         Ambient("right", Box::new(
-                            Serial(vec![In_("arg"), Open("arg"), In("string"), In("concat")])
-                        )),
+                            Serial(vec![In_("arg", S), Open("arg", S), In("string", S), In("concat", S)], S)
+                        ), S),
                         Ambient("string", Box::new(Parallel(vec![
-                            Ambient("concat", Box::new(Parallel(vec![In_("left"), In_("right")]))),
-                            In_("left"),
-                            In_("right")
-                        ]))),
-                        Open_("*")
-                    ]))),
-                    Serial(vec![Open("return"), Open_("*")])
-                ])))]
-            )));
+                            Ambient("concat", Box::new(Parallel(vec![In_("left", S), In_("right", S)], S)), S),
+                            In_("left", S),
+                            In_("right", S)
+                        ], S)), S),
+                        Open_("*", S)
+                    ], S)), S),
+                    Serial(vec![Open("return", S), Open_("*", S)], S)
+                ], S)), S)], S
+            )), S);
         assert_eq!(&format!("{:?}", expr), &format!("{:?}", expected));
 
         let program = "
@@ -303,10 +363,10 @@ string[
         let expr = Parser::new().parse(program).unwrap();
         let monad = Ambient("string", Box::new(
             Ambient("concat", Box::new(Parallel(vec![
-                Ambient("left", Box::new(Ambient("string", Box::new(Noop("a"))))),
-                Ambient("right", Box::new(Ambient("string", Box::new(Noop("b"))))),
-            ])))
-        ));
+                Ambient("left", Box::new(Ambient("string", Box::new(Noop("a", S)), S)), S),
+                Ambient("right", Box::new(Ambient("string", Box::new(Noop("b", S)), S)), S),
+            ], S)), S)
+        ), S);
         assert_eq!(&format!("{:?}", expr), &format!("{:?}", monad));
 
         // TODO: Typo in paper!
@@ -329,9 +389,9 @@ string[
         let expr = Parser::new().parse(program).unwrap();
         let expected = Ambient("string", Box::new(
             Ambient("concat", Box::new(Parallel(vec![
-                Ambient("left", Box::new(monad)),
-                Ambient("right", Box::new(Ambient("string", Box::new(Noop("c")))))
-            ])))));
+                Ambient("left", Box::new(monad), S),
+                Ambient("right", Box::new(Ambient("string", Box::new(Noop("c", S)), S)), S)
+            ], S)), S)), S);
         assert_eq!(&format!("{:?}", expr), &format!("{:?}", expected));
     }
 
@@ -349,10 +409,10 @@ identity[
         let expected = Ambient("identity", Box::new(
             Ambient("int", Box::new(
                 Ambient("length", Box::new(
-                    Ambient("string", Box::new(Noop("hello")))
-                ))
-            ))
-        ));
+                    Ambient("string", Box::new(Noop("hello", S)), S)
+                ), S)
+            ), S)
+        ), S);
         assert_eq!(&format!("{:?}", expr), &format!("{:?}", expected));
     }
 }
@@ -0,0 +1,347 @@
+use std::fmt;
+
+use crate::ast::Exec;
+
+/// Prints `Exec` trees back to canonical ROAM syntax, following the whitepaper's conventions:
+/// top-level parallel processes are separated by ` | `, nested ones by `|`, and co-capabilities
+/// without a target are printed bare, e.g. `open_`.
+///
+/// Parsing the printed form of a tree gives back the same tree, as long as it has the shape the
+/// parser produces: no `Parallel` directly inside a `Parallel` and no `Parallel` or `Serial`
+/// directly inside a `Serial`. Trees that don't are printed with explicit parentheses.
+///
+/// The alternate flag, `{:#}`, prints the same as `exec.pretty(2)`.
+impl<'input> fmt::Display for Exec<'input> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let indent = if f.alternate() { Some(2) } else { None };
+        Printer { f, indent }.exec(self, 0)
+    }
+}
+
+impl<'input> Exec<'input> {
+    /// Displays the tree across multiple lines, indenting nested ambients by `indent` spaces.
+    ///
+    /// Ambients that contain other non-empty ambients are broken up, one parallel process per
+    /// line, while ones that only contain capabilities and empty ambients stay on one line:
+    ///
+    /// ```text
+    /// message[
+    ///   in func.open_|
+    ///   func[
+    ///     x[in_ arg.open arg.in message.open_]|
+    ///     message[in_ x.open x]|
+    ///     in_ arg.open_
+    ///   ]
+    /// ]
+    /// ```
+    pub fn pretty(&self, indent: usize) -> Pretty<'_, 'input> {
+        Pretty { exec: self, indent }
+    }
+}
+
+/// Multi-line display of an `Exec`, see [`Exec::pretty`](enum.Exec.html#method.pretty).
+pub struct Pretty<'e, 'input> {
+    exec: &'e Exec<'input>,
+    indent: usize,
+}
+
+impl<'e, 'input> fmt::Display for Pretty<'e, 'input> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Printer { f, indent: Some(self.indent) }.exec(self.exec, 0)
+    }
+}
+
+struct Printer<'f, 'a> {
+    f: &'f mut fmt::Formatter<'a>,
+    /// `None` prints everything on one line.
+    indent: Option<usize>,
+}
+
+impl<'f, 'a> Printer<'f, 'a> {
+    /// Prints `exec` nested `depth` brackets deep.
+    fn exec(&mut self, exec: &Exec, depth: usize) -> fmt::Result {
+        match exec {
+            Exec::Parallel(procs, _) if procs.is_empty() => write!(self.f, "0"),
+            Exec::Parallel(procs, _) => {
+                if procs.iter().any(|p| self.is_broken(p)) {
+                    self.lines(procs, depth)
+                } else {
+                    let separator = if depth == 0 { " | " } else { "|" };
+                    for (i, p) in procs.iter().enumerate() {
+                        if i > 0 {
+                            write!(self.f, "{}", separator)?;
+                        }
+                        self.term(p, depth)?;
+                    }
+                    Ok(())
+                }
+            },
+            Exec::Serial(seq, _) => {
+                for (i, e) in seq.iter().enumerate() {
+                    if i > 0 {
+                        write!(self.f, ".")?;
+                    }
+                    match e {
+                        Exec::Parallel(procs, _) if !procs.is_empty() => self.group(e, e, depth)?,
+                        Exec::Serial(..) => self.group(e, e, depth)?,
+                        _ => self.exec(e, depth)?,
+                    }
+                }
+                Ok(())
+            },
+            Exec::Noop(name, _) => write!(self.f, "{}[]", name),
+            Exec::Ambient(name, body, _) => {
+                write!(self.f, "{}", name)?;
+                self.bracketed(exec, body, depth, "[", "]")
+            },
+            Exec::Group(inner, _) => self.group(exec, inner, depth),
+            Exec::Open(name, _) => write!(self.f, "open {}", name),
+            Exec::Open_(name, _) => self.co_capability("open_", name),
+            Exec::In(name, _) => write!(self.f, "in {}", name),
+            Exec::In_(name, _) => self.co_capability("in_", name),
+            Exec::Out(name, _) => write!(self.f, "out {}", name),
+            Exec::Out_(name, _) => self.co_capability("out_", name),
+        }
+    }
+
+    /// Prints a process of a `Parallel`, parenthesizing nested ones.
+    fn term(&mut self, exec: &Exec, depth: usize) -> fmt::Result {
+        match exec {
+            Exec::Parallel(procs, _) if !procs.is_empty() => self.group(exec, exec, depth),
+            _ => self.exec(exec, depth),
+        }
+    }
+
+    fn group(&mut self, outer: &Exec, inner: &Exec, depth: usize) -> fmt::Result {
+        self.bracketed(outer, inner, depth, "(", ")")
+    }
+
+    /// Prints `body` between `open` and `close`, on its own lines if `outer` is broken up.
+    fn bracketed(&mut self, outer: &Exec, body: &Exec, depth: usize, open: &str, close: &str) -> fmt::Result {
+        write!(self.f, "{}", open)?;
+        if self.is_broken(outer) {
+            writeln!(self.f)?;
+            self.write_indent(depth + 1)?;
+            match body {
+                Exec::Parallel(procs, _) if !procs.is_empty() => self.lines(procs, depth + 1)?,
+                _ => self.exec(body, depth + 1)?,
+            }
+            writeln!(self.f)?;
+            self.write_indent(depth)?;
+        } else {
+            self.exec(body, depth + 1)?;
+        }
+        write!(self.f, "{}", close)
+    }
+
+    /// Prints one parallel process per line, the first one at the current position.
+    fn lines(&mut self, procs: &[Exec], depth: usize) -> fmt::Result {
+        for (i, p) in procs.iter().enumerate() {
+            if i > 0 {
+                writeln!(self.f, "|")?;
+                self.write_indent(depth)?;
+            }
+            self.term(p, depth)?;
+        }
+        Ok(())
+    }
+
+    fn co_capability(&mut self, capability: &str, name: &str) -> fmt::Result {
+        if name == "*" {
+            write!(self.f, "{}", capability)
+        } else {
+            write!(self.f, "{} {}", capability, name)
+        }
+    }
+
+    fn write_indent(&mut self, depth: usize) -> fmt::Result {
+        write!(self.f, "{:width$}", "", width = depth * self.indent.unwrap_or(0))
+    }
+
+    /// Whether `exec` is printed across multiple lines: only when pretty-printing, and only
+    /// ambients and groups that contain non-empty ambients.
+    fn is_broken(&self, exec: &Exec) -> bool {
+        if self.indent.is_none() {
+            return false;
+        }
+        match exec {
+            Exec::Ambient(_, body, _) | Exec::Group(body, _) => contains_ambient(body),
+            Exec::Parallel(procs, _) | Exec::Serial(procs, _) => procs.iter().any(|p| self.is_broken(p)),
+            _ => false,
+        }
+    }
+}
+
+fn contains_ambient(exec: &Exec) -> bool {
+    match exec {
+        Exec::Ambient(..) => true,
+        Exec::Parallel(procs, _) | Exec::Serial(procs, _) => procs.iter().any(contains_ambient),
+        Exec::Group(inner, _) => contains_ambient(inner),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::{ assert_eq };
+    use proptest::prelude::*;
+    use crate::ast::{ Exec, Exec::*, Span };
+    use crate::parse;
+
+    const S: Span = Span { start: 0, end: 0 };
+
+    fn assert_round_trip(source: &str) {
+        let expr = parse(source).unwrap();
+        assert_eq!(parse(&expr.to_string()).unwrap(), expr);
+        assert_eq!(parse(&expr.pretty(4).to_string()).unwrap(), expr);
+    }
+
+    #[test]
+    fn display_canonical() {
+        let expr = parse("func[in_ x.open x.open_]|x[in func.open_ | result[]]|open func").unwrap();
+        assert_eq!(expr.to_string(), "func[in_ x.open x.open_] | x[in func.open_|result[]] | open func");
+
+        let expr = parse("a[in b.in_ |b[]] | c[out_ d.(open_|0)]").unwrap();
+        assert_eq!(expr.to_string(), "a[in b.in_|b[]] | c[out_ d.(open_|0)]");
+
+        assert_eq!(parse("0").unwrap().to_string(), "0");
+    }
+
+    #[test]
+    fn display_pretty() {
+        let source = "message[in func.open_|func[x[in_ arg.open arg.in message.open_]|message[in_ x.open x]|in_ arg.open_]] | open func";
+        let expr = parse(source).unwrap();
+        assert_eq!(format!("{:#}", expr), "\
+message[
+  in func.open_|
+  func[
+    x[in_ arg.open arg.in message.open_]|
+    message[in_ x.open x]|
+    in_ arg.open_
+  ]
+]|
+open func");
+
+        let source = "string_concat[in_ call.open call.(func[left[in_ arg]|open_]|open return.open_)]";
+        let expr = parse(source).unwrap();
+        assert_eq!(expr.pretty(4).to_string(), "\
+string_concat[
+    in_ call.open call.(
+        func[
+            left[in_ arg]|
+            open_
+        ]|
+        open return.open_
+    )
+]");
+
+        let expr = parse("a[in b] | b[in_ a]").unwrap();
+        assert_eq!(expr.pretty(2).to_string(), "a[in b] | b[in_ a]");
+    }
+
+    #[test]
+    fn display_unparsed_shapes() {
+        let expr = Serial(vec![In("a", S), Parallel(vec![Noop("b", S), Noop("c", S)], S)], S);
+        assert_eq!(expr.to_string(), "in a.(b[]|c[])");
+
+        let expr = Parallel(vec![Noop("a", S), Parallel(vec![Noop("b", S), Open("c", S)], S)], S);
+        assert_eq!(expr.to_string(), "a[] | (b[]|open c)");
+    }
+
+    #[test]
+    fn round_trip_programs() {
+        assert_round_trip("a[b[open_|c[]]|open b]");
+        assert_round_trip("a[in c] | b[in c] | c[in_ a.in_ b.in d] | d[in_ c]");
+        assert_round_trip("
+x[
+    call[out x.in y.open_|return[open_.in x]]|
+    out_ call.in_ y
+] |
+y[in_ call.open call.open return]
+");
+        assert_round_trip("
+string_concat[
+  in_ call.open call.(
+    func[
+      left[
+        in_ arg.open arg.in string.in concat
+      ]|
+      right[
+        in_ arg.open arg.in string.in concat
+      ]|
+      string[
+        concat[in_ left|in_ right]|
+        in_ left|in_ right
+      ]|
+      open_
+    ]|
+    open return.open_
+  )
+]");
+    }
+
+    fn name() -> impl Strategy<Value = &'static str> {
+        prop::sample::select(vec!["a", "b", "x", "func", "arg", "string_concat", "hello-world"])
+    }
+
+    fn co_name() -> impl Strategy<Value = &'static str> {
+        prop_oneof![Just("*"), name()]
+    }
+
+    /// Trees with the shape the grammar produces.
+    fn execution() -> impl Strategy<Value = Exec<'static>> {
+        let leaf = prop_oneof![
+            name().prop_map(|n| Noop(n, S)),
+            name().prop_map(|n| Open(n, S)),
+            co_name().prop_map(|n| Open_(n, S)),
+            name().prop_map(|n| In(n, S)),
+            co_name().prop_map(|n| In_(n, S)),
+            name().prop_map(|n| Out(n, S)),
+            co_name().prop_map(|n| Out_(n, S)),
+            Just(Parallel(vec![], S)),
+        ];
+        leaf.prop_recursive(4, 32, 4, |inner| {
+            let third_tier = prop_oneof![
+                inner.clone(),
+                (name(), inner.clone()).prop_map(|(n, e)| Ambient(n, Box::new(e), S)),
+                inner.clone().prop_map(|e| Group(Box::new(e), S)),
+            ];
+            let sub_execution = prop_oneof![
+                third_tier.clone(),
+                prop::collection::vec(third_tier, 2..4).prop_map(|v| Serial(v, S)),
+            ];
+            prop_oneof![
+                sub_execution.clone(),
+                prop::collection::vec(sub_execution, 2..4).prop_map(|v| Parallel(v, S)),
+            ]
+        }).prop_filter("not a tree the parser produces", is_parsed_shape)
+    }
+
+    /// Whether `exec` could have come out of the parser. The recursive strategy can produce
+    /// `Serial`s and `Parallel`s in positions the grammar only allows through a `Group`.
+    fn is_parsed_shape(exec: &Exec) -> bool {
+        match exec {
+            Parallel(procs, _) => procs.iter().all(|p| match p {
+                Parallel(v, _) => v.is_empty(),
+                e => is_parsed_shape(e),
+            }),
+            Serial(seq, _) => seq.iter().all(|e| match e {
+                Parallel(v, _) => v.is_empty(),
+                Serial(..) => false,
+                e => is_parsed_shape(e),
+            }),
+            Ambient(_, body, _) | Group(body, _) => is_parsed_shape(body),
+            _ => true,
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn round_trip(expr in execution()) {
+            let compact = expr.to_string();
+            prop_assert_eq!(parse(&compact).unwrap(), expr.clone());
+            let pretty = expr.pretty(2).to_string();
+            prop_assert_eq!(parse(&pretty).unwrap(), expr);
+        }
+    }
+}
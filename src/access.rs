@@ -0,0 +1,217 @@
+//! Write access to deployed programs.
+//!
+//! The `keys` field of a manifest addresses the list of keys allowed to write to the program,
+//! that is, to fire its capabilities. The address is the CID of a DAG-CBOR block which is one of:
+//!
+//! ```text
+//! "*"                        anyone can write
+//! <public key>               only the key can write
+//! [<public key>, ...]        any of the keys can write
+//! ```
+//!
+//! Keys are multicodec-prefixed, as in `Creator`. A single key is stored as its bytes, so the
+//! address of the keys of a program only its deployer can write to is the id of the deployer.
+//! A manifest without `keys` can be written to by anyone.
+
+use std::error::Error;
+
+use cid::Cid;
+
+use crate::dag_cbor::{ self, Ipld };
+use crate::manifest::{ Address, Manifest };
+use crate::prelude::*;
+use crate::signing::PublicKey;
+use crate::store::{ BlockStore, StoreError };
+
+/// The block of the wildcard list.
+const ANY: &str = "*";
+
+/// Who can write to a program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Access {
+    /// Anyone.
+    Any,
+    /// Only the keys in the list.
+    Keys(Vec<PublicKey>),
+}
+
+/// Why the keys of a manifest couldn't be resolved.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AccessError {
+    /// The keys address isn't the address of a block.
+    BadAddress(Address),
+    /// The block of the keys isn't in the store.
+    Missing(Cid),
+    /// The block doesn't hash to its CID.
+    HashMismatch(Cid),
+    /// The block isn't `"*"`, a key or a list of keys.
+    BadBlock(Cid),
+    /// A key in the block isn't a valid key of a known scheme.
+    BadKey(Cid),
+    /// The store failed to read the block.
+    Store(StoreError),
+}
+
+impl Display for AccessError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AccessError::BadAddress(address) => write!(f, "{} isn't the address of a key list", address),
+            AccessError::Missing(cid) => write!(f, "missing key list {}", cid),
+            AccessError::HashMismatch(cid) => write!(f, "key list {} doesn't match its hash", cid),
+            AccessError::BadBlock(cid) => write!(f, "{} isn't a key list", cid),
+            AccessError::BadKey(cid) => write!(f, "invalid key in key list {}", cid),
+            AccessError::Store(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl Error for AccessError {}
+
+impl Access {
+    /// Resolves the `keys` of `manifest` to the keys allowed to write to its program, reading the
+    /// key list from `store`.
+    pub fn resolve(manifest: &Manifest, store: &dyn BlockStore) -> Result<Access, AccessError> {
+        let address = match manifest.keys() {
+            Some(address) => address,
+            None => return Ok(Access::Any),
+        };
+        if !address.path().is_empty() {
+            return Err(AccessError::BadAddress(address.clone()));
+        }
+        let cid = address.hash();
+        let bytes = match store.get(cid) {
+            Ok(Some(bytes)) => bytes,
+            Ok(None) => return Err(AccessError::Missing(cid.clone())),
+            Err(StoreError::HashMismatch(cid)) => return Err(AccessError::HashMismatch(cid)),
+            Err(e) => return Err(AccessError::Store(e)),
+        };
+        let key = |ipld: &Ipld| match ipld {
+            Ipld::Bytes(key) => PublicKey::decode(key).map_err(|_| AccessError::BadKey(cid.clone())),
+            _ => Err(AccessError::BadBlock(cid.clone())),
+        };
+        match dag_cbor::decode(&bytes) {
+            Ok(Ipld::String(s)) if s == ANY => Ok(Access::Any),
+            Ok(Ipld::List(keys)) => keys.iter().map(key).collect::<Result<_, _>>().map(Access::Keys),
+            Ok(ipld @ Ipld::Bytes(_)) => Ok(Access::Keys(vec![key(&ipld)?])),
+            _ => Err(AccessError::BadBlock(cid.clone())),
+        }
+    }
+
+    /// The key list as an IPLD value, to be encoded as DAG-CBOR.
+    pub fn to_ipld(&self) -> Ipld {
+        match self {
+            Access::Any => Ipld::String(ANY.to_string()),
+            Access::Keys(keys) if keys.len() == 1 => Ipld::Bytes(keys[0].encode()),
+            Access::Keys(keys) => Ipld::List(keys.iter().map(|key| Ipld::Bytes(key.encode())).collect()),
+        }
+    }
+
+    /// The CID and DAG-CBOR encoding of the key list's block.
+    pub fn block(&self) -> (Cid, Vec<u8>) {
+        let bytes = dag_cbor::encode(&self.to_ipld());
+        (dag_cbor::cid(&bytes), bytes)
+    }
+
+    /// Puts the key list's block in `store`, returning its CID.
+    pub fn store(&self, store: &mut dyn BlockStore) -> Result<Cid, StoreError> {
+        store.put_cbor(&dag_cbor::encode(&self.to_ipld()))
+    }
+
+    /// The address a manifest's `keys` has for this key list.
+    pub fn address(&self) -> Address {
+        Address::new(&self.block().0)
+    }
+
+    /// Whether `key` can write.
+    pub fn allows(&self, key: &PublicKey) -> bool {
+        match self {
+            Access::Any => true,
+            Access::Keys(keys) => keys.contains(key),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manifest::Creator;
+    use crate::signing::{ Keypair, Scheme, Signer };
+    use crate::store::MemoryStore;
+
+    fn key(scheme: Scheme) -> PublicKey {
+        Keypair::generate(scheme).public_key()
+    }
+
+    fn resolve(access: &Access) -> Result<Access, AccessError> {
+        let manifest = Manifest::new(&dag_cbor::cid(b"program"), "program", Some(access.address()), None, None);
+        let (cid, bytes) = access.block();
+        let store: MemoryStore = vec![(cid, bytes)].into_iter().collect();
+        Access::resolve(&manifest, &store)
+    }
+
+    #[test]
+    fn access_resolve() {
+        let (a, b) = (key(Scheme::Secp256k1), key(Scheme::Ed25519));
+        for access in [Access::Any, Access::Keys(vec![a.clone()]), Access::Keys(vec![a.clone(), b])] {
+            assert_eq!(resolve(&access), Ok(access));
+        }
+
+        let manifest = Manifest::new(&dag_cbor::cid(b"program"), "program", None, None, None);
+        assert_eq!(Access::resolve(&manifest, &MemoryStore::new()), Ok(Access::Any));
+    }
+
+    #[test]
+    fn access_single_key_is_creator() {
+        let a = key(Scheme::Ed25519);
+        let access = Access::Keys(vec![a.clone()]);
+        assert_eq!(access.address(), Address::new(Creator::of(&a).id()));
+    }
+
+    #[test]
+    fn access_store() {
+        let access = Access::Keys(vec![key(Scheme::Secp256k1), key(Scheme::Ed25519)]);
+        let mut store = MemoryStore::new();
+        assert_eq!(access.store(&mut store).as_ref(), Ok(access.address().hash()));
+        let manifest = Manifest::new(&dag_cbor::cid(b"program"), "program", Some(access.address()), None, None);
+        assert_eq!(Access::resolve(&manifest, &store), Ok(access));
+    }
+
+    #[test]
+    fn access_allows() {
+        let (a, b, c) = (key(Scheme::Secp256k1), key(Scheme::Ed25519), key(Scheme::Ed25519));
+        assert!(Access::Any.allows(&a));
+        assert!(Access::Keys(vec![a.clone()]).allows(&a));
+        assert!(!Access::Keys(vec![a.clone()]).allows(&b));
+        let both = Access::Keys(vec![a.clone(), b.clone()]);
+        assert!(both.allows(&a) && both.allows(&b) && !both.allows(&c));
+        assert!(!Access::Keys(vec![]).allows(&a));
+    }
+
+    #[test]
+    fn access_resolve_errors() {
+        let access = Access::Keys(vec![key(Scheme::Ed25519)]);
+        let (cid, bytes) = access.block();
+        let manifest = Manifest::new(&dag_cbor::cid(b"program"), "program", Some(access.address()), None, None);
+        let with = |cid: &Cid, bytes: Vec<u8>| Access::resolve(&manifest, &vec![(cid.clone(), bytes)].into_iter().collect::<MemoryStore>());
+
+        assert_eq!(Access::resolve(&manifest, &MemoryStore::new()), Err(AccessError::Missing(cid.clone())));
+        assert_eq!(with(&cid, dag_cbor::encode(&Ipld::Null)), Err(AccessError::HashMismatch(cid.clone())));
+
+        let block = |ipld: Ipld| {
+            let bytes = dag_cbor::encode(&ipld);
+            let cid = dag_cbor::cid(&bytes);
+            let manifest = Manifest::new(&dag_cbor::cid(b"program"), "program", Some(Address::new(&cid)), None, None);
+            (Access::resolve(&manifest, &vec![(cid.clone(), bytes)].into_iter().collect::<MemoryStore>()), cid)
+        };
+        let (bad_block, other) = block(Ipld::String("everyone".to_string()));
+        assert_eq!(bad_block, Err(AccessError::BadBlock(other)));
+        let (bad_key, other) = block(Ipld::Bytes(vec![0xed, 0x01, 0x00]));
+        assert_eq!(bad_key, Err(AccessError::BadKey(other)));
+        let (bad_list, other) = block(Ipld::List(vec![Ipld::Integer(1)]));
+        assert_eq!(bad_list, Err(AccessError::BadBlock(other)));
+
+        let nested = access.address().join("x").unwrap();
+        let manifest = Manifest::new(&dag_cbor::cid(b"program"), "program", Some(nested.clone()), None, None);
+        assert_eq!(Access::resolve(&manifest, &vec![(cid, bytes)].into_iter().collect::<MemoryStore>()), Err(AccessError::BadAddress(nested)));
+    }
+}
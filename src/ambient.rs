@@ -1,11 +1,15 @@
 //! The ambient is the fundamental computation abstraction in ambient calculus. It is a
 
-use cid::{ Cid, Codec, Version };
-use multihash::Sha2_256;
+use cid::Cid;
+use crate::dag_cbor::{ self, Ipld };
 use crate::primitives::Target;
-use crate::manifest::{ Manifest, Address, Creator };
+use crate::access::Access;
+use crate::manifest::Manifest;
 use crate::prelude::*;
-// use crate::keypair::Keypair;
+use crate::keypair::SigningError;
+use crate::keystore::{ Keystore, KeystoreError };
+use crate::signing::Signer;
+use crate::store::{ BlockStore, StoreError };
 
 /// The ambient is the fundamental computation abstraction in ambient calculus. It is a
 /// computation container, with well-defined boundaries that separate an ambient from other
@@ -18,6 +22,7 @@ use crate::prelude::*;
 #[derive(Debug)]
 pub struct Ambient<'a> {
     cid: Cid,
+    manifest: Manifest,
     /// Ambients are addressed by name. Every ambient has a name, which is used to control and
     /// authorize all actions, access, and behavior of the ambient. Two distinct ambients can
     /// share a name, which is a powerful property when modeling non-deterministic behavior of
@@ -36,47 +41,73 @@ pub struct Ambient<'a> {
     program: &'a str
 }
 
-
-unsafe fn any_as_u8_slice<T: Sized>(p: &T) -> &[u8] {
-    ::std::slice::from_raw_parts(
-        (p as *const T) as *const u8,
-        ::std::mem::size_of::<T>(),
-    )
-}
-
-//     let my_struct = MyStruct { id: 0, data: [1; 1024] };
-//     let bytes: &[u8] = unsafe { any_as_u8_slice(&my_struct) };
-//     println!("{:?}", bytes);
-
-fn hash<T>(content: T) -> Cid
-where T: Sized {
-    let bytes = unsafe { any_as_u8_slice(&content) };
-    let h = Sha2_256::digest(bytes);
-    Cid::new(Version::V1, Codec::DagCBOR, h).unwrap()
+/// The CID of the DAG-CBOR encoding of `value`. The encoding is deterministic, so equal values
+/// have equal CIDs on every machine.
+fn hash(value: &Ipld) -> Cid {
+    dag_cbor::cid(&dag_cbor::encode(value))
 }
 
 impl<'a> Ambient<'a> {
+    /// The ambient of `program` deployed as `name`, without a creator.
     pub fn new(name: &'a str, program: &'a str) -> Ambient<'a> {
-        // TODO: Write access. Right now we'll either do * access or this key only.
-        // Currently doing the latter
-        // let keypair = Keypair::generate();
-        // let keypair_cid = hash(keypair.public());
-        // let keys = Address::new("amb", &keypair_cid);
-
-        // // TODO: Proper creator
-        // let creator = Creator::new(&keypair_cid, keypair.public());
-        let program_cid = hash(&program);
-
-        // let signature = keypair.secret().sign(program.as_bytes()).unwrap();
-        let manifest = Manifest::new(&program_cid, name, None, None, None);
-        // println!("{:?}", manifest);
-
-        let manifest_cid = hash(&manifest);
-        // println!("{:?}", manifest_cid.to_string());
-        Ambient { cid: manifest_cid, name, program }
+        let manifest = Manifest::new(&program_cid(program), name, None, None, None);
+        Ambient { cid: manifest.cid(), manifest, name, program }
+    }
+
+    /// Deploys `program` as `name`, signing its manifest with `signer`. Only the deployer's
+    /// key can write to the ambient.
+    pub fn deploy(name: &'a str, program: &'a str, signer: &dyn Signer) -> Result<Ambient<'a>, SigningError> {
+        Ambient::deploy_with_access(name, program, signer, &Access::Keys(vec![signer.public_key()]))
+    }
+
+    /// Deploys `program` as `name`, signing its manifest with `signer`. The keys of `access` can
+    /// write to the ambient; its block has to be published along with the manifest.
+    pub fn deploy_with_access(name: &'a str, program: &'a str, signer: &dyn Signer, access: &Access) -> Result<Ambient<'a>, SigningError> {
+        let mut manifest = Manifest::new(&program_cid(program), name, Some(access.address()), None, None);
+        manifest.sign(signer)?;
+        Ok(Ambient { cid: manifest.cid(), manifest, name, program })
+    }
+
+    /// Deploys `program` as `name`, signing its manifest as the identity `identity` of
+    /// `keystore`, unlocked with `passphrase`.
+    pub fn deploy_as(name: &'a str, program: &'a str, keystore: &Keystore, identity: &str, passphrase: &str) -> Result<Ambient<'a>, KeystoreError> {
+        let keypair = keystore.unlock(identity, passphrase)?;
+        Ok(Ambient::deploy(name, program, &keypair)?)
+    }
+
+    /// The CID of the ambient's manifest, which identifies the ambient.
+    pub fn cid(&self) -> &Cid {
+        &self.cid
+    }
+
+    /// The manifest the ambient was deployed with.
+    pub fn manifest(&self) -> &Manifest {
+        &self.manifest
+    }
+
+    /// The name the ambient was deployed as.
+    pub fn name(&self) -> &'a str {
+        self.name
+    }
+
+    /// The source of the ambient's program.
+    pub fn program(&self) -> &'a str {
+        self.program
+    }
+
+    /// Puts the blocks of the program and its manifest in `store`, returning the CID of the
+    /// manifest. The block of the manifest's keys isn't put, see `Access::store`.
+    pub fn store(&self, store: &mut dyn BlockStore) -> Result<Cid, StoreError> {
+        store.put_cbor(&dag_cbor::encode(&Ipld::String(self.program.to_string())))?;
+        self.manifest.store(store)
     }
 }
 
+/// The CID of `program`, as its manifest links to it.
+pub fn program_cid(program: &str) -> Cid {
+    hash(&Ipld::String(program.to_string()))
+}
+
 // This exists simply so that an Ambient can be a ByteCode target as well as a Computation OpCode
 impl<'a> Target for Ambient<'a> {}
 
@@ -89,12 +120,72 @@ impl<'a> Display for Ambient<'a> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{ ed25519, keypair::Keypair };
+    use crate::manifest::{ Address, Creator };
 
     #[test]
     fn hello_world() {
         let program = "string[hello[]]";
         let ambient = Ambient::new("hello-world", program);
-        // println!("{}", ambient)
+        assert_eq!(
+            ambient.cid().to_string(),
+            "bafyreihiyk3afmeqyfzwtbxlill3j4h3hwejzinrwrobqsaen5g4dj7awy"
+        );
+        assert_eq!(ambient.cid(), Ambient::new("hello-world", program).cid());
+        assert_ne!(ambient.cid(), Ambient::new("hello-world", "string[world[]]").cid());
+        assert_ne!(ambient.cid(), Ambient::new("goodbye-world", program).cid());
+    }
+
+    #[test]
+    fn deploy_signed() {
+        let keypair = Keypair::generate();
+        let program = "string[hello[]]";
+        let ambient = Ambient::deploy("hello-world", program, &keypair).unwrap();
+        let manifest = ambient.manifest();
+        assert_eq!(manifest.verify(&program_cid(program)), Ok(()));
+        assert_eq!(manifest.creator(), Some(&Creator::of(&keypair.public_key())));
+        assert_eq!(manifest.keys(), Some(&Address::new(Creator::of(&keypair.public_key()).id())));
+        assert_eq!(ambient.cid(), &manifest.cid());
+        assert_ne!(ambient.cid(), Ambient::new("hello-world", program).cid());
+        assert!(manifest.verify(&program_cid("string[world[]]")).is_err());
+
+        let other = ed25519::Keypair::generate();
+        let access = Access::Keys(vec![keypair.public_key(), other.public_key()]);
+        let shared = Ambient::deploy_with_access("hello-world", program, &keypair, &access).unwrap();
+        assert_eq!(shared.manifest().keys(), Some(&access.address()));
+        assert_eq!(shared.manifest().verify(&program_cid(program)), Ok(()));
+
+        let ed25519 = ed25519::Keypair::generate();
+        let ambient = Ambient::deploy("hello-world", program, &ed25519).unwrap();
+        assert_eq!(ambient.manifest().verify(&program_cid(program)), Ok(()));
+        assert_eq!(ambient.manifest().creator(), Some(&Creator::of(&ed25519.public_key())));
+    }
+
+    #[test]
+    fn store_program() {
+        let mut store = crate::store::MemoryStore::new();
+        let program = "string[hello[]]";
+        let ambient = Ambient::deploy("hello-world", program, &Keypair::generate()).unwrap();
+        assert_eq!(ambient.store(&mut store).as_ref(), Ok(ambient.cid()));
+        assert_eq!(Manifest::load(ambient.cid(), &store).as_ref(), Ok(ambient.manifest()));
+        let source = store.get(&program_cid(program)).unwrap().unwrap();
+        assert_eq!(dag_cbor::decode(&source), Ok(Ipld::String(program.to_string())));
+    }
+
+    #[test]
+    fn deploy_as_identity() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut keystore = Keystore::open(dir.path().join("keys.json")).unwrap()
+            .with_kdf(crate::keystore::KdfParams { log_n: 4, r: 8, p: 1 });
+        keystore.generate("alice", crate::signing::Scheme::Ed25519, "pass").unwrap();
+
+        let program = "string[hello[]]";
+        let ambient = Ambient::deploy_as("hello-world", program, &keystore, "alice", "pass").unwrap();
+        assert_eq!(ambient.manifest().verify(&program_cid(program)), Ok(()));
+        assert_eq!(ambient.manifest().creator(), Some(&keystore.creator("alice").unwrap()));
+        assert_eq!(ambient.manifest().keys(), Some(&keystore.address("alice").unwrap()));
+        assert!(matches!(Ambient::deploy_as("hello-world", program, &keystore, "alice", "wrong"), Err(KeystoreError::BadPassphrase)));
+        assert!(matches!(Ambient::deploy_as("hello-world", program, &keystore, "bob", "pass"), Err(KeystoreError::NotFound(_))));
     }
 
     // fn ambient_new() {
@@ -0,0 +1,398 @@
+//! Binary encoding of instruction streams.
+//!
+//! A stream is the list of instructions of a single ambient, as produced by the compiler. It is
+//! encoded as a header followed by the instructions:
+//!
+//! ```text
+//! stream      = "amb" version count instruction*
+//! version     = 0x01
+//! count       = varint
+//! instruction = opcode target
+//! opcode      = 0x00 create | 0x01 deploy | 0x02 in | 0x03 in_
+//!             | 0x04 out | 0x05 out_ | 0x06 open | 0x07 open_
+//!             | 0x08 seq | 0x09 par | 0x0a end
+//! target      = 0x00                          (no target, 0)
+//!             | 0x01 primitive                (0 func, 1 call, 2 arg, 3 return)
+//!             | 0x02 length:varint cid        (any other ambient)
+//! ```
+//!
+//! Every `seq` and `par` instruction starts a block that ends at its matching `end`.
+//!
+//! Ambients other than the protocol primitives are referenced by CID: a CIDv1 with the `raw`
+//! codec and the `identity` multihash of the ambient's name. The name can be read back from the
+//! CID, so a stream can be disassembled to its text form without anything but the stream.
+
+use std::convert::TryFrom;
+use std::error::Error;
+
+use cid::{ Cid, Codec };
+use multihash::{ Code, Identity };
+use unsigned_varint::{ decode as varint_decode, encode as varint_encode };
+
+use crate::compiler::Bytecode;
+use crate::prelude::*;
+use crate::primitives::{ Capability, Computation, Distribution, Instruction, Operand };
+
+/// The bytes every stream starts with.
+pub const MAGIC: &[u8] = b"amb";
+
+/// The version of the encoding, written after `MAGIC`.
+pub const VERSION: u8 = 1;
+
+const NO_TARGET: u8 = 0x00;
+const PRIMITIVE_TARGET: u8 = 0x01;
+const CID_TARGET: u8 = 0x02;
+
+/// Why a stream couldn't be decoded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The stream doesn't start with `MAGIC`.
+    BadMagic,
+    /// The stream was encoded with a version of the encoding this one can't read.
+    UnsupportedVersion(u8),
+    /// The stream ended in the middle of an instruction, or before `count` instructions.
+    UnexpectedEnd,
+    /// A length or count that doesn't fit a varint, or isn't in its shortest encoding.
+    BadVarint,
+    /// A byte that isn't one of the capability opcodes.
+    UnknownOpcode(u8),
+    /// A target tag that isn't one of the known target kinds.
+    UnknownTarget(u8),
+    /// A primitive target that isn't one of the primitive opcodes.
+    UnknownPrimitive(u8),
+    /// A target that isn't a valid CID.
+    BadCid(cid::Error),
+    /// A target CID that isn't a raw identity CID of a UTF-8 name, or names a primitive, which
+    /// has its own encoding.
+    BadName,
+    /// An `end` outside of any block, or a block without its `end`.
+    UnbalancedBlock,
+    /// More bytes after the last instruction.
+    TrailingBytes(usize),
+}
+
+impl Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::BadMagic => write!(f, "not an instruction stream"),
+            DecodeError::UnsupportedVersion(v) => write!(f, "unsupported stream version {}", v),
+            DecodeError::UnexpectedEnd => write!(f, "unexpected end of stream"),
+            DecodeError::BadVarint => write!(f, "invalid varint"),
+            DecodeError::UnknownOpcode(op) => write!(f, "unknown opcode {:#04x}", op),
+            DecodeError::UnknownTarget(tag) => write!(f, "unknown target kind {:#04x}", tag),
+            DecodeError::UnknownPrimitive(op) => write!(f, "unknown primitive {:#04x}", op),
+            DecodeError::BadCid(e) => write!(f, "invalid target CID: {}", e),
+            DecodeError::BadName => write!(f, "target CID isn't an ambient name"),
+            DecodeError::UnbalancedBlock => write!(f, "unbalanced seq, par or end instruction"),
+            DecodeError::TrailingBytes(n) => write!(f, "{} trailing bytes after the last instruction", n),
+        }
+    }
+}
+
+impl Error for DecodeError {}
+
+/// Encodes `instructions` as a stream.
+pub fn encode(instructions: &[Bytecode]) -> Vec<u8> {
+    let mut bytes = MAGIC.to_vec();
+    bytes.push(VERSION);
+    write_varint(&mut bytes, instructions.len() as u64);
+    for instruction in instructions {
+        bytes.push(*instruction.opcode() as u8);
+        match instruction.target() {
+            Operand::Any => bytes.push(NO_TARGET),
+            Operand::Computation(c) => bytes.extend(&[PRIMITIVE_TARGET, *c as u8]),
+            Operand::Distribution(d) => bytes.extend(&[PRIMITIVE_TARGET, *d as u8]),
+            Operand::Name(name) => {
+                let cid = name_cid(name).to_bytes();
+                bytes.push(CID_TARGET);
+                write_varint(&mut bytes, cid.len() as u64);
+                bytes.extend(cid);
+            },
+        }
+    }
+    bytes
+}
+
+/// Decodes a stream. Names are borrowed from `bytes`.
+pub fn decode(bytes: &[u8]) -> Result<Vec<Bytecode<'_>>, DecodeError> {
+    let mut reader = Reader { bytes };
+    if reader.take(MAGIC.len())? != MAGIC {
+        return Err(DecodeError::BadMagic);
+    }
+    match reader.byte()? {
+        VERSION => {},
+        v => return Err(DecodeError::UnsupportedVersion(v)),
+    }
+    let count = reader.varint()?;
+    // Every instruction is at least two bytes, which bounds what a valid count can be.
+    if count > (reader.bytes.len() / 2) as u64 {
+        return Err(DecodeError::UnexpectedEnd);
+    }
+
+    let mut instructions = Vec::with_capacity(count as usize);
+    let mut depth = 0usize;
+    for _ in 0..count {
+        let opcode = capability(reader.byte()?)?;
+        match opcode {
+            Capability::seq | Capability::par => depth += 1,
+            Capability::end if depth == 0 => return Err(DecodeError::UnbalancedBlock),
+            Capability::end => depth -= 1,
+            _ => {},
+        }
+        let target = match reader.byte()? {
+            NO_TARGET => Operand::Any,
+            PRIMITIVE_TARGET => primitive(reader.byte()?)?,
+            CID_TARGET => {
+                let len = reader.varint()?;
+                if len > reader.bytes.len() as u64 {
+                    return Err(DecodeError::UnexpectedEnd);
+                }
+                name(reader.take(len as usize)?)?
+            },
+            tag => return Err(DecodeError::UnknownTarget(tag)),
+        };
+        instructions.push(Instruction::new(opcode, target));
+    }
+
+    if depth > 0 {
+        return Err(DecodeError::UnbalancedBlock);
+    }
+    match reader.bytes.len() {
+        0 => Ok(instructions),
+        n => Err(DecodeError::TrailingBytes(n)),
+    }
+}
+
+/// Decodes a stream into its text form, e.g. `(0 create, "a") (2 in, "b") (7 open_, 0)`.
+pub fn disassemble(bytes: &[u8]) -> Result<String, DecodeError> {
+    let instructions = decode(bytes)?;
+    Ok(instructions.iter().map(|i| i.to_string()).collect::<Vec<_>>().join(" "))
+}
+
+/// The CID referencing the ambient `name`.
+pub fn name_cid(name: &str) -> Cid {
+    Cid::new_v1(Codec::Raw, Identity::digest(name.as_bytes()))
+}
+
+fn write_varint(bytes: &mut Vec<u8>, n: u64) {
+    bytes.extend(varint_encode::u64(n, &mut varint_encode::u64_buffer()));
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], DecodeError> {
+        if self.bytes.len() < n {
+            return Err(DecodeError::UnexpectedEnd);
+        }
+        let (taken, rest) = self.bytes.split_at(n);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn byte(&mut self) -> Result<u8, DecodeError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn varint(&mut self) -> Result<u64, DecodeError> {
+        let (n, rest) = varint_decode::u64(self.bytes).map_err(|e| match e {
+            varint_decode::Error::Insufficient => DecodeError::UnexpectedEnd,
+            _ => DecodeError::BadVarint,
+        })?;
+        // Only the shortest encoding of a number is valid, so that every stream has one encoding.
+        let len = self.bytes.len() - rest.len();
+        if len > 1 && self.bytes[len - 1] == 0 {
+            return Err(DecodeError::BadVarint);
+        }
+        self.bytes = rest;
+        Ok(n)
+    }
+}
+
+pub(crate) fn capability(op: u8) -> Result<Capability, DecodeError> {
+    Ok(match op {
+        0 => Capability::create,
+        1 => Capability::deploy,
+        2 => Capability::r#in,
+        3 => Capability::in_,
+        4 => Capability::out,
+        5 => Capability::out_,
+        6 => Capability::open,
+        7 => Capability::open_,
+        8 => Capability::seq,
+        9 => Capability::par,
+        10 => Capability::end,
+        op => return Err(DecodeError::UnknownOpcode(op)),
+    })
+}
+
+fn primitive(op: u8) -> Result<Operand<'static>, DecodeError> {
+    Ok(match op {
+        0 => Operand::Computation(Computation::func),
+        1 => Operand::Distribution(Distribution::call),
+        2 => Operand::Computation(Computation::arg),
+        3 => Operand::Distribution(Distribution::r#return),
+        op => return Err(DecodeError::UnknownPrimitive(op)),
+    })
+}
+
+fn name(bytes: &[u8]) -> Result<Operand<'_>, DecodeError> {
+    let cid = Cid::try_from(bytes).map_err(DecodeError::BadCid)?;
+    let hash = cid.hash();
+    if cid.codec() != Codec::Raw || hash.algorithm() != Code::Identity {
+        return Err(DecodeError::BadName);
+    }
+    // The digest of an identity hash is the name itself, at the end of the CID.
+    let digest = &bytes[bytes.len() - hash.digest().len()..];
+    let name = std::str::from_utf8(digest).map_err(|_| DecodeError::BadName)?;
+    match Operand::from_name(name) {
+        operand @ Operand::Name(_) if !name.is_empty() => Ok(operand),
+        _ => Err(DecodeError::BadName),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::compile;
+    use ambients_parser::parse;
+    use proptest::prelude::*;
+
+    const PROGRAM: &str = "
+x[
+    call[out x.in y.open_|return[open_.in x]]|
+    out_ call.in_ y
+] |
+y[in_ call.open call.open return] |
+func[in_ arg.open_]
+";
+
+    /// The instruction streams of every ambient in `PROGRAM`.
+    fn streams() -> Vec<Vec<u8>> {
+        fn all(compiled: &crate::compiler::Compiled, streams: &mut Vec<Vec<u8>>) {
+            streams.push(encode(&compiled.instructions));
+            for child in &compiled.children {
+                all(child, streams);
+            }
+        }
+        let exec = parse(PROGRAM).unwrap();
+        let mut streams = vec![];
+        all(&compile("program", &exec), &mut streams);
+        streams
+    }
+
+    #[test]
+    fn encode_bytes() {
+        let instructions = vec![
+            Instruction::new(Capability::create, Operand::Name("a")),
+            Instruction::new(Capability::r#in, Operand::Computation(Computation::func)),
+            Instruction::new(Capability::open, Operand::Distribution(Distribution::r#return)),
+            Instruction::new(Capability::open_, Operand::Any),
+        ];
+        assert_eq!(encode(&instructions), vec![
+            b'a', b'm', b'b', 1, 4,
+            0, 2, 5, 0x01, 0x55, 0x00, 0x01, b'a',
+            2, 1, 0,
+            6, 1, 3,
+            7, 0,
+        ]);
+        assert_eq!(encode(&[]), vec![b'a', b'm', b'b', 1, 0]);
+    }
+
+    #[test]
+    fn decode_round_trip() {
+        let exec = parse(PROGRAM).unwrap();
+        let compiled = compile("program", &exec);
+        let bytes = encode(&compiled.instructions);
+        assert_eq!(decode(&bytes).unwrap(), compiled.instructions);
+
+        let x = &compiled.children[0];
+        assert_eq!(decode(&encode(&x.instructions)).unwrap(), x.instructions);
+    }
+
+    #[test]
+    fn disassemble_text() {
+        let exec = parse(PROGRAM).unwrap();
+        let compiled = compile("program", &exec);
+        let text = streams().iter().map(|s| disassemble(s).unwrap()).collect::<Vec<_>>();
+        assert_eq!(text, vec![
+            r#"(1 deploy, "program") (0 create, "x") (0 create, "y") (0 create, 0 func)"#,
+            r#"(0 create, 1 call) (8 seq, 0) (5 out_, 1 call) (3 in_, "y") (10 end, 0)"#,
+            r#"(8 seq, 0) (4 out, "x") (2 in, "y") (7 open_, 0) (10 end, 0) (0 create, 3 return)"#,
+            r#"(8 seq, 0) (7 open_, 0) (2 in, "x") (10 end, 0)"#,
+            r#"(8 seq, 0) (3 in_, 1 call) (6 open, 1 call) (6 open, 3 return) (10 end, 0)"#,
+            r#"(8 seq, 0) (3 in_, 2 arg) (7 open_, 0) (10 end, 0)"#,
+        ]);
+        let lines: Vec<_> = compiled.to_string().lines()
+            .map(|l| l.split_once(": ").unwrap().1.to_string())
+            .collect();
+        assert_eq!(text, lines);
+    }
+
+    #[test]
+    fn decode_errors() {
+        assert_eq!(decode(b""), Err(DecodeError::UnexpectedEnd));
+        assert_eq!(decode(b"abc\x01\x00"), Err(DecodeError::BadMagic));
+        assert_eq!(decode(b"amb\x02\x00"), Err(DecodeError::UnsupportedVersion(2)));
+        assert_eq!(decode(b"amb\x01\x01\x0b\x00"), Err(DecodeError::UnknownOpcode(11)));
+        assert_eq!(decode(b"amb\x01\x01\x0a\x00"), Err(DecodeError::UnbalancedBlock));
+        assert_eq!(decode(b"amb\x01\x02\x08\x00\x09\x00"), Err(DecodeError::UnbalancedBlock));
+        assert_eq!(decode(b"amb\x01\x03\x08\x00\x0a\x00\x0a\x00"), Err(DecodeError::UnbalancedBlock));
+        assert_eq!(decode(b"amb\x01\x01\x02\x03"), Err(DecodeError::UnknownTarget(3)));
+        assert_eq!(decode(b"amb\x01\x01\x02\x01\x04"), Err(DecodeError::UnknownPrimitive(4)));
+        assert_eq!(decode(b"amb\x01\x02\x02\x00"), Err(DecodeError::UnexpectedEnd));
+        assert_eq!(decode(b"amb\x01\x01\x02\x00\x00"), Err(DecodeError::TrailingBytes(1)));
+        assert_eq!(decode(b"amb\x01\x01\x02\x02\x06\x01\x55\x00\x01a"), Err(DecodeError::UnexpectedEnd));
+        assert_eq!(decode(b"amb\x01\x81\x00\x02\x00"), Err(DecodeError::BadVarint));
+        assert_eq!(decode(b"amb\x01\xff\xff\xff\xff\xff\xff\xff\xff\xff\xff"), Err(DecodeError::BadVarint));
+
+        // Not raw, not identity, not UTF-8, and a primitive spelled out as a name.
+        let target = |cid: Vec<u8>| {
+            let mut bytes = b"amb\x01\x01\x02\x02".to_vec();
+            bytes.push(cid.len() as u8);
+            bytes.extend(cid);
+            decode(&bytes).map(|_| ())
+        };
+        assert_eq!(target(Cid::new_v1(Codec::DagCBOR, Identity::digest(b"a")).to_bytes()), Err(DecodeError::BadName));
+        assert_eq!(target(Cid::new_v1(Codec::Raw, multihash::Sha2_256::digest(b"a")).to_bytes()), Err(DecodeError::BadName));
+        assert_eq!(target(Cid::new_v1(Codec::Raw, Identity::digest(b"\xff")).to_bytes()), Err(DecodeError::BadName));
+        assert_eq!(target(name_cid("func").to_bytes()), Err(DecodeError::BadName));
+        assert_eq!(target(name_cid("*").to_bytes()), Err(DecodeError::BadName));
+        assert_eq!(target(vec![0x01, 0x55]), Err(DecodeError::BadCid(cid::Error::ParsingError)));
+    }
+
+    proptest! {
+        #[test]
+        fn decode_arbitrary(bytes in proptest::collection::vec(any::<u8>(), 0..64)) {
+            let _ = decode(&bytes);
+        }
+
+        #[test]
+        fn decode_after_header(bytes in proptest::collection::vec(any::<u8>(), 0..64)) {
+            let mut stream = b"amb\x01".to_vec();
+            stream.extend(bytes);
+            if let Ok(instructions) = decode(&stream) {
+                assert_eq!(decode(&encode(&instructions)).unwrap(), instructions);
+            }
+        }
+
+        #[test]
+        fn decode_truncated(index in 0usize..6, len in any::<prop::sample::Index>()) {
+            let stream = &streams()[index];
+            let len = len.index(stream.len());
+            assert!(decode(&stream[..len]).is_err());
+        }
+
+        #[test]
+        fn decode_corrupted(index in 0usize..6, at in any::<prop::sample::Index>(), byte: u8) {
+            let mut stream = streams()[index].clone();
+            let at = at.index(stream.len());
+            stream[at] = byte;
+            if let Ok(instructions) = decode(&stream) {
+                assert_eq!(encode(&instructions), stream);
+            }
+        }
+    }
+}
@@ -0,0 +1,391 @@
+//! CARv1 archives of deployed programs.
+//!
+//! A CAR (content-addressed archive) is a header naming its root CIDs followed by blocks, each
+//! prefixed with its length and CID:
+//!
+//! ```text
+//! car     = varint header section*
+//! header  = { "roots": [<cid>, ...], "version": 1 }     (DAG-CBOR)
+//! section = varint cid bytes                            (varint is the length of cid + bytes)
+//! ```
+//!
+//! The archive of a program has the program's manifest as its first root and the node of its
+//! slices as its second, see `slicer`. It holds every block the program needs to be checked and
+//! run without a network: the manifest, the program, the program's key list, the creator's key
+//! and the slices. Importing an archive checks every block against its CID and the program
+//! against its manifest before anything is put in the store.
+
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::error::Error;
+use std::io::{ self, Read, Write };
+
+use ambients_parser::parse;
+use cid::Cid;
+use unsigned_varint::{ decode as varint_decode, encode as varint_encode };
+
+use crate::access::{ Access, AccessError };
+use crate::ambient::program_cid;
+use crate::compiler::compile;
+use crate::dag_cbor::{ self, Ipld };
+use crate::manifest::{ Manifest, ManifestError, VerifyError };
+use crate::prelude::*;
+use crate::slicer::{ self, SliceError, Slices };
+use crate::store::{ self, BlockStore, MemoryStore, StoreError };
+
+/// The version of the CAR format.
+pub const VERSION: i64 = 1;
+
+/// The roots and blocks of an archive, in the order they are written.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Car {
+    /// The CIDs the archive is of.
+    pub roots: Vec<Cid>,
+    /// The blocks of the archive, with their CIDs.
+    pub blocks: Vec<(Cid, Vec<u8>)>,
+}
+
+/// Why an archive couldn't be read, written or imported.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CarError {
+    /// Reading or writing the archive failed.
+    Io(String),
+    /// The header isn't a version 1 header with at least one root.
+    BadHeader,
+    /// A section is truncated, or doesn't start with a CID.
+    BadSection,
+    /// A block doesn't hash to its CID.
+    Store(StoreError),
+    /// A block the program needs isn't in the store or the archive.
+    Missing(Cid),
+    /// The manifest couldn't be decoded.
+    Manifest(ManifestError),
+    /// The manifest's signature doesn't check out.
+    Verify(VerifyError),
+    /// The program's key list couldn't be resolved.
+    Access(AccessError),
+    /// The program block isn't the source of a program.
+    BadProgram(Cid),
+    /// The slices couldn't be read.
+    Slices(SliceError),
+    /// The slices aren't the slices of the program.
+    SliceMismatch(Cid),
+}
+
+impl Display for CarError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CarError::Io(e) => write!(f, "archive I/O error: {}", e),
+            CarError::BadHeader => write!(f, "invalid archive header"),
+            CarError::BadSection => write!(f, "invalid archive section"),
+            CarError::Store(e) => write!(f, "{}", e),
+            CarError::Missing(cid) => write!(f, "missing block {}", cid),
+            CarError::Manifest(e) => write!(f, "invalid manifest: {}", e),
+            CarError::Verify(e) => write!(f, "{}", e),
+            CarError::Access(e) => write!(f, "{}", e),
+            CarError::BadProgram(cid) => write!(f, "{} isn't a program", cid),
+            CarError::Slices(e) => write!(f, "{}", e),
+            CarError::SliceMismatch(cid) => write!(f, "slices {} aren't the slices of the program", cid),
+        }
+    }
+}
+
+impl Error for CarError {}
+
+impl From<io::Error> for CarError {
+    fn from(e: io::Error) -> CarError {
+        CarError::Io(e.to_string())
+    }
+}
+
+impl Car {
+    /// Encodes the archive.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut header = BTreeMap::new();
+        header.insert("roots".to_string(), Ipld::List(self.roots.iter().cloned().map(Ipld::Link).collect()));
+        header.insert("version".to_string(), Ipld::Integer(VERSION));
+        let header = dag_cbor::encode(&Ipld::Map(header));
+
+        let mut bytes = vec![];
+        write_varint(&mut bytes, header.len());
+        bytes.extend(header);
+        for (cid, block) in &self.blocks {
+            let cid = cid.to_bytes();
+            write_varint(&mut bytes, cid.len() + block.len());
+            bytes.extend(cid);
+            bytes.extend(block);
+        }
+        bytes
+    }
+
+    /// Decodes an archive, checking every block against its CID.
+    pub fn decode(mut bytes: &[u8]) -> Result<Car, CarError> {
+        let header = section(&mut bytes).ok_or(CarError::BadHeader)?;
+        let roots = match dag_cbor::decode(header) {
+            Ok(Ipld::Map(header)) if header.len() == 2 && header.get("version") == Some(&Ipld::Integer(VERSION)) => {
+                match header.get("roots") {
+                    Some(Ipld::List(roots)) if !roots.is_empty() => roots.iter().map(|root| match root {
+                        Ipld::Link(cid) => Ok(cid.clone()),
+                        _ => Err(CarError::BadHeader),
+                    }).collect::<Result<_, _>>()?,
+                    _ => return Err(CarError::BadHeader),
+                }
+            },
+            _ => return Err(CarError::BadHeader),
+        };
+
+        let mut blocks = vec![];
+        while !bytes.is_empty() {
+            let section = section(&mut bytes).ok_or(CarError::BadSection)?;
+            let len = cid_len(section).ok_or(CarError::BadSection)?;
+            let cid = Cid::try_from(&section[..len]).map_err(|_| CarError::BadSection)?;
+            let block = &section[len..];
+            store::verify(&cid, block).map_err(CarError::Store)?;
+            blocks.push((cid, block.to_vec()));
+        }
+        Ok(Car { roots, blocks })
+    }
+
+    /// Writes the archive to `writer`.
+    pub fn write(&self, writer: &mut dyn Write) -> Result<(), CarError> {
+        Ok(writer.write_all(&self.encode())?)
+    }
+
+    /// Reads an archive from `reader`, checking every block against its CID.
+    pub fn read(reader: &mut dyn Read) -> Result<Car, CarError> {
+        let mut bytes = vec![];
+        reader.read_to_end(&mut bytes)?;
+        Car::decode(&bytes)
+    }
+}
+
+/// The archive of the program whose manifest is `manifest`, with the blocks it needs from
+/// `store`. The creator's key and the slices are derived from the manifest and the program.
+pub fn export(manifest: &Cid, store: &dyn BlockStore) -> Result<Car, CarError> {
+    let get = |cid: &Cid| match store.get(cid) {
+        Ok(Some(bytes)) => Ok(bytes),
+        Ok(None) => Err(CarError::Missing(cid.clone())),
+        Err(e) => Err(CarError::Store(e)),
+    };
+    let manifest_bytes = get(manifest)?;
+    let decoded = Manifest::from_cbor(&manifest_bytes).map_err(CarError::Manifest)?;
+    let program = decoded.program().clone();
+    let source = get(&program)?;
+    let slices = program_slices(&decoded, &source)?;
+
+    let mut blocks = BTreeMap::new();
+    blocks.insert(program, source);
+    if let Some(keys) = decoded.keys() {
+        blocks.insert(keys.hash().clone(), get(keys.hash())?);
+    }
+    if let Some(creator) = decoded.creator() {
+        let key = dag_cbor::encode(&Ipld::Bytes(creator.public_key().to_vec()));
+        blocks.insert(dag_cbor::cid(&key), key);
+    }
+    blocks.extend(slices.blocks);
+
+    let mut car = Car { roots: vec![manifest.clone(), slices.root], blocks: vec![(manifest.clone(), manifest_bytes)] };
+    car.blocks.extend(blocks);
+    Ok(car)
+}
+
+/// Imports the program archived in `car` into `store`, returning the CID of its manifest.
+///
+/// The program has to be the program of the manifest, signed by its creator if it has one, and
+/// its key list and slices have to be in the archive. Nothing is put in the store otherwise.
+pub fn import(car: &Car, store: &mut dyn BlockStore) -> Result<Cid, CarError> {
+    for (cid, block) in &car.blocks {
+        store::verify(cid, block).map_err(CarError::Store)?;
+    }
+    let staged: MemoryStore = car.blocks.iter().cloned().collect();
+
+    let root = car.roots.first().ok_or(CarError::BadHeader)?;
+    let manifest = match Manifest::load(root, &staged) {
+        Err(ManifestError::NotFound(cid)) => return Err(CarError::Missing(cid)),
+        manifest => manifest.map_err(CarError::Manifest)?,
+    };
+    let program = manifest.program();
+    let source = staged.get(program).map_err(CarError::Store)?.ok_or_else(|| CarError::Missing(program.clone()))?;
+    if manifest.creator().is_some() {
+        manifest.verify(program).map_err(CarError::Verify)?;
+    }
+    match Access::resolve(&manifest, &staged) {
+        Err(AccessError::Missing(cid)) => return Err(CarError::Missing(cid)),
+        access => access.map_err(CarError::Access)?,
+    };
+
+    let slices = program_slices(&manifest, &source)?;
+    let root_slice = car.roots.get(1).unwrap_or(&slices.root);
+    if *root_slice != slices.root {
+        return Err(CarError::SliceMismatch(root_slice.clone()));
+    }
+    slicer::load(&slices.root, &staged).map_err(CarError::Slices)?;
+
+    for (cid, block) in &car.blocks {
+        store.put(cid, block).map_err(CarError::Store)?;
+    }
+    Ok(root.clone())
+}
+
+/// The slices of the program whose block is `source`, deployed with `manifest`. The block has to
+/// be the source the `ambient` module addresses the program by.
+fn program_slices(manifest: &Manifest, source: &[u8]) -> Result<Slices, CarError> {
+    let program = manifest.program();
+    let source = match dag_cbor::decode(source) {
+        Ok(Ipld::String(source)) if program_cid(&source) == *program => source,
+        _ => return Err(CarError::BadProgram(program.clone())),
+    };
+    let exec = parse(&source).map_err(|_| CarError::BadProgram(program.clone()))?;
+    Ok(slicer::slice(&compile(manifest.name(), &exec)))
+}
+
+fn write_varint(bytes: &mut Vec<u8>, n: usize) {
+    bytes.extend(varint_encode::usize(n, &mut varint_encode::usize_buffer()));
+}
+
+/// Takes the next length-prefixed section off `bytes`.
+fn section<'a>(bytes: &mut &'a [u8]) -> Option<&'a [u8]> {
+    let (len, rest) = varint_decode::usize(bytes).ok()?;
+    if rest.len() < len {
+        return None;
+    }
+    let (section, rest) = rest.split_at(len);
+    *bytes = rest;
+    Some(section)
+}
+
+/// The length of the CID at the start of `bytes`.
+fn cid_len(bytes: &[u8]) -> Option<usize> {
+    // A CIDv0 is a bare SHA2-256 multihash.
+    if bytes.starts_with(&[0x12, 0x20]) {
+        return if bytes.len() >= 34 { Some(34) } else { None };
+    }
+    let (_version, rest) = varint_decode::u64(bytes).ok()?;
+    let (_codec, rest) = varint_decode::u64(rest).ok()?;
+    let (_hash, rest) = varint_decode::u64(rest).ok()?;
+    let (len, rest) = varint_decode::usize(rest).ok()?;
+    if rest.len() < len {
+        return None;
+    }
+    Some(bytes.len() - rest.len() + len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ambient::Ambient;
+    use crate::signing::{ Keypair, Scheme, Signer };
+
+    const PROGRAM: &str = "c[in_ b.open a | a[open_]] | b[in c]";
+
+    /// A store with the program deployed by `keypair`, writable by `access`.
+    fn deployed(keypair: &Keypair, access: &Access) -> (Cid, MemoryStore) {
+        let mut store = MemoryStore::new();
+        let ambient = Ambient::deploy_with_access("program", PROGRAM, keypair, access).unwrap();
+        ambient.store(&mut store).unwrap();
+        access.store(&mut store).unwrap();
+        (ambient.cid().clone(), store)
+    }
+
+    #[test]
+    fn car_roundtrip() {
+        let keypair = Keypair::generate(Scheme::Ed25519);
+        let other = Keypair::generate(Scheme::Secp256k1);
+        let (manifest, store) = deployed(&keypair, &Access::Keys(vec![keypair.public_key(), other.public_key()]));
+
+        let car = export(&manifest, &store).unwrap();
+        let slices = slicer::slice(&compile("program", &parse(PROGRAM).unwrap()));
+        assert_eq!(car.roots, vec![manifest.clone(), slices.root.clone()]);
+        assert_eq!(car.blocks[0].0, manifest);
+        // The manifest, the program, the key list, the creator's key and the slices.
+        assert_eq!(car.blocks.len(), 4 + slices.blocks.len());
+
+        let mut bytes = vec![];
+        car.write(&mut bytes).unwrap();
+        let read = Car::read(&mut &bytes[..]).unwrap();
+        assert_eq!(read, car);
+
+        let mut imported = MemoryStore::new();
+        assert_eq!(import(&read, &mut imported), Ok(manifest.clone()));
+        assert_eq!(imported.len(), car.blocks.len());
+        assert_eq!(Manifest::load(&manifest, &imported).unwrap().verify(&program_cid(PROGRAM)), Ok(()));
+        assert_eq!(slicer::load(&slices.root, &imported).unwrap(), slices);
+        assert_eq!(export(&manifest, &imported), Ok(car));
+    }
+
+    #[test]
+    fn car_single_key() {
+        // The key list of a program only its creator can write to is the creator's key.
+        let keypair = Keypair::generate(Scheme::Secp256k1);
+        let (manifest, store) = deployed(&keypair, &Access::Keys(vec![keypair.public_key()]));
+        let car = export(&manifest, &store).unwrap();
+        let slices = slicer::slice(&compile("program", &parse(PROGRAM).unwrap()));
+        assert_eq!(car.blocks.len(), 3 + slices.blocks.len());
+        assert_eq!(import(&car, &mut MemoryStore::new()), Ok(manifest));
+    }
+
+    #[test]
+    fn car_decode_errors() {
+        let keypair = Keypair::generate(Scheme::Ed25519);
+        let (manifest, store) = deployed(&keypair, &Access::Any);
+        let bytes = export(&manifest, &store).unwrap().encode();
+
+        assert_eq!(Car::decode(&[]), Err(CarError::BadHeader));
+        assert_eq!(Car::decode(&bytes[..bytes.len() - 1]), Err(CarError::BadSection));
+        let empty = Car { roots: vec![], blocks: vec![] };
+        assert_eq!(Car::decode(&empty.encode()), Err(CarError::BadHeader));
+
+        let mut tampered = bytes.clone();
+        let last = tampered.len() - 1;
+        tampered[last] ^= 1;
+        assert!(matches!(Car::decode(&tampered), Err(CarError::Store(StoreError::HashMismatch(_)))));
+    }
+
+    #[test]
+    fn car_import_errors() {
+        let keypair = Keypair::generate(Scheme::Ed25519);
+        let (manifest, store) = deployed(&keypair, &Access::Keys(vec![keypair.public_key()]));
+        let car = export(&manifest, &store).unwrap();
+        let import = |car: &Car| {
+            let mut store = MemoryStore::new();
+            let result = import(car, &mut store);
+            assert!(result.is_ok() || store.is_empty());
+            result
+        };
+
+        let program = program_cid(PROGRAM);
+        let mut missing = car.clone();
+        missing.blocks.retain(|(cid, _)| *cid != program);
+        assert_eq!(import(&missing), Err(CarError::Missing(program.clone())));
+
+        // A program other than the manifest's, stored under the manifest's program CID.
+        let mut swapped = car.clone();
+        let other = dag_cbor::encode(&Ipld::String("a[]".to_string()));
+        swapped.blocks.retain(|(cid, _)| *cid != program);
+        swapped.blocks.push((program.clone(), other));
+        assert!(matches!(import(&swapped), Err(CarError::Store(StoreError::HashMismatch(_)))));
+
+        let mut wrong_slices = car.clone();
+        wrong_slices.roots[1] = program.clone();
+        assert_eq!(import(&wrong_slices), Err(CarError::SliceMismatch(program)));
+
+        let mut no_slices = car.clone();
+        no_slices.blocks.retain(|(cid, _)| *cid != car.roots[1]);
+        assert_eq!(import(&no_slices), Err(CarError::Slices(SliceError::Missing(car.roots[1].clone()))));
+
+        // A manifest re-signed by someone who isn't its creator.
+        let mut forged = Manifest::load(&manifest, &store).unwrap();
+        let creator = forged.creator().cloned();
+        forged.sign(&Keypair::generate(Scheme::Ed25519)).unwrap();
+        let forged = Manifest::new(forged.program(), forged.name(), forged.keys().cloned(), creator, forged.signature().map(<[u8]>::to_vec));
+        let mut forged_car = car.clone();
+        forged_car.roots[0] = forged.cid();
+        forged_car.blocks[0] = (forged.cid(), forged.to_cbor());
+        assert_eq!(import(&forged_car), Err(CarError::Verify(VerifyError::BadSignature)));
+
+        let mut no_keys = car;
+        let keys = Manifest::load(&manifest, &store).unwrap().keys().unwrap().hash().clone();
+        no_keys.blocks.retain(|(cid, _)| *cid != keys);
+        assert_eq!(import(&no_keys), Err(CarError::Missing(keys)));
+    }
+}
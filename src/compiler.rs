@@ -0,0 +1,485 @@
+//! Compilation of parsed programs to the bytecode of the Ambients protocol.
+//!
+//! The compiler produces a bytecode representation for every unique ambient and their nested
+//! ambients. Each ambient compiles to the list of `(<opcode>, <target>)` instructions found in
+//! its body, in source order, where every nested ambient is a `create` instruction and compiles
+//! to its own list:
+//!
+//! ```text
+//! a[in b] | b[in_ a.open_]
+//!
+//! program: (1 deploy, "program") (0 create, "a") (0 create, "b")
+//!   a: (2 in, "b")
+//!   b: (8 seq, 0) (3 in_, "a") (7 open_, 0) (10 end, 0)
+//! ```
+//!
+//! The instructions of an ambient run in parallel. Steps taken one after another are a `seq`
+//! block up to its `end`, and processes run in parallel as a single step of a `seq` block are a
+//! `par` block, so `in a.(b[]|c[])` compiles to:
+//!
+//! ```text
+//! (8 seq, 0) (2 in, "a") (9 par, 0) (0 create, "b") (0 create, "c") (10 end, 0) (10 end, 0)
+//! ```
+//!
+//! The inactive process `0` compiles to nothing, and blocks left with a single step or process
+//! are dropped, so `in a.(in b|0)` compiles like `in a.in b`.
+//!
+//! Targets that are protocol primitives compile to the primitive's opcode, e.g. `in func`
+//! compiles to `(2 in, 0 func)`.
+
+use ambients_parser::ast::Exec;
+use crate::prelude::*;
+use crate::primitives::{ Capability, Instruction, Operand };
+
+/// A single bytecode instruction: a capability and its target.
+pub type Bytecode<'a> = Instruction<Capability, Operand<'a>>;
+
+/// The bytecode of an ambient, along with the bytecode of the ambients nested in it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Compiled<'a> {
+    /// The name of the ambient.
+    pub name: &'a str,
+    /// The instructions of the ambient itself.
+    pub instructions: Vec<Bytecode<'a>>,
+    /// The ambients this one creates, in the order of their `create` instructions.
+    pub children: Vec<Compiled<'a>>,
+}
+
+/// Compiles the program `exec`, deployed as `name`.
+///
+/// The program itself is compiled as an ambient named `name`, which starts with a `deploy`
+/// instruction.
+pub fn compile<'a>(name: &'a str, exec: &Exec<'a>) -> Compiled<'a> {
+    let mut program = Compiled::new(name);
+    program.push(Capability::deploy, name);
+    program.emit(exec);
+    program
+}
+
+impl<'a> Compiled<'a> {
+    fn new(name: &'a str) -> Compiled<'a> {
+        Compiled { name, instructions: vec![], children: vec![] }
+    }
+
+    fn push(&mut self, opcode: Capability, target: &'a str) {
+        self.instructions.push(Instruction::new(opcode, Operand::from_name(target)));
+    }
+
+    fn emit(&mut self, exec: &Exec<'a>) {
+        match exec {
+            Exec::Parallel(procs, _) => {
+                for p in procs {
+                    self.emit(p);
+                }
+            },
+            Exec::Serial(steps, _) => match &non_nil(steps)[..] {
+                [] => {},
+                [step] => self.emit(step),
+                _ => {
+                    self.push(Capability::seq, "*");
+                    self.emit_step(exec);
+                    self.push(Capability::end, "*");
+                },
+            },
+            Exec::Group(inner, _) => self.emit(inner),
+            Exec::Noop(name, _) => {
+                self.push(Capability::create, name);
+                self.children.push(Compiled::new(name));
+            },
+            Exec::Ambient(name, body, _) => {
+                self.push(Capability::create, name);
+                let mut child = Compiled::new(name);
+                child.emit(body);
+                self.children.push(child);
+            },
+            Exec::In(name, _) => self.push(Capability::r#in, name),
+            Exec::In_(name, _) => self.push(Capability::in_, name),
+            Exec::Out(name, _) => self.push(Capability::out, name),
+            Exec::Out_(name, _) => self.push(Capability::out_, name),
+            Exec::Open(name, _) => self.push(Capability::open, name),
+            Exec::Open_(name, _) => self.push(Capability::open_, name),
+        }
+    }
+
+    /// Emits `exec` as steps of the enclosing `seq` block.
+    fn emit_step(&mut self, exec: &Exec<'a>) {
+        match exec {
+            Exec::Serial(steps, _) => {
+                for step in steps {
+                    self.emit_step(step);
+                }
+            },
+            Exec::Group(inner, _) => self.emit_step(inner),
+            Exec::Parallel(procs, _) => match &non_nil(procs)[..] {
+                [] => {},
+                [proc] => self.emit_step(proc),
+                procs => {
+                    self.push(Capability::par, "*");
+                    procs.iter().for_each(|p| self.emit(p));
+                    self.push(Capability::end, "*");
+                },
+            },
+            _ => self.emit(exec),
+        }
+    }
+
+    fn write(&self, f: &mut fmt::Formatter<'_>, depth: usize) -> fmt::Result {
+        write!(f, "{:width$}{}:", "", self.name, width = depth * 2)?;
+        for instruction in &self.instructions {
+            write!(f, " {}", instruction)?;
+        }
+        for child in &self.children {
+            writeln!(f)?;
+            child.write(f, depth + 1)?;
+        }
+        Ok(())
+    }
+}
+
+/// Whether `exec` is the inactive process `0`, however it's grouped or composed with itself.
+fn is_nil(exec: &Exec) -> bool {
+    match exec {
+        Exec::Parallel(procs, _) | Exec::Serial(procs, _) => procs.iter().all(is_nil),
+        Exec::Group(inner, _) => is_nil(inner),
+        _ => false,
+    }
+}
+
+/// The processes of `procs` other than `0`, which compile to nothing.
+fn non_nil<'e, 'a>(procs: &'e [Exec<'a>]) -> Vec<&'e Exec<'a>> {
+    procs.iter().filter(|p| !is_nil(p)).collect()
+}
+
+/// Prints one ambient per line, nested ambients indented under their parent.
+impl<'a> Display for Compiled<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write(f, 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ambients_parser::parse;
+
+    fn assert_compiles_to(program: &str, expected: &str) {
+        let exec = parse(program).unwrap();
+        assert_eq!(compile("program", &exec).to_string(), expected.trim());
+    }
+
+    #[test]
+    fn compile_instructions() {
+        let exec = parse("a[in func.open_]").unwrap();
+        let compiled = compile("program", &exec);
+        assert_eq!(compiled.instructions, vec![
+            Instruction::new(Capability::deploy, Operand::Name("program")),
+            Instruction::new(Capability::create, Operand::Name("a")),
+        ]);
+        assert_eq!(compiled.children[0].instructions, vec![
+            Instruction::new(Capability::seq, Operand::Any),
+            Instruction::new(Capability::r#in, Operand::Computation(crate::primitives::Computation::func)),
+            Instruction::new(Capability::open_, Operand::Any),
+            Instruction::new(Capability::end, Operand::Any),
+        ]);
+    }
+
+    #[test]
+    fn compile_serial() {
+        let compiled = |program| compile("program", &parse(program).unwrap());
+        assert_ne!(compiled("x[in a.in b]"), compiled("x[in a | in b]"));
+        assert_ne!(compiled("x[in a.(in b|c[])]"), compiled("x[in a.in b|c[]]"));
+        assert_eq!(compiled("x[(in a.in b)]"), compiled("x[in a.in b]"));
+        assert_eq!(compiled("x[in a.(in b.in c)]"), compiled("x[in a.in b.in c]"));
+        assert_eq!(compiled("x[in a.(in b)]"), compiled("x[in a.in b]"));
+
+        // `0` compiles to nothing, wherever it is.
+        assert_eq!(compiled("a[in b|0]"), compiled("a[in b]"));
+        assert_eq!(compiled("x[in a.(in b|0)]"), compiled("x[in a.in b]"));
+        assert_eq!(compiled("x[in a.(in b|0|c[])]"), compiled("x[in a.(in b|c[])]"));
+        assert_eq!(compiled("x[in a.(0|0)]"), compiled("x[in a]"));
+        assert_eq!(compiled("x[(0|in a).in b]"), compiled("x[in a.in b]"));
+
+        assert_compiles_to("x[in a | in b]", r#"
+program: (1 deploy, "program") (0 create, "x")
+  x: (2 in, "a") (2 in, "b")
+"#);
+        assert_compiles_to("a[in b] | b[in_ a.open_]", r#"
+program: (1 deploy, "program") (0 create, "a") (0 create, "b")
+  a: (2 in, "b")
+  b: (8 seq, 0) (3 in_, "a") (7 open_, 0) (10 end, 0)
+"#);
+        assert_compiles_to("x[in a.(b[]|c[]) | out_ y]", r#"
+program: (1 deploy, "program") (0 create, "x")
+  x: (8 seq, 0) (2 in, "a") (9 par, 0) (0 create, "b") (0 create, "c") (10 end, 0) (10 end, 0) (5 out_, "y")
+    b:
+    c:
+"#);
+    }
+
+    #[test]
+    fn compile_values() {
+        assert_compiles_to("a[]", r#"
+program: (1 deploy, "program") (0 create, "a")
+  a:
+"#);
+        assert_compiles_to("hello[]", r#"
+program: (1 deploy, "program") (0 create, "hello")
+  hello:
+"#);
+    }
+
+    #[test]
+    fn compile_parallel() {
+        assert_compiles_to("a[] | b[]", r#"
+program: (1 deploy, "program") (0 create, "a") (0 create, "b")
+  a:
+  b:
+"#);
+        assert_compiles_to("a[ b[] ] | c[]", r#"
+program: (1 deploy, "program") (0 create, "a") (0 create, "c")
+  a: (0 create, "b")
+    b:
+  c:
+"#);
+    }
+
+    #[test]
+    fn compile_capabilities() {
+        assert_compiles_to("a[b[open_|c[]]|open b]", r#"
+program: (1 deploy, "program") (0 create, "a")
+  a: (0 create, "b") (6 open, "b")
+    b: (7 open_, 0) (0 create, "c")
+      c:
+"#);
+        assert_compiles_to("a[in b] | b[in_ a]", r#"
+program: (1 deploy, "program") (0 create, "a") (0 create, "b")
+  a: (2 in, "b")
+  b: (3 in_, "a")
+"#);
+        assert_compiles_to("b[a[out b]|out_ a]", r#"
+program: (1 deploy, "program") (0 create, "b")
+  b: (0 create, "a") (5 out_, "a")
+    a: (4 out, "b")
+"#);
+    }
+
+    #[test]
+    fn compile_paths() {
+        assert_compiles_to("a[in c] | b[in c] | c[in_ a.in_ b.in d] | d[in_ c]", r#"
+program: (1 deploy, "program") (0 create, "a") (0 create, "b") (0 create, "c") (0 create, "d")
+  a: (2 in, "c")
+  b: (2 in, "c")
+  c: (8 seq, 0) (3 in_, "a") (3 in_, "b") (2 in, "d") (10 end, 0)
+  d: (3 in_, "c")
+"#);
+        assert_compiles_to("a[in b.in_ |b[]]", r#"
+program: (1 deploy, "program") (0 create, "a")
+  a: (8 seq, 0) (2 in, "b") (3 in_, 0) (10 end, 0) (0 create, "b")
+    b:
+"#);
+    }
+
+    #[test]
+    fn compile_func() {
+        assert_compiles_to("func[in_ x.open x.open_]", r#"
+program: (1 deploy, "program") (0 create, 0 func)
+  func: (8 seq, 0) (3 in_, "x") (6 open, "x") (7 open_, 0) (10 end, 0)
+"#);
+        assert_compiles_to("func[in_ x.open x.open_] | x[in func.open_|result[]] |open func", r#"
+program: (1 deploy, "program") (0 create, 0 func) (0 create, "x") (6 open, 0 func)
+  func: (8 seq, 0) (3 in_, "x") (6 open, "x") (7 open_, 0) (10 end, 0)
+  x: (8 seq, 0) (2 in, 0 func) (7 open_, 0) (10 end, 0) (0 create, "result")
+    result:
+"#);
+    }
+
+    #[test]
+    fn compile_arg() {
+        assert_compiles_to("arg[in_ x.open x.in y.open_] | y[in_ arg.open arg.in func.open_]", r#"
+program: (1 deploy, "program") (0 create, 2 arg) (0 create, "y")
+  arg: (8 seq, 0) (3 in_, "x") (6 open, "x") (2 in, "y") (7 open_, 0) (10 end, 0)
+  y: (8 seq, 0) (3 in_, 2 arg) (6 open, 2 arg) (2 in, 0 func) (7 open_, 0) (10 end, 0)
+"#);
+        assert_compiles_to("
+arg[in_ x.open x.in y.open_] | x[in arg.open_|input[]] |
+y[in_ arg.open arg.in func.open_] |
+func[in_ y.open y.open_]
+", r#"
+program: (1 deploy, "program") (0 create, 2 arg) (0 create, "x") (0 create, "y") (0 create, 0 func)
+  arg: (8 seq, 0) (3 in_, "x") (6 open, "x") (2 in, "y") (7 open_, 0) (10 end, 0)
+  x: (8 seq, 0) (2 in, 2 arg) (7 open_, 0) (10 end, 0) (0 create, "input")
+    input:
+  y: (8 seq, 0) (3 in_, 2 arg) (6 open, 2 arg) (2 in, 0 func) (7 open_, 0) (10 end, 0)
+  func: (8 seq, 0) (3 in_, "y") (6 open, "y") (7 open_, 0) (10 end, 0)
+"#);
+    }
+
+    #[test]
+    fn compile_function_expression() {
+        assert_compiles_to("
+message[
+  in func.open_|
+  func[
+    x[in_ arg.open arg.in message.open_]|
+    message[in_ x.open x]|
+    in_ arg.open_
+  ]
+] |
+func[
+  in_ message.open message.open func.open_|
+  arg[
+    in func.in x.open_|
+    string[hello[]]
+  ]
+]|
+open func
+", r#"
+program: (1 deploy, "program") (0 create, "message") (0 create, 0 func) (6 open, 0 func)
+  message: (8 seq, 0) (2 in, 0 func) (7 open_, 0) (10 end, 0) (0 create, 0 func)
+    func: (0 create, "x") (0 create, "message") (8 seq, 0) (3 in_, 2 arg) (7 open_, 0) (10 end, 0)
+      x: (8 seq, 0) (3 in_, 2 arg) (6 open, 2 arg) (2 in, "message") (7 open_, 0) (10 end, 0)
+      message: (8 seq, 0) (3 in_, "x") (6 open, "x") (10 end, 0)
+  func: (8 seq, 0) (3 in_, "message") (6 open, "message") (6 open, 0 func) (7 open_, 0) (10 end, 0) (0 create, 2 arg)
+    arg: (8 seq, 0) (2 in, 0 func) (2 in, "x") (7 open_, 0) (10 end, 0) (0 create, "string")
+      string: (0 create, "hello")
+        hello:
+"#);
+    }
+
+    #[test]
+    fn compile_call() {
+        assert_compiles_to("call[out x.in y.open_]", r#"
+program: (1 deploy, "program") (0 create, 1 call)
+  call: (8 seq, 0) (4 out, "x") (2 in, "y") (7 open_, 0) (10 end, 0)
+"#);
+        assert_compiles_to("
+x[call[out x.in y.open_|payload[]] | out_ call] |
+y[in_ call.open call]
+", r#"
+program: (1 deploy, "program") (0 create, "x") (0 create, "y")
+  x: (0 create, 1 call) (5 out_, 1 call)
+    call: (8 seq, 0) (4 out, "x") (2 in, "y") (7 open_, 0) (10 end, 0) (0 create, "payload")
+      payload:
+  y: (8 seq, 0) (3 in_, 1 call) (6 open, 1 call) (10 end, 0)
+"#);
+    }
+
+    #[test]
+    fn compile_return() {
+        assert_compiles_to("return[open_.in x]", r#"
+program: (1 deploy, "program") (0 create, 3 return)
+  return: (8 seq, 0) (7 open_, 0) (2 in, "x") (10 end, 0)
+"#);
+        assert_compiles_to("
+x[
+    call[out x.in y.open_|return[open_.in x]]|
+    out_ call.in_ y
+] |
+y[in_ call.open call.open return]
+", r#"
+program: (1 deploy, "program") (0 create, "x") (0 create, "y")
+  x: (0 create, 1 call) (8 seq, 0) (5 out_, 1 call) (3 in_, "y") (10 end, 0)
+    call: (8 seq, 0) (4 out, "x") (2 in, "y") (7 open_, 0) (10 end, 0) (0 create, 3 return)
+      return: (8 seq, 0) (7 open_, 0) (2 in, "x") (10 end, 0)
+  y: (8 seq, 0) (3 in_, 1 call) (6 open, 1 call) (6 open, 3 return) (10 end, 0)
+"#);
+    }
+
+    #[test]
+    fn compile_monoid() {
+        assert_compiles_to("
+string_concat[
+  in_ call.open call.(
+    func[
+      left[
+        in_ arg.open arg.in string.in concat
+      ]|
+      right[
+        in_ arg.open arg.in string.in concat
+      ]|
+      string[
+        concat[in_ left|in_ right]|
+        in_ left|in_ right
+      ]|
+      open_
+    ]|
+    open return.open_
+  )
+]", r#"
+program: (1 deploy, "program") (0 create, "string_concat")
+  string_concat: (8 seq, 0) (3 in_, 1 call) (6 open, 1 call) (9 par, 0) (0 create, 0 func) (8 seq, 0) (6 open, 3 return) (7 open_, 0) (10 end, 0) (10 end, 0) (10 end, 0)
+    func: (0 create, "left") (0 create, "right") (0 create, "string") (7 open_, 0)
+      left: (8 seq, 0) (3 in_, 2 arg) (6 open, 2 arg) (2 in, "string") (2 in, "concat") (10 end, 0)
+      right: (8 seq, 0) (3 in_, 2 arg) (6 open, 2 arg) (2 in, "string") (2 in, "concat") (10 end, 0)
+      string: (0 create, "concat") (3 in_, "left") (3 in_, "right")
+        concat: (3 in_, "left") (3 in_, "right")
+"#);
+        assert_compiles_to("
+string[
+  concat[
+    left[string[a[]]]|
+    right[string[b[]]]
+  ]
+]
+", r#"
+program: (1 deploy, "program") (0 create, "string")
+  string: (0 create, "concat")
+    concat: (0 create, "left") (0 create, "right")
+      left: (0 create, "string")
+        string: (0 create, "a")
+          a:
+      right: (0 create, "string")
+        string: (0 create, "b")
+          b:
+"#);
+        assert_compiles_to("
+string[
+  concat[
+    left[
+      string[
+        concat[
+          left[string[a[]]]|
+          right[string[b[]]]
+        ]
+      ]
+    ]|
+    right[string[c[]]]
+  ]
+]
+", r#"
+program: (1 deploy, "program") (0 create, "string")
+  string: (0 create, "concat")
+    concat: (0 create, "left") (0 create, "right")
+      left: (0 create, "string")
+        string: (0 create, "concat")
+          concat: (0 create, "left") (0 create, "right")
+            left: (0 create, "string")
+              string: (0 create, "a")
+                a:
+            right: (0 create, "string")
+              string: (0 create, "b")
+                b:
+      right: (0 create, "string")
+        string: (0 create, "c")
+          c:
+"#);
+    }
+
+    #[test]
+    fn compile_functors() {
+        assert_compiles_to("
+identity[
+  int[
+    length[string[hello[]]]
+  ]
+]
+", r#"
+program: (1 deploy, "program") (0 create, "identity")
+  identity: (0 create, "int")
+    int: (0 create, "length")
+      length: (0 create, "string")
+        string: (0 create, "hello")
+          hello:
+"#);
+    }
+}
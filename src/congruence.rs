@@ -0,0 +1,137 @@
+//! Structural congruence of ambient expressions.
+//!
+//! Two terms are structurally congruent when the calculus can't tell them apart, i.e. when they
+//! can be rewritten into each other with the rules:
+//!
+//! ```text
+//! P | Q ≡ Q | P              (commutativity)
+//! (P | Q) | R ≡ P | (Q | R)  (associativity)
+//! P | 0 ≡ P                  (0 is the unit of |)
+//! a[0] ≡ a[]
+//! M.0 ≡ M
+//! ```
+//!
+//! Congruence is decided by rewriting both terms into a canonical form, in which parallel
+//! composition is flattened and its processes are sorted, and comparing those.
+
+use ambients_parser::ast::Exec;
+use crate::reduction::{ components, compose };
+
+/// Whether `a` and `b` are structurally congruent.
+///
+/// ```text
+/// a[] | b[in_ a]  ≡  (b[in_ a.0] | 0) | a[0]
+/// ```
+pub fn congruent(a: &Exec, b: &Exec) -> bool {
+    canonicalize(a) == canonicalize(b)
+}
+
+/// Rewrites `exec` into its canonical form: nested `Parallel`s and `Group`s are flattened, the
+/// `0` processes are dropped and the remaining processes sorted, at every level of the tree.
+///
+/// Congruent terms have equal canonical forms, and the canonical form of a term is congruent to
+/// it.
+pub fn canonicalize<'a>(exec: &Exec<'a>) -> Exec<'a> {
+    let mut procs: Vec<(String, Exec)> = components(exec.clone()).iter()
+        .map(canonicalize_process)
+        .filter(|p| !is_nil(p))
+        .map(|p| (p.to_string(), p))
+        .collect();
+    procs.sort_by(|(a, _), (b, _)| a.cmp(b));
+    compose(procs.into_iter().map(|(_, p)| p).collect())
+}
+
+/// Canonicalizes a single process of a parallel composition, i.e. anything but a `Parallel` or a
+/// `Group`.
+fn canonicalize_process<'a>(exec: &Exec<'a>) -> Exec<'a> {
+    match exec {
+        Exec::Ambient(name, body, span) => {
+            let body = canonicalize(body);
+            if is_nil(&body) {
+                Exec::Noop(name, *span)
+            } else {
+                Exec::Ambient(name, Box::new(body), *span)
+            }
+        },
+        Exec::Serial(seq, span) => {
+            let mut canonical = Vec::new();
+            for e in seq {
+                match canonicalize(e) {
+                    Exec::Serial(inner, _) => canonical.extend(inner),
+                    e if is_nil(&e) => {},
+                    e @ Exec::Parallel(..) => {
+                        let span = e.span();
+                        canonical.push(Exec::Group(Box::new(e), span))
+                    },
+                    e => canonical.push(e),
+                }
+            }
+            match canonical.len() {
+                0 => Exec::Parallel(vec![], *span),
+                1 => canonical.pop().unwrap(),
+                _ => Exec::Serial(canonical, *span),
+            }
+        },
+        e => e.clone(),
+    }
+}
+
+fn is_nil(exec: &Exec) -> bool {
+    match exec {
+        Exec::Parallel(procs, _) => procs.is_empty(),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ambients_parser::parse;
+
+    fn assert_congruent(a: &str, b: &str) {
+        let (a, b) = (parse(a).unwrap(), parse(b).unwrap());
+        assert!(congruent(&a, &b), "{} ≢ {}", a, b);
+    }
+
+    fn assert_not_congruent(a: &str, b: &str) {
+        let (a, b) = (parse(a).unwrap(), parse(b).unwrap());
+        assert!(!congruent(&a, &b), "{} ≡ {}", a, b);
+    }
+
+    #[test]
+    fn congruent_parallel() {
+        assert_congruent("a[] | b[]", "b[] | a[]");
+        assert_congruent("(a[] | b[]) | c[]", "a[] | (b[] | c[])");
+        assert_congruent("a[] | b[] | c[]", "c[] | (b[] | a[])");
+        assert_congruent("x[a[] | b[in c]]", "x[(b[in c] | a[])]");
+        assert_not_congruent("a[] | b[]", "a[] | a[] | b[]");
+        assert_not_congruent("a[b[]]", "a[] | b[]");
+    }
+
+    #[test]
+    fn congruent_nil() {
+        assert_congruent("a[] | 0", "a[]");
+        assert_congruent("a[0]", "a[]");
+        assert_congruent("a[0 | 0]", "a[]");
+        assert_congruent("in b.0", "in b");
+        assert_congruent("a[in b.(0 | c[])]", "a[in b.c[]]");
+        assert_congruent("0 | (0 | 0)", "0");
+    }
+
+    #[test]
+    fn congruent_serial() {
+        assert_congruent("in a.(open_ | b[])", "in a.(b[] | open_)");
+        assert_congruent("in a.(in b.open_)", "in a.in b.open_");
+        assert_not_congruent("in a.in b", "in b.in a");
+        assert_not_congruent("in a.(b[] | c[])", "in a.b[] | c[]");
+    }
+
+    #[test]
+    fn canonical_form() {
+        let exec = parse("(b[in_ a.0] | 0) | a[0] | c[(e[] | d[])]").unwrap();
+        assert_eq!(canonicalize(&exec).to_string(), "a[] | b[in_ a] | c[d[]|e[]]");
+
+        let canonical = canonicalize(&exec);
+        assert_eq!(canonicalize(&canonical), canonical);
+    }
+}
@@ -0,0 +1,354 @@
+//! DAG-CBOR, the deterministic subset of CBOR that IPLD uses for Merkle-DAG nodes.
+//!
+//! Every value has exactly one encoding: integers and lengths use the shortest form, map keys
+//! are strings sorted by length and then bytewise, and links to other nodes are CIDs in tag 42.
+//! Decoding rejects anything that isn't in that form, so that decoding and re-encoding a node
+//! always gives back the bytes it was hashed from.
+
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::error::Error;
+
+use cid::{ Cid, Codec };
+use multihash::Sha2_256;
+
+use crate::prelude::*;
+
+/// The CBOR tag of a CID.
+const CID_TAG: u64 = 42;
+
+/// How deep lists and maps can nest before decoding gives up.
+const MAX_DEPTH: usize = 64;
+
+/// A value of the IPLD data model.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Ipld {
+    /// `null`.
+    Null,
+    /// `true` or `false`.
+    Bool(bool),
+    /// A signed integer.
+    Integer(i64),
+    /// A byte string.
+    Bytes(Vec<u8>),
+    /// A UTF-8 string.
+    String(String),
+    /// A list of values.
+    List(Vec<Ipld>),
+    /// A map from strings to values.
+    Map(BTreeMap<String, Ipld>),
+    /// A link to another node.
+    Link(Cid),
+}
+
+/// Why bytes couldn't be decoded as DAG-CBOR.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The input ended in the middle of a value.
+    UnexpectedEnd,
+    /// A CBOR item DAG-CBOR doesn't allow, e.g. a float, an undefined or an indefinite length.
+    Unsupported(u8),
+    /// An integer or length that isn't in its shortest form, or a negative integer too small
+    /// for an `i64`.
+    BadInteger,
+    /// A string that isn't UTF-8.
+    BadString,
+    /// A map key that isn't a string, or keys that aren't sorted or unique.
+    BadMap,
+    /// A tag other than 42, or a tag 42 that isn't a CID.
+    BadLink,
+    /// Lists or maps nested deeper than decoding allows.
+    TooDeep,
+    /// More bytes after the value.
+    TrailingBytes(usize),
+}
+
+impl Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::UnexpectedEnd => write!(f, "unexpected end of input"),
+            DecodeError::Unsupported(byte) => write!(f, "unsupported CBOR item {:#04x}", byte),
+            DecodeError::BadInteger => write!(f, "integer not in its shortest form or out of range"),
+            DecodeError::BadString => write!(f, "string isn't UTF-8"),
+            DecodeError::BadMap => write!(f, "map keys aren't unique, sorted strings"),
+            DecodeError::BadLink => write!(f, "invalid link"),
+            DecodeError::TooDeep => write!(f, "values nested too deep"),
+            DecodeError::TrailingBytes(n) => write!(f, "{} trailing bytes after the value", n),
+        }
+    }
+}
+
+impl Error for DecodeError {}
+
+impl Ipld {
+    /// Looks up `key` in a map.
+    pub fn get(&self, key: &str) -> Option<&Ipld> {
+        match self {
+            Ipld::Map(map) => map.get(key),
+            _ => None,
+        }
+    }
+}
+
+/// Encodes `value` as DAG-CBOR.
+pub fn encode(value: &Ipld) -> Vec<u8> {
+    let mut bytes = vec![];
+    write(&mut bytes, value);
+    bytes
+}
+
+/// Decodes a single DAG-CBOR value spanning all of `bytes`.
+pub fn decode(bytes: &[u8]) -> Result<Ipld, DecodeError> {
+    let mut reader = Reader { bytes };
+    let value = reader.value(0)?;
+    match reader.bytes.len() {
+        0 => Ok(value),
+        n => Err(DecodeError::TrailingBytes(n)),
+    }
+}
+
+/// The CID of a DAG-CBOR encoded node: a CIDv1 with the `dag-cbor` codec and the SHA2-256
+/// multihash of `bytes`.
+pub fn cid(bytes: &[u8]) -> Cid {
+    Cid::new_v1(Codec::DagCBOR, Sha2_256::digest(bytes))
+}
+
+fn write_head(bytes: &mut Vec<u8>, major: u8, n: u64) {
+    let major = major << 5;
+    if n < 24 {
+        bytes.push(major | n as u8);
+    } else if n <= u64::from(u8::MAX) {
+        bytes.extend(&[major | 24, n as u8]);
+    } else if n <= u64::from(u16::MAX) {
+        bytes.push(major | 25);
+        bytes.extend(&(n as u16).to_be_bytes());
+    } else if n <= u64::from(u32::MAX) {
+        bytes.push(major | 26);
+        bytes.extend(&(n as u32).to_be_bytes());
+    } else {
+        bytes.push(major | 27);
+        bytes.extend(&n.to_be_bytes());
+    }
+}
+
+fn write(bytes: &mut Vec<u8>, value: &Ipld) {
+    match value {
+        Ipld::Null => bytes.push(0xf6),
+        Ipld::Bool(false) => bytes.push(0xf4),
+        Ipld::Bool(true) => bytes.push(0xf5),
+        Ipld::Integer(n) if *n >= 0 => write_head(bytes, 0, *n as u64),
+        Ipld::Integer(n) => write_head(bytes, 1, (-1 - *n) as u64),
+        Ipld::Bytes(b) => {
+            write_head(bytes, 2, b.len() as u64);
+            bytes.extend(b);
+        },
+        Ipld::String(s) => {
+            write_head(bytes, 3, s.len() as u64);
+            bytes.extend(s.as_bytes());
+        },
+        Ipld::List(list) => {
+            write_head(bytes, 4, list.len() as u64);
+            for v in list {
+                write(bytes, v);
+            }
+        },
+        Ipld::Map(map) => {
+            write_head(bytes, 5, map.len() as u64);
+            let mut entries: Vec<_> = map.iter().collect();
+            entries.sort_by(|(a, _), (b, _)| key_order(a, b));
+            for (k, v) in entries {
+                write_head(bytes, 3, k.len() as u64);
+                bytes.extend(k.as_bytes());
+                write(bytes, v);
+            }
+        },
+        Ipld::Link(cid) => {
+            let cid = cid.to_bytes();
+            write_head(bytes, 6, CID_TAG);
+            // The leading zero is the multibase prefix of binary CIDs.
+            write_head(bytes, 2, cid.len() as u64 + 1);
+            bytes.push(0);
+            bytes.extend(cid);
+        },
+    }
+}
+
+/// Map keys are sorted by length first, then bytewise.
+fn key_order(a: &str, b: &str) -> std::cmp::Ordering {
+    a.len().cmp(&b.len()).then_with(|| a.cmp(b))
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: u64) -> Result<&'a [u8], DecodeError> {
+        if (self.bytes.len() as u64) < n {
+            return Err(DecodeError::UnexpectedEnd);
+        }
+        let (taken, rest) = self.bytes.split_at(n as usize);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    /// Reads the major type and argument of the next item.
+    fn head(&mut self) -> Result<(u8, u64), DecodeError> {
+        let byte = self.take(1)?[0];
+        let (major, info) = (byte >> 5, byte & 0x1f);
+        if major == 7 {
+            return Ok((major, u64::from(info)));
+        }
+        let (n, min) = match info {
+            0..=23 => return Ok((major, u64::from(info))),
+            24 => (u64::from(self.take(1)?[0]), 24),
+            25 => (u64::from(u16::from_be_bytes(<[u8; 2]>::try_from(self.take(2)?).unwrap())), 0x100),
+            26 => (u64::from(u32::from_be_bytes(<[u8; 4]>::try_from(self.take(4)?).unwrap())), 0x1_0000),
+            27 => (u64::from_be_bytes(<[u8; 8]>::try_from(self.take(8)?).unwrap()), 0x1_0000_0000),
+            _ => return Err(DecodeError::Unsupported(byte)),
+        };
+        if n < min {
+            return Err(DecodeError::BadInteger);
+        }
+        Ok((major, n))
+    }
+
+    fn string(&mut self, len: u64) -> Result<String, DecodeError> {
+        let bytes = self.take(len)?;
+        std::str::from_utf8(bytes).map(str::to_string).map_err(|_| DecodeError::BadString)
+    }
+
+    fn value(&mut self, depth: usize) -> Result<Ipld, DecodeError> {
+        if depth > MAX_DEPTH {
+            return Err(DecodeError::TooDeep);
+        }
+        let first = *self.bytes.first().ok_or(DecodeError::UnexpectedEnd)?;
+        Ok(match self.head()? {
+            (0, n) => Ipld::Integer(i64::try_from(n).map_err(|_| DecodeError::BadInteger)?),
+            (1, n) => Ipld::Integer(-1 - i64::try_from(n).map_err(|_| DecodeError::BadInteger)?),
+            (2, len) => Ipld::Bytes(self.take(len)?.to_vec()),
+            (3, len) => Ipld::String(self.string(len)?),
+            (4, len) => {
+                // Every item is at least a byte, which bounds what a valid length can be.
+                if len > self.bytes.len() as u64 {
+                    return Err(DecodeError::UnexpectedEnd);
+                }
+                let mut list = Vec::with_capacity(len as usize);
+                for _ in 0..len {
+                    list.push(self.value(depth + 1)?);
+                }
+                Ipld::List(list)
+            },
+            (5, len) => {
+                let mut map = BTreeMap::new();
+                let mut last: Option<String> = None;
+                for _ in 0..len {
+                    let key = match self.head()? {
+                        (3, len) => self.string(len)?,
+                        _ => return Err(DecodeError::BadMap),
+                    };
+                    if let Some(last) = &last {
+                        if key_order(last, &key) != std::cmp::Ordering::Less {
+                            return Err(DecodeError::BadMap);
+                        }
+                    }
+                    let value = self.value(depth + 1)?;
+                    last = Some(key.clone());
+                    map.insert(key, value);
+                }
+                Ipld::Map(map)
+            },
+            (6, CID_TAG) => match self.head()? {
+                (2, len) => match self.take(len)? {
+                    [0, cid @ ..] => Ipld::Link(Cid::try_from(cid).map_err(|_| DecodeError::BadLink)?),
+                    _ => return Err(DecodeError::BadLink),
+                },
+                _ => return Err(DecodeError::BadLink),
+            },
+            (6, _) => return Err(DecodeError::BadLink),
+            (7, 20) => Ipld::Bool(false),
+            (7, 21) => Ipld::Bool(true),
+            (7, 22) => Ipld::Null,
+            _ => return Err(DecodeError::Unsupported(first)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn map(entries: Vec<(&str, Ipld)>) -> Ipld {
+        Ipld::Map(entries.into_iter().map(|(k, v)| (k.to_string(), v)).collect())
+    }
+
+    #[test]
+    fn encode_values() {
+        assert_eq!(encode(&Ipld::Null), vec![0xf6]);
+        assert_eq!(encode(&Ipld::Bool(true)), vec![0xf5]);
+        assert_eq!(encode(&Ipld::Integer(23)), vec![0x17]);
+        assert_eq!(encode(&Ipld::Integer(24)), vec![0x18, 24]);
+        assert_eq!(encode(&Ipld::Integer(1000)), vec![0x19, 0x03, 0xe8]);
+        assert_eq!(encode(&Ipld::Integer(-1)), vec![0x20]);
+        assert_eq!(encode(&Ipld::Integer(-1000)), vec![0x39, 0x03, 0xe7]);
+        assert_eq!(encode(&Ipld::Bytes(vec![1, 2])), vec![0x42, 1, 2]);
+        assert_eq!(encode(&Ipld::String("a".to_string())), vec![0x61, b'a']);
+        assert_eq!(encode(&Ipld::List(vec![Ipld::Null])), vec![0x81, 0xf6]);
+    }
+
+    #[test]
+    fn encode_sorted_keys() {
+        let value = map(vec![("bb", Ipld::Integer(1)), ("c", Ipld::Integer(2)), ("a", Ipld::Integer(3))]);
+        assert_eq!(encode(&value), vec![0xa3, 0x61, b'a', 3, 0x61, b'c', 2, 0x62, b'b', b'b', 1]);
+        assert_eq!(decode(&encode(&value)).unwrap(), value);
+    }
+
+    #[test]
+    fn encode_link() {
+        let link = cid(b"node");
+        let bytes = encode(&Ipld::Link(link.clone()));
+        assert_eq!(&bytes[..5], &[0xd8, 42, 0x58, 37, 0]);
+        assert_eq!(&bytes[5..], &link.to_bytes()[..]);
+        assert_eq!(decode(&bytes).unwrap(), Ipld::Link(link));
+    }
+
+    #[test]
+    fn decode_round_trip() {
+        let value = map(vec![
+            ("name", Ipld::String("hello".to_string())),
+            ("links", Ipld::List(vec![Ipld::Link(cid(b"a")), Ipld::Link(cid(b"b"))])),
+            ("size", Ipld::Integer(-70000)),
+            ("data", Ipld::Bytes(vec![0; 300])),
+            ("nested", map(vec![("ok", Ipld::Bool(false)), ("none", Ipld::Null)])),
+        ]);
+        assert_eq!(decode(&encode(&value)).unwrap(), value);
+    }
+
+    #[test]
+    fn decode_errors() {
+        assert_eq!(decode(&[]), Err(DecodeError::UnexpectedEnd));
+        assert_eq!(decode(&[0x18, 23]), Err(DecodeError::BadInteger));
+        assert_eq!(decode(&[0x19, 0, 1]), Err(DecodeError::BadInteger));
+        assert_eq!(decode(&[0x1b, 0x80, 0, 0, 0, 0, 0, 0, 0]), Err(DecodeError::BadInteger));
+        assert_eq!(decode(&[0x5f]), Err(DecodeError::Unsupported(0x5f)));
+        assert_eq!(decode(&[0xf9, 0, 0]), Err(DecodeError::Unsupported(0xf9)));
+        assert_eq!(decode(&[0x62, 0xff, 0xfe]), Err(DecodeError::BadString));
+        assert_eq!(decode(&[0xa2, 0x61, b'b', 1, 0x61, b'a', 2]), Err(DecodeError::BadMap));
+        assert_eq!(decode(&[0xa2, 0x61, b'a', 1, 0x61, b'a', 2]), Err(DecodeError::BadMap));
+        assert_eq!(decode(&[0xa1, 1, 1]), Err(DecodeError::BadMap));
+        assert_eq!(decode(&[0xc1, 1]), Err(DecodeError::BadLink));
+        assert_eq!(decode(&[0xd8, 42, 0x42, 1, 2]), Err(DecodeError::BadLink));
+        assert_eq!(decode(&[0x81; 100]), Err(DecodeError::TooDeep));
+        assert_eq!(decode(&[0xf6, 0xf6]), Err(DecodeError::TrailingBytes(1)));
+    }
+
+    proptest! {
+        #[test]
+        fn decode_arbitrary(bytes in proptest::collection::vec(any::<u8>(), 0..64)) {
+            if let Ok(value) = decode(&bytes) {
+                assert_eq!(encode(&value), bytes);
+            }
+        }
+    }
+}
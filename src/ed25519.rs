@@ -0,0 +1,186 @@
+//! Ed25519 keys, for deployers whose identities aren't secp256k1.
+//!
+//! Mirrors `keypair`, the secp256k1 keys, over `ed25519-dalek`. Secret keys are cleared from
+//! memory when they are dropped, and the buffers they are decoded from are zeroed once decoding
+//! succeeds.
+
+use std::convert::TryFrom;
+use std::fmt;
+
+use ed25519_dalek::Signer as _;
+use zeroize::Zeroize;
+
+use crate::keypair::DecodingError;
+
+/// An Ed25519 keypair.
+pub struct Keypair(ed25519_dalek::Keypair);
+
+impl Keypair {
+    /// Generate a new Ed25519 `Keypair`.
+    pub fn generate() -> Keypair {
+        Keypair(ed25519_dalek::Keypair::generate(&mut rand::thread_rng()))
+    }
+
+    /// Get the public key of this keypair.
+    pub fn public(&self) -> PublicKey {
+        PublicKey(self.0.public)
+    }
+
+    /// Get the secret key of this keypair.
+    pub fn secret(&self) -> SecretKey {
+        SecretKey::from_bytes(&mut self.0.secret.to_bytes())
+            .expect("ed25519::SecretKey::from_bytes(to_bytes(k)) != k")
+    }
+
+    /// Sign a message with this keypair, producing a 64-byte Ed25519 signature.
+    pub fn sign(&self, msg: &[u8]) -> Vec<u8> {
+        self.0.sign(msg).to_bytes().to_vec()
+    }
+}
+
+impl Clone for Keypair {
+    fn clone(&self) -> Keypair {
+        Keypair::from(self.secret())
+    }
+}
+
+impl fmt::Debug for Keypair {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Keypair").field("public", &self.public()).finish()
+    }
+}
+
+/// Promote an Ed25519 secret key into a keypair.
+impl From<SecretKey> for Keypair {
+    fn from(secret: SecretKey) -> Keypair {
+        let public = ed25519_dalek::PublicKey::from(&secret.0);
+        Keypair(ed25519_dalek::Keypair { secret: secret.0, public })
+    }
+}
+
+/// Demote an Ed25519 keypair into a secret key.
+impl From<Keypair> for SecretKey {
+    fn from(kp: Keypair) -> SecretKey {
+        SecretKey(kp.0.secret)
+    }
+}
+
+/// An Ed25519 secret key.
+pub struct SecretKey(ed25519_dalek::SecretKey);
+
+impl fmt::Debug for SecretKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "SecretKey")
+    }
+}
+
+impl Clone for SecretKey {
+    fn clone(&self) -> SecretKey {
+        SecretKey::from_bytes(&mut self.to_bytes())
+            .expect("ed25519::SecretKey::from_bytes(to_bytes(k)) != k")
+    }
+}
+
+impl SecretKey {
+    /// Generate a new Ed25519 secret key.
+    pub fn generate() -> SecretKey {
+        SecretKey(ed25519_dalek::SecretKey::generate(&mut rand::thread_rng()))
+    }
+
+    /// Create an Ed25519 secret key from a 32-byte slice, zeroing the slice on success.
+    pub fn from_bytes(mut sk: impl AsMut<[u8]>) -> Result<SecretKey, DecodingError> {
+        let sk_bytes = sk.as_mut();
+        let secret = ed25519_dalek::SecretKey::from_bytes(&*sk_bytes)
+            .map_err(|e| DecodingError::new("failed to parse ed25519 secret key").source(e))?;
+        sk_bytes.zeroize();
+        Ok(SecretKey(secret))
+    }
+
+    /// Returns the raw bytes of the secret key. Zeroing them is up to the caller.
+    pub fn to_bytes(&self) -> [u8; 32] {
+        self.0.to_bytes()
+    }
+}
+
+/// An Ed25519 public key.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct PublicKey(ed25519_dalek::PublicKey);
+
+impl PublicKey {
+    /// Verify the Ed25519 signature on a message using the public key. Only canonical
+    /// signatures, by keys that aren't of small order, are accepted.
+    pub fn verify(&self, msg: &[u8], sig: &[u8]) -> bool {
+        ed25519_dalek::Signature::try_from(sig)
+            .map(|s| self.0.verify_strict(msg, &s).is_ok())
+            .unwrap_or(false)
+    }
+
+    /// Encode the public key as its 32-byte compressed Edwards point.
+    pub fn encode(&self) -> [u8; 32] {
+        self.0.to_bytes()
+    }
+
+    /// Decode a public key from the bytes produced by `encode`.
+    pub fn decode(k: &[u8]) -> Result<PublicKey, DecodingError> {
+        ed25519_dalek::PublicKey::from_bytes(k)
+            .map_err(|e| DecodingError::new("failed to parse ed25519 public key").source(e))
+            .map(PublicKey)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Test 1 of RFC8032, section 7.1.
+    const SECRET: &str = "9d61b19deffd5a60ba844af492ec2cc44449c5697b326919703bac031cae7f60";
+    const PUBLIC: &str = "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a";
+    const SIGNATURE: &str = "e5564300c360ac729086e2cc806e828a84877f1eb8e5d974d873e065224901555fb8821590a33bacc61e39701cf9b46bd25bf5f0595bbe24655141438e7a100b";
+
+    #[test]
+    fn ed25519_vectors() {
+        let keypair = Keypair::from(SecretKey::from_bytes(hex::decode(SECRET).unwrap()).unwrap());
+        assert_eq!(hex::encode(keypair.public().encode()), PUBLIC);
+        assert_eq!(hex::encode(keypair.sign(b"")), SIGNATURE);
+        assert!(keypair.public().verify(b"", &hex::decode(SIGNATURE).unwrap()));
+    }
+
+    #[test]
+    fn ed25519_secret_from_bytes() {
+        let sk1 = SecretKey::generate();
+        let mut sk_bytes = sk1.to_bytes();
+        let sk2 = SecretKey::from_bytes(&mut sk_bytes).unwrap();
+        assert_eq!(sk1.to_bytes(), sk2.to_bytes());
+        assert_eq!(sk_bytes, [0; 32]);
+
+        let mut short = [1; 31];
+        assert!(SecretKey::from_bytes(&mut short).is_err());
+        assert_eq!(short, [1; 31]);
+    }
+
+    #[test]
+    fn ed25519_sign_verify() {
+        let keypair = Keypair::generate();
+        let signature = keypair.sign(b"hello");
+        assert_eq!(signature.len(), 64);
+        assert!(keypair.public().verify(b"hello", &signature));
+        assert!(!keypair.public().verify(b"hello!", &signature));
+        assert!(!Keypair::generate().public().verify(b"hello", &signature));
+        assert!(!keypair.public().verify(b"hello", &signature[1..]));
+        assert_eq!(keypair.clone().sign(b"hello"), signature);
+    }
+
+    #[test]
+    fn ed25519_public_key_decode() {
+        let public = Keypair::generate().public();
+        assert_eq!(PublicKey::decode(&public.encode()).unwrap(), public);
+        assert!(PublicKey::decode(&public.encode()[1..]).is_err());
+    }
+
+    #[test]
+    fn ed25519_debug_hides_secret() {
+        let keypair = Keypair::from(SecretKey::from_bytes(hex::decode(SECRET).unwrap()).unwrap());
+        assert_eq!(format!("{:?}", keypair.secret()), "SecretKey");
+        assert!(!format!("{:?}", keypair).contains(SECRET));
+    }
+}
@@ -1,237 +1,406 @@
+//! Secp256k1 keys, which deployers sign program manifests with.
+//!
 //! I wanted to implement our own version of it, but it's almost entirely a copy/paste from
-//! https://github.com/libp2p/rust-libp2p/blob/34e7e353104c1e1fced20ac39a7f86eaa473e94b/core/src/identity/secp256k1.rs
+//! <https://github.com/libp2p/rust-libp2p/blob/34e7e353104c1e1fced20ac39a7f86eaa473e94b/core/src/identity/secp256k1.rs>
 //! I just didn't want ot be tied into the rest of libp2p just yet.
 //!
+//! Secret keys are cleared from memory when they are dropped, and the buffers they are decoded
+//! from are zeroed once decoding succeeds.
+
+use asn1_der::typed::{ DerDecodable, Sequence };
+use rand::RngCore;
+use sha2::{Digest as ShaDigestTrait, Sha256};
+use secp256k1::{Message, Signature};
+use zeroize::Zeroize;
+
+use std::error::Error;
+use std::fmt;
+
+/// An error during decoding of key material.
+#[derive(Debug)]
+pub struct DecodingError {
+    msg: String,
+    source: Option<Box<dyn Error + Send + Sync>>
+}
+
+impl DecodingError {
+    pub(crate) fn new<S: ToString>(msg: S) -> Self {
+        Self { msg: msg.to_string(), source: None }
+    }
+
+    pub(crate) fn source(self, source: impl Error + Send + Sync + 'static) -> Self {
+        Self { source: Some(Box::new(source)), .. self }
+    }
+}
+
+impl fmt::Display for DecodingError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Key decoding error: {}", self.msg)
+    }
+}
+
+impl Error for DecodingError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        self.source.as_ref().map(|s| &**s as &dyn Error)
+    }
+}
+
+/// An error during signing of a message.
+#[derive(Debug)]
+pub struct SigningError {
+    msg: String,
+    source: Option<Box<dyn Error + Send + Sync>>
+}
+
+/// An error during encoding of key material.
+impl SigningError {
+    pub(crate) fn new<S: ToString>(msg: S) -> Self {
+        Self { msg: msg.to_string(), source: None }
+    }
+}
+
+impl fmt::Display for SigningError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Key signing error: {}", self.msg)
+    }
+}
+
+impl Error for SigningError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        self.source.as_ref().map(|s| &**s as &dyn Error)
+    }
+}
+
+/// The `parameters` field of a secp256k1 ECPrivateKey: the curve's object identifier,
+/// 1.3.132.0.10.
+const SECP256K1_OID: &[u8] = &[0x06, 0x05, 0x2b, 0x81, 0x04, 0x00, 0x0a];
+
+/// A Secp256k1 keypair.
+#[derive(Clone)]
+pub struct Keypair {
+    secret: SecretKey,
+    public: PublicKey
+}
+
+impl Keypair {
+    /// Generate a new sec256k1 `Keypair`.
+    pub fn generate() -> Keypair {
+        Keypair::from(SecretKey::generate())
+    }
+
+    /// Get the public key of this keypair.
+    pub fn public(&self) -> &PublicKey {
+        &self.public
+    }
+
+    /// Get the secret key of this keypair.
+    pub fn secret(&self) -> &SecretKey {
+        &self.secret
+    }
+}
+
+impl fmt::Debug for Keypair {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Keypair").field("public", &self.public).finish()
+    }
+}
+
+/// Promote a Secp256k1 secret key into a keypair.
+impl From<SecretKey> for Keypair {
+    fn from(secret: SecretKey) -> Keypair {
+        let public = PublicKey(secp256k1::PublicKey::from_secret_key(&secret.0));
+        Keypair { secret, public }
+    }
+}
+
+/// Demote a Secp256k1 keypair into a secret key.
+impl From<Keypair> for SecretKey {
+    fn from(kp: Keypair) -> SecretKey {
+        kp.secret
+    }
+}
+
+/// A Secp256k1 secret key.
+#[derive(Clone)]
+pub struct SecretKey(secp256k1::SecretKey);
+
+impl fmt::Debug for SecretKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "SecretKey")
+    }
+}
+
+impl SecretKey {
+    /// Generate a new Secp256k1 secret key.
+    pub fn generate() -> SecretKey {
+        let mut r = rand::thread_rng();
+        let mut b = [0; secp256k1::util::SECRET_KEY_SIZE];
+        // This is how it is done in `secp256k1::SecretKey::random` which
+        // we do not use here because it uses `rand::Rng` from rand-0.4.
+        loop {
+            r.fill_bytes(&mut b);
+            if let Ok(k) = secp256k1::SecretKey::parse(&b) {
+                return SecretKey(k)
+            }
+        }
+    }
+
+    /// Create a secret key from a byte slice, zeroing the slice on success.
+    /// If the bytes do not constitute a valid Secp256k1 secret key, an
+    /// error is returned.
+    pub fn from_bytes(mut sk: impl AsMut<[u8]>) -> Result<SecretKey, DecodingError> {
+        let sk_bytes = sk.as_mut();
+        let secret = secp256k1::SecretKey::parse_slice(&*sk_bytes)
+            .map_err(|_| DecodingError::new("failed to parse secp256k1 secret key"))?;
+        sk_bytes.zeroize();
+        Ok(SecretKey(secret))
+    }
+
+    /// Decode a DER-encoded Secp256k1 secret key in an ECPrivateKey
+    /// structure as defined in [RFC5915], zeroing the slice on success.
+    ///
+    /// [RFC5915]: https://tools.ietf.org/html/rfc5915
+    pub fn from_der(mut der: impl AsMut<[u8]>) -> Result<SecretKey, DecodingError> {
+        let der_obj = der.as_mut();
+        let sk = {
+            let obj = Sequence::decode(&*der_obj)
+                .map_err(|e| DecodingError::new("Secp256k1 DER ECPrivateKey").source(e))?;
+            let version: u8 = obj.get_as(0)
+                .map_err(|e| DecodingError::new("Secp256k1 DER ECPrivateKey version").source(e))?;
+            if version != 1 {
+                return Err(DecodingError::new("unsupported ECPrivateKey version"));
+            }
+            let mut sk_bytes: Vec<u8> = obj.get_as(1)
+                .map_err(|e| DecodingError::new("Secp256k1 DER ECPrivateKey privateKey").source(e))?;
+            let sk = SecretKey::from_bytes(&mut sk_bytes);
+            sk_bytes.zeroize();
+            // The optional `parameters` must name the secp256k1 curve.
+            for i in 2..obj.len() {
+                let field = obj.get(i).map_err(|e| DecodingError::new("Secp256k1 DER ECPrivateKey").source(e))?;
+                if field.tag() == 0xa0 && field.value() != SECP256K1_OID {
+                    return Err(DecodingError::new("ECPrivateKey isn't a secp256k1 key"));
+                }
+            }
+            sk?
+        };
+        der_obj.zeroize();
+        Ok(sk)
+    }
+
+    /// Sign a message with this secret key, producing a DER-encoded
+    /// ECDSA signature, as defined in [RFC3278].
+    ///
+    /// [RFC3278]: https://tools.ietf.org/html/rfc3278#section-8.2
+    pub fn sign(&self, msg: &[u8]) -> Result<Vec<u8>, SigningError> {
+        self.sign_hash(Sha256::digest(msg).as_ref())
+    }
+
+    /// Returns the raw bytes of the secret key. Zeroing them is up to the caller.
+    pub fn to_bytes(&self) -> [u8; 32] {
+        self.0.serialize()
+    }
+
+    /// Sign a raw message of length 256 bits with this secret key, produces a DER-encoded
+    /// ECDSA signature.
+    pub fn sign_hash(&self, msg: &[u8]) -> Result<Vec<u8>, SigningError> {
+        let m = Message::parse_slice(msg)
+            .map_err(|_| SigningError::new("failed to parse secp256k1 digest"))?;
+        Ok(secp256k1::sign(&m, &self.0).0.serialize_der().as_ref().into())
+    }
+}
+
+/// A Secp256k1 public key.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct PublicKey(secp256k1::PublicKey);
+
+impl PublicKey {
+    /// Verify the Secp256k1 signature on a message using the public key.
+    pub fn verify(&self, msg: &[u8], sig: &[u8]) -> bool {
+        self.verify_hash(Sha256::digest(msg).as_ref(), sig)
+    }
+
+    /// Verify the Secp256k1 DER-encoded signature on a raw 256-bit message using the public key.
+    /// Signatures with a high `s` are rejected, as anyone could derive them from the low-`s`
+    /// ones `sign_hash` makes, changing the CIDs of whatever they sign.
+    pub fn verify_hash(&self, msg: &[u8], sig: &[u8]) -> bool {
+        Message::parse_slice(msg)
+            .and_then(|m| Signature::parse_der(sig).map(|s| !s.s.is_high() && secp256k1::verify(&m, &s, &self.0)))
+            .unwrap_or(false)
+    }
+
+    /// Encode the public key in compressed form, i.e. with one coordinate
+    /// represented by a single bit.
+    pub fn encode(&self) -> [u8; 33] {
+        self.0.serialize_compressed()
+    }
+
+    /// Encode the public key in uncompressed form.
+    pub fn encode_uncompressed(&self) -> [u8; 65] {
+        self.0.serialize()
+    }
+
+    /// Decode a public key from a byte slice in either of the formats produced
+    /// by `encode` and `encode_uncompressed`.
+    pub fn decode(k: &[u8]) -> Result<PublicKey, DecodingError> {
+        let format = match k.len() {
+            33 => secp256k1::PublicKeyFormat::Compressed,
+            65 => secp256k1::PublicKeyFormat::Full,
+            _ => return Err(DecodingError::new("invalid secp256k1 public key length")),
+        };
+        secp256k1::PublicKey::parse_slice(k, Some(format))
+            .map_err(|_| DecodingError::new("failed to parse secp256k1 public key"))
+            .map(PublicKey)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The secret key 1, whose public key is the generator of the curve.
+    const ONE: [u8; 32] = [
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1,
+    ];
+
+    /// The secret key 1 in an ECPrivateKey structure, with the secp256k1 OID as parameters.
+    fn one_der() -> Vec<u8> {
+        let mut der = vec![0x30, 0x2e, 0x02, 0x01, 0x01, 0x04, 0x20];
+        der.extend(&ONE);
+        der.extend(&[0xa0, 0x07]);
+        der.extend(SECP256K1_OID);
+        der
+    }
+
+    #[test]
+    fn secp256k1_secret_from_bytes() {
+        let sk1 = SecretKey::generate();
+        let mut sk_bytes = [0; 32];
+        sk_bytes.copy_from_slice(&sk1.0.serialize()[..]);
+        let sk2 = SecretKey::from_bytes(&mut sk_bytes).unwrap();
+        assert_eq!(sk1.0.serialize(), sk2.0.serialize());
+        assert_eq!(sk_bytes, [0; 32]);
+    }
+
+    #[test]
+    fn secp256k1_secret_from_invalid_bytes() {
+        let mut zero = [0; 32];
+        assert!(SecretKey::from_bytes(&mut zero).is_err());
+        let mut order = hex::decode("fffffffffffffffffffffffffffffffebaaedce6af48a03bbfd25e8cd0364141").unwrap();
+        assert!(SecretKey::from_bytes(&mut order).is_err());
+        let mut short = [1; 31];
+        assert!(SecretKey::from_bytes(&mut short).is_err());
+        assert_eq!(short, [1; 31]);
+    }
+
+    #[test]
+    fn secp256k1_public_key_vectors() {
+        let keypair = Keypair::from(SecretKey::from_bytes(ONE).unwrap());
+        assert_eq!(
+            hex::encode(&keypair.public().encode()[..]),
+            "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798"
+        );
+        assert_eq!(
+            hex::encode(&keypair.public().encode_uncompressed()[..]),
+            "0479be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798\
+             483ada7726a3c4655da4fbfc0e1108a8fd17b448a68554199c47d08ffb10d4b8"
+        );
+
+        let mut two = ONE;
+        two[31] = 2;
+        let keypair = Keypair::from(SecretKey::from_bytes(two).unwrap());
+        assert_eq!(
+            hex::encode(&keypair.public().encode()[..]),
+            "02c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5"
+        );
+    }
+
+    #[test]
+    fn secp256k1_public_key_formats() {
+        let keypair = Keypair::generate();
+        let public = keypair.public();
+        assert_eq!(&PublicKey::decode(&public.encode()).unwrap(), public);
+        assert_eq!(&PublicKey::decode(&public.encode_uncompressed()).unwrap(), public);
+        assert!(PublicKey::decode(&public.encode()[1..]).is_err());
+        let mut bad = public.encode();
+        bad[0] = 0x05;
+        assert!(PublicKey::decode(&bad).is_err());
+    }
+
+    #[test]
+    fn secp256k1_signature_vectors() {
+        // RFC6979 deterministic signatures, with the low `s` of Bitcoin.
+        let secret = SecretKey::from_bytes(ONE).unwrap();
+        let signature = secret.sign(b"Satoshi Nakamoto").unwrap();
+        assert_eq!(
+            hex::encode(&signature),
+            "3045\
+             022100934b1ea10a4b3c1757e2b0c017d0b6143ce3c9a7e6a4a49860d7a6ab210ee3d8\
+             02202442ce9d2b916064108014783e923ec36b49743e2ffa1c4496f01a512aafd9e5"
+        );
+        assert_eq!(signature, secret.sign(b"Satoshi Nakamoto").unwrap());
+    }
+
+    #[test]
+    fn secp256k1_sign_verify() {
+        let keypair = Keypair::generate();
+        let signature = keypair.secret().sign(b"hello").unwrap();
+        assert!(keypair.public().verify(b"hello", &signature));
+        assert!(!keypair.public().verify(b"hello!", &signature));
+        assert!(!Keypair::generate().public().verify(b"hello", &signature));
+        assert!(!keypair.public().verify(b"hello", &signature[1..]));
+
+        let digest = Sha256::digest(b"hello");
+        assert!(keypair.public().verify_hash(digest.as_ref(), &signature));
+        assert!(keypair.secret().sign_hash(b"too short").is_err());
+
+        // The same signature with `s` negated.
+        let mut high = Signature::parse_der(&signature).unwrap();
+        assert!(!high.s.is_high());
+        high.s = -high.s;
+        assert!(!keypair.public().verify(b"hello", high.serialize_der().as_ref()));
+        high.normalize_s();
+        assert!(keypair.public().verify(b"hello", high.serialize_der().as_ref()));
+    }
+
+    #[test]
+    fn secp256k1_secret_from_der() {
+        let mut der = one_der();
+        let secret = SecretKey::from_der(&mut der).unwrap();
+        assert_eq!(secret.to_bytes(), ONE);
+        assert!(der.iter().all(|b| *b == 0));
+
+        // Without the optional parameters.
+        let mut der = one_der();
+        der.truncate(39);
+        der[1] = 0x25;
+        assert_eq!(SecretKey::from_der(&mut der).unwrap().to_bytes(), ONE);
+    }
+
+    #[test]
+    fn secp256k1_secret_from_invalid_der() {
+        let mut version = one_der();
+        version[4] = 2;
+        assert!(SecretKey::from_der(&mut version).is_err());
+        assert_eq!(version[7..39], ONE);
+
+        // The P-256 curve, 1.2.840.10045.3.1.7.
+        let mut curve = one_der();
+        curve.truncate(39);
+        curve.extend(&[0xa0, 0x0a, 0x06, 0x08, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07]);
+        curve[1] = 0x31;
+        assert!(SecretKey::from_der(&mut curve).is_err());
+
+        let mut truncated = one_der();
+        truncated.pop();
+        assert!(SecretKey::from_der(&mut truncated).is_err());
+        assert!(SecretKey::from_der(&mut [0x30, 0x00]).is_err());
+    }
 
-// use asn1_der::{DerObject};
-// use rand::RngCore;
-// use sha2::{Digest as ShaDigestTrait, Sha256};
-// use secp256k1::{Message, Signature};
-// use zeroize::Zeroize;
-// 
-// use std::error::Error;
-// use std::fmt;
-// 
-// /// An error during decoding of key material.
-// #[derive(Debug)]
-// pub struct DecodingError {
-//     msg: String,
-//     source: Option<Box<dyn Error + Send + Sync>>
-// }
-// 
-// impl DecodingError {
-//     pub(crate) fn new<S: ToString>(msg: S) -> Self {
-//         Self { msg: msg.to_string(), source: None }
-//     }
-// 
-//     pub(crate) fn source(self, source: impl Error + Send + Sync + 'static) -> Self {
-//         Self { source: Some(Box::new(source)), .. self }
-//     }
-// }
-// 
-// impl fmt::Display for DecodingError {
-//     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-//         write!(f, "Key decoding error: {}", self.msg)
-//     }
-// }
-// 
-// impl Error for DecodingError {
-//     fn source(&self) -> Option<&(dyn Error + 'static)> {
-//         self.source.as_ref().map(|s| &**s as &dyn Error)
-//     }
-// }
-// 
-// /// An error during signing of a message.
-// #[derive(Debug)]
-// pub struct SigningError {
-//     msg: String,
-//     source: Option<Box<dyn Error + Send + Sync>>
-// }
-// 
-// /// An error during encoding of key material.
-// impl SigningError {
-//     pub(crate) fn new<S: ToString>(msg: S) -> Self {
-//         Self { msg: msg.to_string(), source: None }
-//     }
-// 
-//     pub(crate) fn source(self, source: impl Error + Send + Sync + 'static) -> Self {
-//         Self { source: Some(Box::new(source)), .. self }
-//     }
-// }
-// 
-// impl fmt::Display for SigningError {
-//     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-//         write!(f, "Key signing error: {}", self.msg)
-//     }
-// }
-// 
-// impl Error for SigningError {
-//     fn source(&self) -> Option<&(dyn Error + 'static)> {
-//         self.source.as_ref().map(|s| &**s as &dyn Error)
-//     }
-// }
-// 
-// /// A Secp256k1 keypair.
-// #[derive(Clone)]
-// pub struct Keypair {
-//     secret: SecretKey,
-//     public: PublicKey
-// }
-// 
-// impl Keypair {
-//     /// Generate a new sec256k1 `Keypair`.
-//     pub fn generate() -> Keypair {
-//         Keypair::from(SecretKey::generate())
-//     }
-// 
-//     /// Get the public key of this keypair.
-//     pub fn public(&self) -> &PublicKey {
-//         &self.public
-//     }
-// 
-//     /// Get the secret key of this keypair.
-//     pub fn secret(&self) -> &SecretKey {
-//         &self.secret
-//     }
-// }
-// 
-// /// Promote a Secp256k1 secret key into a keypair.
-// impl From<SecretKey> for Keypair {
-//     fn from(secret: SecretKey) -> Keypair {
-//         let public = PublicKey(secp256k1::PublicKey::from_secret_key(&secret.0));
-//         Keypair { secret, public }
-//     }
-// }
-// 
-// /// Demote a Secp256k1 keypair into a secret key.
-// impl From<Keypair> for SecretKey {
-//     fn from(kp: Keypair) -> SecretKey {
-//         kp.secret
-//     }
-// }
-// 
-// /// A Secp256k1 secret key.
-// #[derive(Clone)]
-// pub struct SecretKey(secp256k1::SecretKey);
-// 
-// impl SecretKey {
-//     /// Generate a new Secp256k1 secret key.
-//     pub fn generate() -> SecretKey {
-//         let mut r = rand::thread_rng();
-//         let mut b = [0; secp256k1::util::SECRET_KEY_SIZE];
-//         // This is how it is done in `secp256k1::SecretKey::random` which
-//         // we do not use here because it uses `rand::Rng` from rand-0.4.
-//         loop {
-//             r.fill_bytes(&mut b);
-//             if let Ok(k) = secp256k1::SecretKey::parse(&b) {
-//                 return SecretKey(k)
-//             }
-//         }
-//     }
-// 
-//     /// Create a secret key from a byte slice, zeroing the slice on success.
-//     /// If the bytes do not constitute a valid Secp256k1 secret key, an
-//     /// error is returned.
-//     pub fn from_bytes(mut sk: impl AsMut<[u8]>) -> Result<SecretKey, DecodingError> {
-//         let sk_bytes = sk.as_mut();
-//         let secret = secp256k1::SecretKey::parse_slice(&*sk_bytes)
-//             .map_err(|_| DecodingError::new("failed to parse secp256k1 secret key"))?;
-//         sk_bytes.zeroize();
-//         Ok(SecretKey(secret))
-//     }
-// 
-//     /// Decode a DER-encoded Secp256k1 secret key in an ECPrivateKey
-//     /// structure as defined in [RFC5915].
-//     ///
-//     /// [RFC5915]: https://tools.ietf.org/html/rfc5915
-//     pub fn from_der(mut der: impl AsMut<[u8]>) -> Result<SecretKey, DecodingError> {
-//         // TODO: Stricter parsing.
-//         let der_obj = der.as_mut();
-//         let obj: Vec<DerObject> = FromDerObject::deserialize((&*der_obj).iter())
-//             .map_err(|e| DecodingError::new("Secp256k1 DER ECPrivateKey").source(e))?;
-//         der_obj.zeroize();
-//         let sk_obj = obj.into_iter().nth(1)
-//             .ok_or_else(|| DecodingError::new("Not enough elements in DER"))?;
-//         let mut sk_bytes: Vec<u8> = FromDerObject::from_der_object(sk_obj)
-//             .map_err(DecodingError::new)?;
-//         let sk = SecretKey::from_bytes(&mut sk_bytes)?;
-//         sk_bytes.zeroize();
-//         Ok(sk)
-//     }
-// 
-//     /// Sign a message with this secret key, producing a DER-encoded
-//     /// ECDSA signature, as defined in [RFC3278].
-//     ///
-//     /// [RFC3278]: https://tools.ietf.org/html/rfc3278#section-8.2
-//     pub fn sign(&self, msg: &[u8]) -> Result<Vec<u8>, SigningError> {
-//         self.sign_hash(Sha256::digest(msg).as_ref())
-//     }
-// 
-//     /// Returns the raw bytes of the secret key.
-//     pub fn to_bytes(&self) -> [u8; 32] {
-//         self.0.serialize()
-//     }
-// 
-// 
-//     /// Sign a raw message of length 256 bits with this secret key, produces a DER-encoded
-//     /// ECDSA signature.
-//     pub fn sign_hash(&self, msg: &[u8]) -> Result<Vec<u8>, SigningError> {
-//         let m = Message::parse_slice(msg)
-//             .map_err(|_| SigningError::new("failed to parse secp256k1 digest"))?;
-//         secp256k1::sign(&m, &self.0)
-//             .map(|s| s.0.serialize_der().as_ref().into())
-//             .map_err(|_| SigningError::new("failed to create secp256k1 signature"))
-//     }
-// }
-// 
-// /// A Secp256k1 public key.
-// #[derive(PartialEq, Eq, Clone, Debug)]
-// pub struct PublicKey(secp256k1::PublicKey);
-// 
-// impl PublicKey {
-//     /// Verify the Secp256k1 signature on a message using the public key.
-//     pub fn verify(&self, msg: &[u8], sig: &[u8]) -> bool {
-//         self.verify_hash(Sha256::digest(msg).as_ref(), sig)
-//     }
-// 
-//     /// Verify the Secp256k1 DER-encoded signature on a raw 256-bit message using the public key.
-//     pub fn verify_hash(&self, msg: &[u8], sig: &[u8]) -> bool {
-//         Message::parse_slice(msg)
-//             .and_then(|m| Signature::parse_der(sig).map(|s| secp256k1::verify(&m, &s, &self.0)))
-//             .unwrap_or(false)
-//     }
-// 
-//     /// Encode the public key in compressed form, i.e. with one coordinate
-//     /// represented by a single bit.
-//     pub fn encode(&self) -> [u8; 33] {
-//         self.0.serialize_compressed()
-//     }
-// 
-//     /// Encode the public key in uncompressed form.
-//     pub fn encode_uncompressed(&self) -> [u8; 65] {
-//         self.0.serialize()
-//     }
-// 
-//     /// Decode a public key from a byte slice in the the format produced
-//     /// by `encode`.
-//     pub fn decode(k: &[u8]) -> Result<PublicKey, DecodingError> {
-//         secp256k1::PublicKey::parse_slice(k, Some(secp256k1::PublicKeyFormat::Compressed))
-//             .map_err(|_| DecodingError::new("failed to parse secp256k1 public key"))
-//             .map(PublicKey)
-//     }
-// }
-// 
-// #[cfg(test)]
-// mod tests {
-//     use super::*;
-// 
-//     #[test]
-//     fn secp256k1_secret_from_bytes() {
-//         let sk1 = SecretKey::generate();
-//         let mut sk_bytes = [0; 32];
-//         sk_bytes.copy_from_slice(&sk1.0.serialize()[..]);
-//         let sk2 = SecretKey::from_bytes(&mut sk_bytes).unwrap();
-//         assert_eq!(sk1.0.serialize(), sk2.0.serialize());
-//         assert_eq!(sk_bytes, [0; 32]);
-//     }
-// }
+    #[test]
+    fn secp256k1_debug_hides_secret() {
+        let keypair = Keypair::from(SecretKey::from_bytes(ONE).unwrap());
+        assert_eq!(format!("{:?}", keypair.secret()), "SecretKey");
+        assert!(!format!("{:?}", keypair).contains("SecretKey"));
+    }
+}
@@ -0,0 +1,507 @@
+//! A file-backed store of named deployer identities.
+//!
+//! The store is a JSON file mapping names to keypairs. Public keys are stored in the clear, so
+//! the keys of an identity, its `Creator` and its keys `Address` can be read without a
+//! passphrase. Secret keys are encrypted with XChaCha20-Poly1305, under a key derived from a
+//! passphrase with scrypt:
+//!
+//! ```text
+//! {
+//!   "version": 1,
+//!   "keys": {
+//!     "alice": {
+//!       "publicKey": 'ed01b5c7ab11f8e1d2...',
+//!       "kdf": { "salt": '9f1c...', "logN": 15, "r": 8, "p": 1 },
+//!       "nonce": '4a6f...',
+//!       "ciphertext": '0c3e...'
+//!     }
+//!   }
+//! }
+//! ```
+//!
+//! The ciphertext is authenticated together with the name and public key of the identity, so an
+//! entry can't be renamed or have its public key swapped without the passphrase.
+
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::path::{ Path, PathBuf };
+
+use chacha20poly1305::aead::{ Aead, NewAead, Payload };
+use chacha20poly1305::{ Key, XChaCha20Poly1305, XNonce };
+use rand::RngCore;
+use serde_json::{ json, Map, Value };
+use zeroize::Zeroize;
+
+use crate::keypair::{ DecodingError, SigningError };
+use crate::manifest::{ Address, Creator };
+use crate::prelude::*;
+use crate::signing::{ Keypair, PublicKey, Scheme, Signer };
+
+/// The version of the file format.
+pub const VERSION: u64 = 1;
+
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+
+/// The most memory, 128·r·N bytes, the scrypt parameters of a keystore file may ask for: 256 MiB,
+/// eight times the default.
+const MAX_MEMORY: u128 = 256 << 20;
+/// The most work, N·r·p, the scrypt parameters of a keystore file may ask for: sixteen times the
+/// default, a few seconds.
+const MAX_WORK: u128 = 1 << 22;
+
+/// Why a keystore operation failed.
+#[derive(Debug)]
+pub enum KeystoreError {
+    /// The keystore file couldn't be read or written.
+    Io(io::Error),
+    /// The keystore file isn't a keystore, or is of an unsupported version.
+    Format(String),
+    /// There is no identity with the name.
+    NotFound(String),
+    /// There already is an identity with the name.
+    Exists(String),
+    /// The passphrase is wrong, or the entry was tampered with.
+    BadPassphrase,
+    /// The key material of an entry is invalid.
+    Key(DecodingError),
+    /// The identity couldn't sign.
+    Signing(SigningError),
+}
+
+impl Display for KeystoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeystoreError::Io(e) => write!(f, "keystore I/O error: {}", e),
+            KeystoreError::Format(e) => write!(f, "invalid keystore: {}", e),
+            KeystoreError::NotFound(name) => write!(f, "no identity named `{}`", name),
+            KeystoreError::Exists(name) => write!(f, "an identity named `{}` already exists", name),
+            KeystoreError::BadPassphrase => write!(f, "wrong passphrase"),
+            KeystoreError::Key(e) => write!(f, "{}", e),
+            KeystoreError::Signing(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl Error for KeystoreError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            KeystoreError::Io(e) => Some(e),
+            KeystoreError::Key(e) => Some(e),
+            KeystoreError::Signing(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for KeystoreError {
+    fn from(e: io::Error) -> KeystoreError {
+        KeystoreError::Io(e)
+    }
+}
+
+impl From<SigningError> for KeystoreError {
+    fn from(e: SigningError) -> KeystoreError {
+        KeystoreError::Signing(e)
+    }
+}
+
+/// The cost of deriving an encryption key from a passphrase with scrypt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KdfParams {
+    /// The log2 of the CPU/memory cost.
+    pub log_n: u8,
+    /// The block size.
+    pub r: u32,
+    /// The parallelization.
+    pub p: u32,
+}
+
+impl KdfParams {
+    /// Whether deriving a key takes at most `MAX_MEMORY` bytes of memory and `MAX_WORK` work.
+    fn is_affordable(&self) -> bool {
+        if self.log_n >= 64 {
+            return false;
+        }
+        let (n, r, p) = (1u128 << self.log_n, u128::from(self.r), u128::from(self.p));
+        128 * r * n <= MAX_MEMORY && n * r * p <= MAX_WORK
+    }
+}
+
+/// The parameters recommended for interactive logins: about 32 MiB of memory.
+impl Default for KdfParams {
+    fn default() -> KdfParams {
+        KdfParams { log_n: 15, r: 8, p: 1 }
+    }
+}
+
+/// A stored identity.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Entry {
+    public_key: PublicKey,
+    kdf: KdfParams,
+    salt: Vec<u8>,
+    nonce: Vec<u8>,
+    ciphertext: Vec<u8>,
+}
+
+/// A file-backed store of named, passphrase-encrypted keypairs.
+#[derive(Debug)]
+pub struct Keystore {
+    path: PathBuf,
+    kdf: KdfParams,
+    entries: BTreeMap<String, Entry>,
+}
+
+impl Keystore {
+    /// Opens the keystore at `path`, which is empty if the file doesn't exist yet. Nothing is
+    /// written until `save`.
+    pub fn open(path: impl AsRef<Path>) -> Result<Keystore, KeystoreError> {
+        let path = path.as_ref().to_path_buf();
+        let entries = match fs::read_to_string(&path) {
+            Ok(json) => parse(&json)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Keystore { path, kdf: KdfParams::default(), entries })
+    }
+
+    /// Uses `kdf` to encrypt the identities added from now on.
+    pub fn with_kdf(mut self, kdf: KdfParams) -> Keystore {
+        self.kdf = kdf;
+        self
+    }
+
+    /// The path of the keystore file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The names of the identities, in order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.entries.keys().map(|name| name.as_str())
+    }
+
+    /// Whether there is an identity named `name`.
+    pub fn contains(&self, name: &str) -> bool {
+        self.entries.contains_key(name)
+    }
+
+    /// Generates a keypair of `scheme` and stores it as `name`, encrypted with `passphrase`.
+    pub fn generate(&mut self, name: &str, scheme: Scheme, passphrase: &str) -> Result<PublicKey, KeystoreError> {
+        let keypair = Keypair::generate(scheme);
+        self.insert(name, &keypair, passphrase)?;
+        Ok(keypair.public_key())
+    }
+
+    /// Stores `keypair` as `name`, encrypted with `passphrase`.
+    pub fn insert(&mut self, name: &str, keypair: &Keypair, passphrase: &str) -> Result<(), KeystoreError> {
+        if self.entries.contains_key(name) {
+            return Err(KeystoreError::Exists(name.to_string()));
+        }
+        let public_key = keypair.public_key();
+        let mut salt = vec![0; SALT_LEN];
+        let mut nonce = vec![0; NONCE_LEN];
+        rand::thread_rng().fill_bytes(&mut salt);
+        rand::thread_rng().fill_bytes(&mut nonce);
+
+        let mut key = derive_key(passphrase, &salt, self.kdf)?;
+        let mut secret = keypair.secret_to_bytes();
+        let aad = associated_data(name, &public_key);
+        let ciphertext = XChaCha20Poly1305::new(&Key::from(key))
+            .encrypt(&xnonce(&nonce), Payload { msg: &secret, aad: &aad })
+            .expect("a secret key is well within the XChaCha20-Poly1305 message size limit");
+        key.zeroize();
+        secret.zeroize();
+
+        let entry = Entry { public_key, kdf: self.kdf, salt, nonce, ciphertext };
+        self.entries.insert(name.to_string(), entry);
+        Ok(())
+    }
+
+    /// Removes the identity `name`.
+    pub fn remove(&mut self, name: &str) -> Result<(), KeystoreError> {
+        self.entries.remove(name).map(|_| ()).ok_or_else(|| KeystoreError::NotFound(name.to_string()))
+    }
+
+    /// The public key of the identity `name`.
+    pub fn public_key(&self, name: &str) -> Result<&PublicKey, KeystoreError> {
+        Ok(&self.entry(name)?.public_key)
+    }
+
+    /// The creator a manifest signed by `name` has.
+    pub fn creator(&self, name: &str) -> Result<Creator, KeystoreError> {
+        Ok(Creator::of(self.public_key(name)?))
+    }
+
+    /// The address of the keys of `name`, as manifests deployed by `name` have it.
+    pub fn address(&self, name: &str) -> Result<Address, KeystoreError> {
+        Ok(Address::new(self.creator(name)?.id()))
+    }
+
+    /// Decrypts the keypair of `name` with `passphrase`.
+    pub fn unlock(&self, name: &str, passphrase: &str) -> Result<Keypair, KeystoreError> {
+        let entry = self.entry(name)?;
+        let mut key = derive_key(passphrase, &entry.salt, entry.kdf)?;
+        let aad = associated_data(name, &entry.public_key);
+        let secret = XChaCha20Poly1305::new(&Key::from(key))
+            .decrypt(&xnonce(&entry.nonce), Payload { msg: &entry.ciphertext, aad: &aad });
+        key.zeroize();
+        let mut secret = secret.map_err(|_| KeystoreError::BadPassphrase)?;
+
+        let keypair = Keypair::from_secret_bytes(entry.public_key.scheme(), &mut secret);
+        secret.zeroize();
+        let keypair = keypair.map_err(KeystoreError::Key)?;
+        if keypair.public_key() != entry.public_key {
+            return Err(KeystoreError::Key(DecodingError::new("secret key doesn't match the public key")));
+        }
+        Ok(keypair)
+    }
+
+    /// Writes the keystore to its file, replacing it at once so a crash never leaves a partial
+    /// file. On Unix, the file is only readable by its owner.
+    pub fn save(&self) -> Result<(), KeystoreError> {
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(".tmp");
+        let tmp = PathBuf::from(tmp);
+
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        {
+            use std::io::Write;
+            let mut file = options.open(&tmp)?;
+            file.write_all(self.to_json().as_bytes())?;
+            file.sync_all()?;
+        }
+        fs::rename(&tmp, &self.path)?;
+        Ok(())
+    }
+
+    fn entry(&self, name: &str) -> Result<&Entry, KeystoreError> {
+        self.entries.get(name).ok_or_else(|| KeystoreError::NotFound(name.to_string()))
+    }
+
+    fn to_json(&self) -> String {
+        let keys: Map<String, Value> = self.entries.iter().map(|(name, entry)| {
+            let value = json!({
+                "publicKey": hex::encode(entry.public_key.encode()),
+                "kdf": {
+                    "salt": hex::encode(&entry.salt),
+                    "logN": entry.kdf.log_n,
+                    "r": entry.kdf.r,
+                    "p": entry.kdf.p,
+                },
+                "nonce": hex::encode(&entry.nonce),
+                "ciphertext": hex::encode(&entry.ciphertext),
+            });
+            (name.clone(), value)
+        }).collect();
+        json!({ "version": VERSION, "keys": keys }).to_string()
+    }
+}
+
+/// Reads the entries of a keystore file.
+fn parse(json: &str) -> Result<BTreeMap<String, Entry>, KeystoreError> {
+    let format = |e: &str| KeystoreError::Format(e.to_string());
+    let value: Value = serde_json::from_str(json).map_err(|e| KeystoreError::Format(e.to_string()))?;
+    match value.get("version").and_then(Value::as_u64) {
+        Some(VERSION) => {},
+        Some(v) => return Err(KeystoreError::Format(format!("unsupported version {}", v))),
+        None => return Err(format("missing version")),
+    }
+    let keys = value.get("keys").and_then(Value::as_object).ok_or_else(|| format("missing keys"))?;
+
+    let mut entries = BTreeMap::new();
+    for (name, entry) in keys {
+        let bad = || KeystoreError::Format(format!("invalid entry `{}`", name));
+        let bytes = |value: Option<&Value>| value
+            .and_then(Value::as_str)
+            .and_then(|s| hex::decode(s).ok())
+            .ok_or_else(bad);
+        let number = |field: &str| entry.get("kdf")
+            .and_then(|kdf| kdf.get(field))
+            .and_then(Value::as_u64)
+            .ok_or_else(bad);
+
+        let public_key = PublicKey::decode(&bytes(entry.get("publicKey"))?).map_err(KeystoreError::Key)?;
+        let out_of_range = || KeystoreError::Format(format!("scrypt parameters of `{}` out of range", name));
+        let kdf = KdfParams {
+            log_n: u8::try_from(number("logN")?).map_err(|_| out_of_range())?,
+            r: u32::try_from(number("r")?).map_err(|_| out_of_range())?,
+            p: u32::try_from(number("p")?).map_err(|_| out_of_range())?,
+        };
+        if !kdf.is_affordable() {
+            return Err(out_of_range());
+        }
+        let salt = bytes(entry.get("kdf").and_then(|kdf| kdf.get("salt")))?;
+        let nonce = bytes(entry.get("nonce"))?;
+        if salt.len() != SALT_LEN || nonce.len() != NONCE_LEN {
+            return Err(bad());
+        }
+        let ciphertext = bytes(entry.get("ciphertext"))?;
+        entries.insert(name.clone(), Entry { public_key, kdf, salt, nonce, ciphertext });
+    }
+    Ok(entries)
+}
+
+/// Derives the key that encrypts a secret key from `passphrase`.
+fn derive_key(passphrase: &str, salt: &[u8], kdf: KdfParams) -> Result<[u8; 32], KeystoreError> {
+    let params = scrypt::ScryptParams::new(kdf.log_n, kdf.r, kdf.p)
+        .map_err(|_| KeystoreError::Format("invalid scrypt parameters".to_string()))?;
+    let mut key = [0; 32];
+    scrypt::scrypt(passphrase.as_bytes(), salt, &params, &mut key)
+        .expect("32 bytes is a valid scrypt output length");
+    Ok(key)
+}
+
+/// The nonce of an entry, whose length was checked when it was read or generated.
+fn xnonce(nonce: &[u8]) -> XNonce {
+    let mut bytes = [0; NONCE_LEN];
+    bytes.copy_from_slice(nonce);
+    XNonce::from(bytes)
+}
+
+/// The data a ciphertext is bound to: the name and public key of its identity.
+fn associated_data(name: &str, public_key: &PublicKey) -> Vec<u8> {
+    let mut aad = public_key.encode();
+    aad.extend(name.as_bytes());
+    aad
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signing::Verifier;
+
+    /// Cheap parameters, so the tests don't spend their time in scrypt.
+    const FAST: KdfParams = KdfParams { log_n: 4, r: 8, p: 1 };
+
+    fn keystore(dir: &tempfile::TempDir) -> Keystore {
+        Keystore::open(dir.path().join("keys.json")).unwrap().with_kdf(FAST)
+    }
+
+    #[test]
+    fn keystore_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut keys = keystore(&dir);
+        assert_eq!(keys.names().count(), 0);
+        let alice = keys.generate("alice", Scheme::Ed25519, "correct horse").unwrap();
+        let bob = keys.generate("bob", Scheme::Secp256k1, "battery staple").unwrap();
+        keys.save().unwrap();
+
+        let keys = Keystore::open(keys.path()).unwrap();
+        assert_eq!(keys.names().collect::<Vec<_>>(), vec!["alice", "bob"]);
+        assert_eq!(keys.public_key("alice").unwrap(), &alice);
+        assert_eq!(keys.public_key("bob").unwrap(), &bob);
+
+        let keypair = keys.unlock("alice", "correct horse").unwrap();
+        assert_eq!(keypair.public_key(), alice);
+        let signature = Signer::sign(&keypair, b"hello").unwrap();
+        assert!(alice.verify(b"hello", &signature));
+        assert_eq!(keys.unlock("bob", "battery staple").unwrap().public_key(), bob);
+    }
+
+    #[test]
+    fn keystore_exports_creator() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut keys = keystore(&dir);
+        let keypair = Keypair::generate(Scheme::Ed25519);
+        keys.insert("alice", &keypair, "pass").unwrap();
+        assert_eq!(keys.creator("alice").unwrap(), Creator::of(&keypair.public_key()));
+        assert_eq!(keys.address("alice").unwrap(), Address::new(Creator::of(&keypair.public_key()).id()));
+        assert!(matches!(keys.creator("carol"), Err(KeystoreError::NotFound(_))));
+    }
+
+    #[test]
+    fn keystore_rejects() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut keys = keystore(&dir);
+        keys.generate("alice", Scheme::Ed25519, "pass").unwrap();
+        assert!(matches!(keys.generate("alice", Scheme::Ed25519, "pass"), Err(KeystoreError::Exists(_))));
+        assert!(matches!(keys.unlock("alice", "wrong"), Err(KeystoreError::BadPassphrase)));
+        assert!(matches!(keys.unlock("bob", "pass"), Err(KeystoreError::NotFound(_))));
+
+        // An entry moved to another name, or with another public key, no longer decrypts.
+        let mut renamed = keystore(&dir);
+        renamed.entries.insert("mallory".to_string(), keys.entries["alice"].clone());
+        assert!(matches!(renamed.unlock("mallory", "pass"), Err(KeystoreError::BadPassphrase)));
+        let mut swapped = keys.entries["alice"].clone();
+        swapped.public_key = Keypair::generate(Scheme::Ed25519).public_key();
+        renamed.entries.insert("alice".to_string(), swapped);
+        assert!(matches!(renamed.unlock("alice", "pass"), Err(KeystoreError::BadPassphrase)));
+
+        keys.remove("alice").unwrap();
+        assert!(!keys.contains("alice"));
+        assert!(matches!(keys.remove("alice"), Err(KeystoreError::NotFound(_))));
+    }
+
+    #[test]
+    fn keystore_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut keys = keystore(&dir);
+        keys.generate("alice", Scheme::Secp256k1, "pass").unwrap();
+        let secret = keys.unlock("alice", "pass").unwrap().secret_to_bytes();
+        keys.save().unwrap();
+
+        let json = fs::read_to_string(keys.path()).unwrap();
+        assert!(!json.contains(&hex::encode(secret)));
+        let value: Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["version"], 1);
+        assert_eq!(value["keys"]["alice"]["kdf"]["logN"], 4);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(keys.path()).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        let path = dir.path().join("bad.json");
+        fs::write(&path, r#"{"version":2,"keys":{}}"#).unwrap();
+        assert!(matches!(Keystore::open(&path), Err(KeystoreError::Format(_))));
+        fs::write(&path, r#"{"version":1,"keys":{"a":{"publicKey":"00"}}}"#).unwrap();
+        assert!(Keystore::open(&path).is_err());
+        fs::write(&path, "[").unwrap();
+        assert!(matches!(Keystore::open(&path), Err(KeystoreError::Format(_))));
+    }
+
+    #[test]
+    fn keystore_rejects_kdf() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut keys = keystore(&dir);
+        keys.generate("alice", Scheme::Ed25519, "pass").unwrap();
+        keys.save().unwrap();
+        let value: Value = serde_json::from_str(&fs::read_to_string(keys.path()).unwrap()).unwrap();
+
+        let open = |field: &str, n: Value| {
+            let mut value = value.clone();
+            value["keys"]["alice"]["kdf"][field] = n;
+            fs::write(keys.path(), value.to_string()).unwrap();
+            Keystore::open(keys.path())
+        };
+        assert!(KdfParams::default().is_affordable());
+        // With r = 8 and p = 1, N can be 2^18 before scrypt takes more than 256 MiB.
+        assert!(open("logN", json!(4)).is_ok());
+        assert!(open("logN", json!(18)).is_ok());
+        assert!(matches!(open("logN", json!(19)), Err(KeystoreError::Format(_))));
+        // 260 used to be read as a log N of 4.
+        assert!(matches!(open("logN", json!(260)), Err(KeystoreError::Format(_))));
+        assert!(matches!(open("logN", json!(100)), Err(KeystoreError::Format(_))));
+        // With N = 16, r can be 2^17 before it takes more than 256 MiB.
+        assert!(open("r", json!(1 << 17)).is_ok());
+        assert!(matches!(open("r", json!((1 << 17) + 1)), Err(KeystoreError::Format(_))));
+        assert!(matches!(open("r", json!(u64::from(u32::MAX) + 9)), Err(KeystoreError::Format(_))));
+        // With N = 16 and r = 8, p can be 2^15 before N·r·p is more than 2^22.
+        assert!(open("p", json!(1 << 15)).is_ok());
+        assert!(matches!(open("p", json!((1 << 15) + 1)), Err(KeystoreError::Format(_))));
+        assert!(matches!(open("salt", json!("")), Err(KeystoreError::Format(_))));
+        assert!(matches!(open("salt", json!(hex::encode([0; SALT_LEN + 1]))), Err(KeystoreError::Format(_))));
+    }
+}
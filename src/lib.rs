@@ -31,14 +31,29 @@
 //! # Testing 123
 //! Objectives, per the whitepaper:
 //! 1. compile original source code to an intermediate abstract syntax structure (usually as in
-//! Abstract Syntax Tree)
+//!    Abstract Syntax Tree)
 //! 2. translate the intermediate structure to the computation primitives, distribution primitives
-//! and computation abstractions of the Ambients protocol
+//!    and computation abstractions of the Ambients protocol
 //! 3. generate the bytecode executable from the primitives
 
 mod prelude;
 
-mod ambient;
-mod primitives;
-mod manifest;
-mod keypair;
+pub mod ambient;
+pub mod primitives;
+pub mod manifest;
+pub mod keypair;
+pub mod ed25519;
+pub mod signing;
+pub mod keystore;
+pub mod access;
+pub mod runtime;
+pub mod reduction;
+pub mod congruence;
+pub mod compiler;
+pub mod bytecode;
+pub mod dag_cbor;
+pub mod slicer;
+pub mod store;
+pub mod car;
+pub mod log;
+pub mod replay;
@@ -0,0 +1,858 @@
+//! Merkle-DAG event logs of program executions.
+//!
+//! Every execution is recorded as a log of entries, each linking to the entries it causally
+//! depends on by CID. The log of a program starts with its `deploy` entry, followed by a `create`
+//! entry for every ambient in the program, linked to the entry of the ambient it is created in.
+//! Ambients created in the same place are numbered in program order, so that ambients with the
+//! same name have entries of their own.
+//! Every capability event fired on the program is then an entry linked to the latest entries of
+//! the ambients whose capabilities it consumed:
+//!
+//! ```text
+//! in    a[in b.P | Q] | b[in_ a.R | S]    a and b
+//! out   b[a[out b.P | Q] | out_ a.R | S]  b and a
+//! open  open a.P | a[open_ a.Q | R]       the enclosing ambient and a
+//! ```
+//!
+//! Events in ambients that don't touch are concurrent, so the log is partially ordered. Every
+//! entry carries a Lamport clock, one more than the largest clock of the entries it links to, or
+//! 0 for the `deploy` entry. Entries are listed by clock, and entries with the same clock, which
+//! are concurrent, by CID. Every replica with the same entries lists them in the same order, and
+//! every entry is after the entries it links to.
+//!
+//! Entries are DAG-CBOR nodes addressed by their CID, so the heads of a log verify the whole log.
+//! Logs of the same program from different replicas merge into the log of all their entries.
+//!
+//! ```text
+//! {
+//!   program: <cid of the manifest>,
+//!   op: 1 | 0 | 2 | 4 | 6,       (deploy, create, or the in, out or open of the event)
+//!   ambient: "a",                (create)
+//!   path: ["c"],                 (create)
+//!   index: 1,                    (create)
+//!   event: { ... },              (in, out and open, see `runtime`)
+//!   next: [<cid>, ...],          (sorted)
+//!   clock: 3
+//! }
+//! ```
+//!
+//! Ops are stored as the opcodes of `primitives::Capability`.
+
+use std::collections::{ BTreeMap, BTreeSet };
+use std::convert::TryFrom;
+use std::error::Error;
+
+use ambients_parser::ast::Exec;
+use cid::Cid;
+
+use crate::bytecode;
+use crate::dag_cbor::{ self, Ipld };
+use crate::keypair::SigningError;
+use crate::prelude::*;
+use crate::primitives::Capability;
+use crate::reduction::Rule;
+use crate::runtime::{ Event, EventDecodeError, EventError, Runtime };
+use crate::signing::Signer;
+use crate::store::{ BlockStore, StoreError };
+
+/// What an entry records.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Op {
+    /// The program was deployed.
+    Deploy,
+    /// The ambient `ambient` was created inside the ambients `path`, outermost first.
+    Create {
+        /// The name of the ambient.
+        ambient: String,
+        /// Names of the ambients it was created in, outermost first.
+        path: Vec<String>,
+        /// How many ambients were created at `path` before it.
+        index: u64,
+    },
+    /// A capability event fired.
+    Fire(Event),
+}
+
+impl Op {
+    /// The opcode the op is stored as: `deploy`, `create`, or the capability the event consumed.
+    pub fn capability(&self) -> Capability {
+        match self {
+            Op::Deploy => Capability::deploy,
+            Op::Create { .. } => Capability::create,
+            Op::Fire(event) => match event.rule() {
+                Rule::In => Capability::r#in,
+                Rule::Out => Capability::out,
+                Rule::Open => Capability::open,
+            },
+        }
+    }
+}
+
+/// An entry of a log.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    program: Cid,
+    op: Op,
+    next: Vec<Cid>,
+    clock: u64,
+}
+
+/// Why an entry couldn't be decoded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EntryDecodeError {
+    /// The input isn't valid DAG-CBOR.
+    Cbor(dag_cbor::DecodeError),
+    /// A required field is missing.
+    Missing(&'static str),
+    /// A field doesn't have the type or format it should, or the entry has a field it can't have.
+    Invalid(&'static str),
+    /// The event of the entry couldn't be decoded.
+    Event(EventDecodeError),
+}
+
+impl Display for EntryDecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EntryDecodeError::Cbor(e) => write!(f, "invalid DAG-CBOR: {}", e),
+            EntryDecodeError::Missing(field) => write!(f, "missing entry field `{}`", field),
+            EntryDecodeError::Invalid(field) => write!(f, "invalid entry field `{}`", field),
+            EntryDecodeError::Event(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl Error for EntryDecodeError {}
+
+/// Why a log couldn't be built, read or recorded.
+#[derive(Debug)]
+pub enum LogError {
+    /// The log doesn't start with a `deploy` entry, or has another one.
+    NoDeploy,
+    /// An entry is for another program.
+    ProgramMismatch(Cid),
+    /// An entry links to an entry that isn't in the log, or an entry other than the `deploy`
+    /// entry doesn't link to any.
+    BadLink(Cid),
+    /// The clock of an entry isn't one more than the largest clock of the entries it links to.
+    BadClock(Cid),
+    /// An entry isn't in the store.
+    Missing(Cid),
+    /// An entry couldn't be decoded.
+    BadEntry(Cid, EntryDecodeError),
+    /// The store failed to read or write an entry.
+    Store(StoreError),
+    /// An event couldn't be signed.
+    Signing(SigningError),
+    /// An event was rejected by the runtime.
+    Event(EventError),
+}
+
+impl Display for LogError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LogError::NoDeploy => write!(f, "log doesn't start with its only deploy entry"),
+            LogError::ProgramMismatch(cid) => write!(f, "entry {} is for another program", cid),
+            LogError::BadLink(cid) => write!(f, "entry {} links to an entry that isn't in the log", cid),
+            LogError::BadClock(cid) => write!(f, "clock of entry {} doesn't follow the entries it links to", cid),
+            LogError::Missing(cid) => write!(f, "missing entry {}", cid),
+            LogError::BadEntry(cid, e) => write!(f, "invalid entry {}: {}", cid, e),
+            LogError::Store(e) => write!(f, "{}", e),
+            LogError::Signing(e) => write!(f, "{}", e),
+            LogError::Event(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl Error for LogError {}
+
+impl From<SigningError> for LogError {
+    fn from(e: SigningError) -> LogError {
+        LogError::Signing(e)
+    }
+}
+
+impl From<EventError> for LogError {
+    fn from(e: EventError) -> LogError {
+        LogError::Event(e)
+    }
+}
+
+impl Entry {
+    /// The entry of `op` on the program whose manifest is `program`, after the entries `next`,
+    /// at `clock`.
+    pub fn new(program: &Cid, op: Op, next: impl IntoIterator<Item = Cid>, clock: u64) -> Entry {
+        let next: BTreeSet<_> = next.into_iter().collect();
+        Entry { program: program.clone(), op, next: next.into_iter().collect(), clock }
+    }
+
+    /// The CID of the manifest of the program.
+    pub fn program(&self) -> &Cid {
+        &self.program
+    }
+
+    /// What the entry records.
+    pub fn op(&self) -> &Op {
+        &self.op
+    }
+
+    /// The CIDs of the entries the entry causally depends on, sorted.
+    pub fn next(&self) -> &[Cid] {
+        &self.next
+    }
+
+    /// The Lamport clock of the entry.
+    pub fn clock(&self) -> u64 {
+        self.clock
+    }
+
+    /// The event the entry records, `None` if it doesn't record one.
+    pub fn event(&self) -> Option<&Event> {
+        match &self.op {
+            Op::Fire(event) => Some(event),
+            _ => None,
+        }
+    }
+
+    /// The entry as an IPLD map, to be encoded as DAG-CBOR.
+    pub fn to_ipld(&self) -> Ipld {
+        let mut map = BTreeMap::new();
+        map.insert("program".to_string(), Ipld::Link(self.program.clone()));
+        match &self.op {
+            Op::Deploy => {},
+            Op::Create { ambient, path, index } => {
+                map.insert("ambient".to_string(), Ipld::String(ambient.clone()));
+                map.insert("path".to_string(), Ipld::List(path.iter().cloned().map(Ipld::String).collect()));
+                map.insert("index".to_string(), Ipld::Integer(*index as i64));
+            },
+            Op::Fire(event) => {
+                map.insert("event".to_string(), event.to_ipld());
+            },
+        }
+        map.insert("op".to_string(), Ipld::Integer(self.op.capability() as i64));
+        map.insert("next".to_string(), Ipld::List(self.next.iter().cloned().map(Ipld::Link).collect()));
+        map.insert("clock".to_string(), Ipld::Integer(self.clock as i64));
+        Ipld::Map(map)
+    }
+
+    /// Reads an entry from its IPLD map.
+    pub fn from_ipld(ipld: &Ipld) -> Result<Entry, EntryDecodeError> {
+        let map = match ipld {
+            Ipld::Map(map) => map,
+            _ => return Err(EntryDecodeError::Invalid("entry")),
+        };
+        let program = match map.get("program") {
+            Some(Ipld::Link(cid)) => cid.clone(),
+            Some(_) => return Err(EntryDecodeError::Invalid("program")),
+            None => return Err(EntryDecodeError::Missing("program")),
+        };
+        let next: Vec<Cid> = match map.get("next") {
+            Some(Ipld::List(next)) => next.iter().map(|link| match link {
+                Ipld::Link(cid) => Ok(cid.clone()),
+                _ => Err(EntryDecodeError::Invalid("next")),
+            }).collect::<Result<_, _>>()?,
+            Some(_) => return Err(EntryDecodeError::Invalid("next")),
+            None => return Err(EntryDecodeError::Missing("next")),
+        };
+        // Sorted and unique, so that every entry has one encoding.
+        if next.windows(2).any(|w| w[0] >= w[1]) {
+            return Err(EntryDecodeError::Invalid("next"));
+        }
+        let clock = match map.get("clock") {
+            Some(Ipld::Integer(clock)) if *clock >= 0 => *clock as u64,
+            Some(_) => return Err(EntryDecodeError::Invalid("clock")),
+            None => return Err(EntryDecodeError::Missing("clock")),
+        };
+        // The fields of the op, besides `program`, `op`, `next` and `clock`.
+        let opcode = match map.get("op") {
+            Some(Ipld::Integer(op)) => u8::try_from(*op).ok().and_then(|op| bytecode::capability(op).ok()),
+            Some(_) => None,
+            None => return Err(EntryDecodeError::Missing("op")),
+        };
+        let (op, fields) = match opcode {
+            Some(Capability::deploy) => (Op::Deploy, 0),
+            Some(Capability::create) => {
+                let ambient = match map.get("ambient") {
+                    Some(Ipld::String(ambient)) => ambient.clone(),
+                    Some(_) => return Err(EntryDecodeError::Invalid("ambient")),
+                    None => return Err(EntryDecodeError::Missing("ambient")),
+                };
+                let path = match map.get("path") {
+                    Some(Ipld::List(path)) => path.iter().map(|name| match name {
+                        Ipld::String(name) => Ok(name.clone()),
+                        _ => Err(EntryDecodeError::Invalid("path")),
+                    }).collect::<Result<_, _>>()?,
+                    Some(_) => return Err(EntryDecodeError::Invalid("path")),
+                    None => return Err(EntryDecodeError::Missing("path")),
+                };
+                let index = match map.get("index") {
+                    Some(Ipld::Integer(index)) if *index >= 0 => *index as u64,
+                    Some(_) => return Err(EntryDecodeError::Invalid("index")),
+                    None => return Err(EntryDecodeError::Missing("index")),
+                };
+                (Op::Create { ambient, path, index }, 3)
+            },
+            Some(opcode @ Capability::r#in) | Some(opcode @ Capability::out) | Some(opcode @ Capability::open) => {
+                let event = map.get("event").ok_or(EntryDecodeError::Missing("event"))?;
+                let event = Event::from_ipld(event).map_err(EntryDecodeError::Event)?;
+                if *event.program() != program {
+                    return Err(EntryDecodeError::Invalid("event"));
+                }
+                let op = Op::Fire(event);
+                if op.capability() != opcode {
+                    return Err(EntryDecodeError::Invalid("op"));
+                }
+                (op, 1)
+            },
+            _ => return Err(EntryDecodeError::Invalid("op")),
+        };
+        if map.len() != 4 + fields {
+            return Err(EntryDecodeError::Invalid("entry"));
+        }
+        Ok(Entry { program, op, next, clock })
+    }
+
+    /// Encodes the entry as DAG-CBOR.
+    pub fn to_cbor(&self) -> Vec<u8> {
+        dag_cbor::encode(&self.to_ipld())
+    }
+
+    /// Decodes an entry from DAG-CBOR.
+    pub fn from_cbor(bytes: &[u8]) -> Result<Entry, EntryDecodeError> {
+        Entry::from_ipld(&dag_cbor::decode(bytes).map_err(EntryDecodeError::Cbor)?)
+    }
+
+    /// The CID of the entry.
+    pub fn cid(&self) -> Cid {
+        dag_cbor::cid(&self.to_cbor())
+    }
+}
+
+impl Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.op {
+            Op::Deploy => write!(f, "deploy"),
+            Op::Create { ambient, path, .. } if path.is_empty() => write!(f, "create {}", ambient),
+            Op::Create { ambient, path, .. } => write!(f, "{}: create {}", path.join("/"), ambient),
+            Op::Fire(event) => write!(f, "{}", event),
+        }
+    }
+}
+
+/// The log of an execution: its entries, by clock and then by CID.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Log {
+    entries: BTreeMap<Cid, Entry>,
+    order: BTreeSet<(u64, Cid)>,
+}
+
+impl Log {
+    /// The log of `entries`, in any order. There has to be a single `deploy` entry at clock 0,
+    /// and every other entry has to be for its program, link to entries of the log and have the
+    /// clock that follows theirs.
+    pub fn from_entries(entries: impl IntoIterator<Item = Entry>) -> Result<Log, LogError> {
+        let mut log = Log { entries: BTreeMap::new(), order: BTreeSet::new() };
+        for entry in entries {
+            log.push(entry.cid(), entry);
+        }
+        let root = match log.iter().next() {
+            Some((_, entry)) if entry.op == Op::Deploy && entry.next.is_empty() && entry.clock == 0 => entry,
+            _ => return Err(LogError::NoDeploy),
+        };
+        for (cid, entry) in log.iter().skip(1) {
+            if entry.op == Op::Deploy {
+                return Err(LogError::NoDeploy);
+            }
+            if entry.program != root.program {
+                return Err(LogError::ProgramMismatch(cid.clone()));
+            }
+            if entry.next.is_empty() || !entry.next.iter().all(|next| log.entries.contains_key(next)) {
+                return Err(LogError::BadLink(cid.clone()));
+            }
+            if entry.clock != log.clock(&entry.next) {
+                return Err(LogError::BadClock(cid.clone()));
+            }
+        }
+        Ok(log)
+    }
+
+    /// Adds `entry`, unless the log already has it, returning its CID.
+    fn push(&mut self, cid: Cid, entry: Entry) -> Cid {
+        if !self.entries.contains_key(&cid) {
+            self.order.insert((entry.clock, cid.clone()));
+            self.entries.insert(cid.clone(), entry);
+        }
+        cid
+    }
+
+    /// The clock of an entry after the entries `next`.
+    fn clock<'a>(&self, next: impl IntoIterator<Item = &'a Cid>) -> u64 {
+        next.into_iter().filter_map(|cid| self.entries.get(cid)).map(|entry| entry.clock + 1).max().unwrap_or(0)
+    }
+
+    /// The CID of the manifest of the program.
+    pub fn program(&self) -> &Cid {
+        &self.entries[self.root()].program
+    }
+
+    /// The CID of the `deploy` entry, the first entry of the log.
+    pub fn root(&self) -> &Cid {
+        &self.order.iter().next().expect("a log has a deploy entry").1
+    }
+
+    /// The number of entries.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether the log has no entries, which it never has: it has a `deploy` entry at least.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The entry `cid`.
+    pub fn get(&self, cid: &Cid) -> Option<&Entry> {
+        self.entries.get(cid)
+    }
+
+    /// The entries with their CIDs, by clock and then by CID.
+    pub fn iter(&self) -> impl Iterator<Item = (&Cid, &Entry)> {
+        self.order.iter().map(move |(_, cid)| (cid, &self.entries[cid]))
+    }
+
+    /// The events of the log, in log order.
+    pub fn events(&self) -> impl Iterator<Item = &Event> {
+        self.iter().filter_map(|(_, entry)| entry.event())
+    }
+
+    /// The CIDs of the entries no entry links to, sorted.
+    pub fn heads(&self) -> Vec<Cid> {
+        let linked: BTreeSet<_> = self.entries.values().flat_map(|entry| &entry.next).collect();
+        self.entries.keys().filter(|cid| !linked.contains(cid)).cloned().collect()
+    }
+
+    /// Whether the entry `a` happened before the entry `b`, that is, whether `b` links to `a`
+    /// through any number of entries. Entries where neither happened before the other are
+    /// concurrent.
+    pub fn happened_before(&self, a: &Cid, b: &Cid) -> bool {
+        let clock = match self.entries.get(a) {
+            Some(entry) => entry.clock,
+            None => return false,
+        };
+        let mut seen = BTreeSet::new();
+        let mut pending = vec![b];
+        while let Some(cid) = pending.pop() {
+            // Clocks only grow along links, so nothing below `a`'s clock can lead to it.
+            for next in self.entries.get(cid).into_iter().flat_map(|entry| &entry.next) {
+                if next == a {
+                    return true;
+                }
+                if self.entries[next].clock > clock && seen.insert(next) {
+                    pending.push(next);
+                }
+            }
+        }
+        false
+    }
+
+    /// The log of the entries of both logs, which have to be logs of the same program.
+    pub fn merge(&self, other: &Log) -> Result<Log, LogError> {
+        if self.root() != other.root() {
+            return Err(LogError::ProgramMismatch(other.root().clone()));
+        }
+        let mut merged = self.clone();
+        for (cid, entry) in other.iter() {
+            merged.push(cid.clone(), entry.clone());
+        }
+        Ok(merged)
+    }
+
+    /// Puts every entry in `store`.
+    pub fn store(&self, store: &mut dyn BlockStore) -> Result<(), StoreError> {
+        self.iter().try_for_each(|(cid, entry)| store.put(cid, &entry.to_cbor()))
+    }
+
+    /// Reads the log whose heads are `heads` from `store`, following the links of every entry.
+    pub fn load(heads: &[Cid], store: &dyn BlockStore) -> Result<Log, LogError> {
+        let mut entries: BTreeMap<Cid, Entry> = BTreeMap::new();
+        let mut pending = heads.to_vec();
+        while let Some(cid) = pending.pop() {
+            if entries.contains_key(&cid) {
+                continue;
+            }
+            let bytes = match store.get(&cid) {
+                Ok(Some(bytes)) => bytes,
+                Ok(None) => return Err(LogError::Missing(cid)),
+                Err(e) => return Err(LogError::Store(e)),
+            };
+            let entry = Entry::from_cbor(&bytes).map_err(|e| LogError::BadEntry(cid.clone(), e))?;
+            pending.extend(entry.next.iter().filter(|next| !entries.contains_key(*next)).cloned());
+            entries.insert(cid, entry);
+        }
+        Log::from_entries(entries.into_values())
+    }
+}
+
+/// Records the entries of an execution as its events fire.
+///
+/// The recorder keeps the latest entries of every ambient, by the names of the ambients from the
+/// top level down to it. Ambients with the same name in the same place share their entries, which
+/// can only order the log more than needed.
+#[derive(Debug, Clone)]
+pub struct Recorder {
+    log: Log,
+    latest: BTreeMap<Location, BTreeSet<Cid>>,
+}
+
+/// Where an ambient is: the names of the ambients from the top level down to it.
+type Location = Vec<String>;
+
+impl Recorder {
+    /// Starts the log of `exec`, the program whose manifest is `program`, with its `deploy` and
+    /// `create` entries.
+    pub fn new(program: &Cid, exec: &Exec) -> Recorder {
+        let deploy = Entry::new(program, Op::Deploy, vec![], 0);
+        let mut log = Log { entries: BTreeMap::new(), order: BTreeSet::new() };
+        let root = log.push(deploy.cid(), deploy);
+        let mut recorder = Recorder { log, latest: BTreeMap::new() };
+        recorder.latest.insert(vec![], vec![root].into_iter().collect());
+
+        let mut created = vec![];
+        ambients(exec, &mut vec![], &mut created);
+        let mut counts: BTreeMap<Location, u64> = BTreeMap::new();
+        for (path, ambient) in created {
+            let next = recorder.latest(&path);
+            let clock = recorder.log.clock(&next);
+            let count = counts.entry(path.clone()).or_default();
+            let index = *count;
+            *count += 1;
+            let op = Op::Create { ambient: ambient.to_string(), path: path.clone(), index };
+            let entry = Entry::new(program, op, next, clock);
+            let cid = recorder.log.push(entry.cid(), entry);
+            let mut location = path;
+            location.push(ambient.to_string());
+            recorder.latest.entry(location).or_default().insert(cid);
+        }
+        recorder
+    }
+
+    /// The log recorded so far.
+    pub fn log(&self) -> &Log {
+        &self.log
+    }
+
+    /// Ends the recording, returning the log.
+    pub fn finish(self) -> Log {
+        self.log
+    }
+
+    /// Records `event`, which has to be the event that fired next, returning the CID of its
+    /// entry.
+    pub fn record(&mut self, event: Event) -> Cid {
+        let (_, after, (from, to)) = locations(&event);
+        let entry = self.entry(event);
+        let cid = self.log.push(entry.cid(), entry);
+        self.relocate(&from, &to);
+        for location in after {
+            self.latest.insert(location, vec![cid.clone()].into_iter().collect());
+        }
+        cid
+    }
+
+    /// The entry `event` would be recorded as if it fired next.
+    pub fn entry(&self, event: Event) -> Entry {
+        let (before, _, _) = locations(&event);
+        let next: BTreeSet<_> = before.iter().flat_map(|location| self.latest(location)).collect();
+        let clock = self.log.clock(&next);
+        Entry::new(self.log.program(), Op::Fire(event), next, clock)
+    }
+
+    /// The latest entries of the ambient at `location`, or of the nearest ambient around it.
+    fn latest(&self, location: &[String]) -> BTreeSet<Cid> {
+        (0..=location.len()).rev()
+            .find_map(|len| self.latest.get(&location[..len]))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Moves the ambients at `from` and inside it to `to`.
+    fn relocate(&mut self, from: &[String], to: &[String]) {
+        let moved: Vec<_> = self.latest.keys().filter(|location| location.starts_with(from)).cloned().collect();
+        for location in moved {
+            let cids = self.latest.remove(&location).expect("listed");
+            let mut relocated = to.to_vec();
+            relocated.extend_from_slice(&location[from.len()..]);
+            self.latest.entry(relocated).or_default().extend(cids);
+        }
+    }
+}
+
+/// The ambients whose capabilities `event` consumes, where they end up, and where the ambient
+/// that moves goes from and to, by the names of the ambients down to them.
+fn locations(event: &Event) -> (Vec<Location>, Vec<Location>, (Location, Location)) {
+    let at = |names: &[&str]| {
+        let mut location = event.path().to_vec();
+        location.extend(names.iter().map(|name| name.to_string()));
+        location
+    };
+    let (a, b) = (event.ambient(), event.target().unwrap_or_default());
+    match event.rule() {
+        Rule::In => (vec![at(&[a]), at(&[b])], vec![at(&[b]), at(&[b, a])], (at(&[a]), at(&[b, a]))),
+        Rule::Out => (vec![at(&[b]), at(&[b, a])], vec![at(&[b]), at(&[a])], (at(&[b, a]), at(&[a]))),
+        Rule::Open => (vec![at(&[]), at(&[a])], vec![at(&[])], (at(&[a]), at(&[]))),
+    }
+}
+
+/// Every ambient of `exec` with the names of the ambients it is in, outermost first.
+fn ambients<'a>(exec: &Exec<'a>, path: &mut Vec<String>, created: &mut Vec<(Vec<String>, &'a str)>) {
+    match exec {
+        Exec::Parallel(procs, _) | Exec::Serial(procs, _) => procs.iter().for_each(|p| ambients(p, path, created)),
+        Exec::Group(inner, _) => ambients(inner, path, created),
+        Exec::Noop(name, _) => created.push((path.clone(), name)),
+        Exec::Ambient(name, body, _) => {
+            created.push((path.clone(), name));
+            path.push(name.to_string());
+            ambients(body, path, created);
+            path.pop();
+        },
+        _ => {},
+    }
+}
+
+/// Reduces the program of `runtime` to its normal form, firing every event as `signer`, and
+/// returns the log of the execution.
+pub fn execute(runtime: &mut Runtime, signer: &dyn Signer) -> Result<Log, LogError> {
+    let mut recorder = Recorder::new(runtime.program(), runtime.exec());
+    while let Some(event) = runtime.next_event(signer)? {
+        runtime.apply(&event)?;
+        recorder.record(event);
+    }
+    Ok(recorder.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::access::Access;
+    use crate::ambient::program_cid;
+    use crate::manifest::Manifest;
+    use crate::signing::{ Keypair, Scheme };
+    use crate::store::MemoryStore;
+    use ambients_parser::parse;
+
+    const PROGRAM: &str = "c[in_ b.open a | a[open_]] | b[in c] | x[in_ y] | y[in x]";
+
+    fn run(program: &str, access: Access, signer: &dyn Signer) -> Result<Log, LogError> {
+        let manifest = Manifest::new(&program_cid(program), "program", Some(access.address()), None, None);
+        let mut runtime = Runtime::new(&manifest, access, parse(program).unwrap());
+        execute(&mut runtime, signer)
+    }
+
+    fn entry<'a>(log: &'a Log, text: &str) -> (&'a Cid, &'a Entry) {
+        log.iter().find(|(_, entry)| entry.to_string() == text).unwrap()
+    }
+
+    #[test]
+    fn log_entries() {
+        let keypair = Keypair::generate(Scheme::Ed25519);
+        let log = run(PROGRAM, Access::Any, &keypair).unwrap();
+        let entries = [
+            "deploy", "create c", "c: create a", "create b", "create x", "create y",
+            "b in c", "y in x", "c: open a",
+        ];
+        let clocks: Vec<_> = entries.iter().map(|text| entry(&log, text).1.clock()).collect();
+        assert_eq!(clocks, vec![0, 1, 2, 1, 1, 1, 2, 2, 3]);
+        assert_eq!(log.len(), entries.len());
+        assert_eq!(log.events().count(), 3);
+        assert!(log.iter().all(|(_, entry)| entry.program() == log.program()));
+
+        // Creates link to the entry of the ambient they are created in.
+        let (c, _) = entry(&log, "create c");
+        assert_eq!(entry(&log, "create c").1.next(), &[log.root().clone()]);
+        assert_eq!(entry(&log, "c: create a").1.next(), std::slice::from_ref(c));
+
+        // Events link to the latest entries of the ambients whose capabilities they consume.
+        let (b, _) = entry(&log, "create b");
+        let (b_in_c, fired) = entry(&log, "b in c");
+        let mut expected = [c.clone(), b.clone()];
+        expected.sort();
+        assert_eq!(fired.next(), &expected[..]);
+        let (a, _) = entry(&log, "c: create a");
+        let mut expected = [b_in_c.clone(), a.clone()];
+        expected.sort();
+        assert_eq!(entry(&log, "c: open a").1.next(), &expected[..]);
+
+        // `y in x` doesn't touch `b` or `c`, so it is concurrent with their events.
+        let (y_in_x, _) = entry(&log, "y in x");
+        assert!(!entry(&log, "y in x").1.next().contains(b_in_c));
+        let (open_a, _) = entry(&log, "c: open a");
+        assert_eq!(log.heads(), { let mut heads = vec![y_in_x.clone(), open_a.clone()]; heads.sort(); heads });
+    }
+
+    #[test]
+    fn log_same_names() {
+        let program = program_cid("a[] | a[] | b[c[] | c[]]");
+        let log = Recorder::new(&program, &parse("a[] | a[] | b[c[] | c[]]").unwrap()).finish();
+        let mut creates: Vec<_> = log.iter().filter_map(|(_, entry)| match entry.op() {
+            Op::Create { index, .. } => Some((entry.to_string(), *index)),
+            _ => None,
+        }).collect();
+        creates.sort();
+        let expected = [("b: create c", 0), ("b: create c", 1), ("create a", 0), ("create a", 1), ("create b", 2)];
+        assert_eq!(creates, expected.iter().map(|(text, index)| (text.to_string(), *index)).collect::<Vec<_>>());
+
+        // Ambients of the same name in ambients of the same name.
+        let log = Recorder::new(&program, &parse("b[c[]] | b[c[]]").unwrap()).finish();
+        assert_eq!(log.len(), 5);
+    }
+
+    #[test]
+    fn log_moves() {
+        // `a` moves into `b`, so leaving it depends on having entered it.
+        let log = run("a[in b.out b] | b[in_ a.out_ a]", Access::Any, &Keypair::generate(Scheme::Ed25519)).unwrap();
+        let entries: Vec<_> = log.iter().map(|(_, entry)| entry.to_string()).collect();
+        assert_eq!(&entries[3..], &["a in b", "a out b"]);
+        let (a_in_b, _) = entry(&log, "a in b");
+        let (a_out_b, fired) = entry(&log, "a out b");
+        assert_eq!(fired.next(), std::slice::from_ref(a_in_b));
+        assert_eq!(log.heads(), vec![a_out_b.clone()]);
+    }
+
+    #[test]
+    fn log_store_load() {
+        let log = run(PROGRAM, Access::Any, &Keypair::generate(Scheme::Secp256k1)).unwrap();
+        let mut store = MemoryStore::new();
+        log.store(&mut store).unwrap();
+        let loaded = Log::load(&log.heads(), &store).unwrap();
+        assert_eq!(loaded, log);
+        assert_eq!(loaded.heads(), log.heads());
+        assert_eq!(loaded.root(), log.root());
+
+        let (c, _) = entry(&log, "create c");
+        store.delete(c).unwrap();
+        assert!(matches!(Log::load(&log.heads(), &store), Err(LogError::Missing(cid)) if cid == *c));
+    }
+
+    #[test]
+    fn log_from_entries_errors() {
+        let log = run(PROGRAM, Access::Any, &Keypair::generate(Scheme::Ed25519)).unwrap();
+        let entries: Vec<_> = log.iter().map(|(_, entry)| entry.clone()).collect();
+        assert_eq!(Log::from_entries(entries.clone()).unwrap(), log);
+
+        assert!(matches!(Log::from_entries(vec![]), Err(LogError::NoDeploy)));
+        assert!(matches!(Log::from_entries(entries[1..].to_vec()), Err(LogError::NoDeploy)));
+        let mut reversed = entries.clone();
+        reversed.reverse();
+        assert_eq!(Log::from_entries(reversed).unwrap(), log);
+
+        let (c, _) = entry(&log, "create c");
+        let missing = entries.iter().filter(|entry| entry.cid() != *c).cloned();
+        // Either entry linking to it, whichever comes first.
+        assert!(matches!(Log::from_entries(missing), Err(LogError::BadLink(cid)) if log.get(&cid).unwrap().next().contains(c)));
+        let z = |program: &Cid, next: Vec<Cid>, clock| Entry::new(program, Op::Create { ambient: "z".to_string(), path: vec![], index: 0 }, next, clock);
+        let unlinked = z(log.program(), vec![], 1);
+        assert!(matches!(Log::from_entries(entries.iter().cloned().chain(Some(unlinked.clone()))), Err(LogError::BadLink(cid)) if cid == unlinked.cid()));
+        let late = z(log.program(), vec![log.root().clone()], 2);
+        assert!(matches!(Log::from_entries(entries.iter().cloned().chain(Some(late.clone()))), Err(LogError::BadClock(cid)) if cid == late.cid()));
+        let other = z(&program_cid("other"), vec![log.root().clone()], 1);
+        assert!(matches!(Log::from_entries(entries.iter().cloned().chain(Some(other))), Err(LogError::ProgramMismatch(_))));
+    }
+
+    #[test]
+    fn log_total_order() {
+        let log = run(PROGRAM, Access::Any, &Keypair::generate(Scheme::Ed25519)).unwrap();
+        let order: Vec<_> = log.iter().map(|(cid, entry)| (entry.clock(), cid.clone())).collect();
+        let mut sorted = order.clone();
+        sorted.sort();
+        assert_eq!(order, sorted);
+        for (cid, entry) in log.iter() {
+            assert!(entry.next().iter().all(|next| log.get(next).unwrap().clock() < entry.clock()));
+            assert!(entry.next().iter().all(|next| log.happened_before(next, cid)));
+        }
+
+        let cid = |text| entry(&log, text).0;
+        assert!(log.happened_before(cid("create c"), cid("c: open a")));
+        assert!(log.happened_before(log.root(), cid("y in x")));
+        assert!(!log.happened_before(cid("c: open a"), cid("create c")));
+        assert!(!log.happened_before(cid("b in c"), cid("b in c")));
+        // Concurrent events, with the same clock.
+        assert!(!log.happened_before(cid("b in c"), cid("y in x")) && !log.happened_before(cid("y in x"), cid("b in c")));
+        assert_eq!(entry(&log, "b in c").1.clock(), entry(&log, "y in x").1.clock());
+    }
+
+    #[test]
+    fn log_merge() {
+        let log = run(PROGRAM, Access::Any, &Keypair::generate(Scheme::Ed25519)).unwrap();
+        // Two replicas, each missing an event the other has.
+        let without = |text| Log::from_entries(log.iter().filter(|(_, e)| e.to_string() != text).map(|(_, e)| e.clone())).unwrap();
+        let (a, b) = (without("y in x"), without("c: open a"));
+        assert_eq!(a.merge(&b).unwrap(), log);
+        assert_eq!(b.merge(&a).unwrap(), log);
+        assert_eq!(a.merge(&a).unwrap(), a);
+        assert_eq!(a.merge(&b).unwrap().heads(), log.heads());
+
+        let other = run("a[]", Access::Any, &Keypair::generate(Scheme::Ed25519)).unwrap();
+        assert!(matches!(a.merge(&other), Err(LogError::ProgramMismatch(cid)) if cid == *other.root()));
+    }
+
+    #[test]
+    fn log_unauthorized() {
+        let allowed = Keypair::generate(Scheme::Ed25519);
+        let result = run(PROGRAM, Access::Keys(vec![allowed.public_key()]), &Keypair::generate(Scheme::Ed25519));
+        assert!(matches!(result, Err(LogError::Event(EventError::Unauthorized))));
+    }
+
+    #[test]
+    fn entry_cbor() {
+        let log = run(PROGRAM, Access::Any, &Keypair::generate(Scheme::Ed25519)).unwrap();
+        for (cid, entry) in log.iter() {
+            assert_eq!(Entry::from_cbor(&entry.to_cbor()).as_ref(), Ok(entry));
+            assert_eq!(&entry.cid(), cid);
+        }
+
+        let (_, fired) = entry(&log, "b in c");
+        let mut unsorted = fired.to_ipld();
+        if let Ipld::Map(map) = &mut unsorted {
+            let mut next = fired.next().to_vec();
+            next.reverse();
+            map.insert("next".to_string(), Ipld::List(next.into_iter().map(Ipld::Link).collect()));
+        }
+        assert_eq!(Entry::from_ipld(&unsorted), Err(EntryDecodeError::Invalid("next")));
+
+        let mut other_program = fired.to_ipld();
+        if let Ipld::Map(map) = &mut other_program {
+            map.insert("program".to_string(), Ipld::Link(program_cid("other")));
+        }
+        assert_eq!(Entry::from_ipld(&other_program), Err(EntryDecodeError::Invalid("event")));
+
+        let mut extra = entry(&log, "deploy").1.to_ipld();
+        if let Ipld::Map(map) = &mut extra {
+            map.insert("ambient".to_string(), Ipld::String("a".to_string()));
+        }
+        assert_eq!(Entry::from_ipld(&extra), Err(EntryDecodeError::Invalid("entry")));
+
+        let mut negative = fired.to_ipld();
+        if let Ipld::Map(map) = &mut negative {
+            map.insert("clock".to_string(), Ipld::Integer(-1));
+        }
+        assert_eq!(Entry::from_ipld(&negative), Err(EntryDecodeError::Invalid("clock")));
+
+        // Ops are capability opcodes, and a fired event's has to be its rule's.
+        let ipld = fired.to_ipld();
+        assert_eq!(ipld.get("op"), Some(&Ipld::Integer(Capability::r#in as i64)));
+        assert_eq!(entry(&log, "deploy").1.to_ipld().get("op"), Some(&Ipld::Integer(1)));
+        assert_eq!(entry(&log, "create c").1.to_ipld().get("op"), Some(&Ipld::Integer(0)));
+        for op in [Ipld::Integer(Capability::open as i64), Ipld::Integer(Capability::in_ as i64), Ipld::Integer(256), Ipld::String("fire".to_string())] {
+            let mut wrong = ipld.clone();
+            if let Ipld::Map(map) = &mut wrong {
+                map.insert("op".to_string(), op);
+            }
+            assert_eq!(Entry::from_ipld(&wrong), Err(EntryDecodeError::Invalid("op")));
+        }
+
+        let mut bad_event = ipld;
+        if let Ipld::Map(map) = &mut bad_event {
+            map.insert("event".to_string(), Ipld::Map(BTreeMap::new()));
+        }
+        assert_eq!(Entry::from_ipld(&bad_event), Err(EntryDecodeError::Event(EventDecodeError::Missing("program"))));
+    }
+}
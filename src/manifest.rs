@@ -4,75 +4,748 @@
 //!  manifest is the identifier of the program.
 //!
 //!  The identifier in turn is used to construct a program address.
-//!  {
+//!
+//! ```text
+//! {
 //!   program: 'zdpuAkfNT6xd5mC3Jk3ZNMGrjoqqRqSKTLjU...',
 //!   name: 'hello-world',
 //!   keys: '/amb/zdpuAuTSoDhKKgAfjJBRvWw4wSg5r6b3oW...',
 //!   creator: {
 //!     id: 'zdpuAwkLw7KAgXSEqduQQoyo9MrpkWrKDrKtBUg...',
-//!     publicKey: '04c9680e7399c5d9589df2b62f32d568...'
+//!     publicKey: 'e70102c9680e7399c5d9589df2b62f32d...'
 //!   }
 //!   signature: '30440220264d3bab838066d856087779af...',
 //! }
+//! ```
 //!
+//! Manifests are hashed in their DAG-CBOR encoding, where CIDs are links and the public key and
+//! signature are bytes. In their JSON encoding, CIDs are strings and bytes are hex. The public key
+//! is prefixed with the multicodec of its signature scheme, see `signing`.
+
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::error::Error;
+use std::str::FromStr;
+
+use serde_json::{ Map, Value };
 
+use crate::dag_cbor::{ self, Ipld };
+use crate::keypair::SigningError;
 use crate::prelude::*;
+use crate::signing::{ PublicKey, Signer, Verifier };
+use crate::store::{ BlockStore, StoreError };
 use cid::Cid;
-// use crate::keypair::PublicKey;
 
-#[derive(Debug)]
-pub struct Address<'a> {
-    protocol: &'a str,
-    hash: &'a Cid,
+/// The protocol of program addresses.
+pub const PROTOCOL: &str = "amb";
+
+/// The address of a program, or of an ambient nested inside a program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Address {
+    hash: Cid,
+    path: Vec<String>,
+}
+
+/// Why a string isn't an address.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AddressError {
+    /// The address doesn't start with `/`.
+    NotAbsolute,
+    /// The address has no protocol.
+    MissingProtocol,
+    /// The protocol isn't `amb`.
+    UnsupportedProtocol(String),
+    /// The address has no CID after the protocol.
+    MissingCid,
+    /// The identifier isn't a valid CID.
+    BadCid(cid::Error),
+    /// A segment of the path isn't an ambient name.
+    BadName(String),
+}
+
+impl Display for AddressError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AddressError::NotAbsolute => write!(f, "address doesn't start with `/`"),
+            AddressError::MissingProtocol => write!(f, "address has no protocol"),
+            AddressError::UnsupportedProtocol(p) => write!(f, "unsupported address protocol `{}`", p),
+            AddressError::MissingCid => write!(f, "address has no CID"),
+            AddressError::BadCid(e) => write!(f, "invalid address CID: {}", e),
+            AddressError::BadName(name) => write!(f, "`{}` isn't an ambient name", name),
+        }
+    }
 }
 
+impl Error for AddressError {}
+
 /// The program address consists of the protocol prefix and the identifier, separated by /.
 ///
 /// For example, if the manifest hashes to zdpuAwAdomEUPx54FZVLt33ZeGZ5VrJkTgLxQiUZNBwZ3kr7e,
 /// the address of the program can be represented as (complete hash truncated for brevity):
 ///
 /// > /amb/zdpuAwAdomEUPx54FZVLt33ZeGZ5VrJkTgLxQiUZNBwZ3...
-impl<'a> Address<'a> {
-    pub fn new (protocol: &'a str, hash: &'a Cid) -> Address<'a> {
-        Address{ hash, protocol }
+///
+/// Ambients nested inside the program are addressed by the names on the way to them:
+///
+/// > /amb/zdpuAwAdomEUPx54FZVLt33ZeGZ5VrJkTgLxQiUZNBwZ3.../x/call
+impl Address {
+    /// The address of the program `hash`.
+    pub fn new (hash: &Cid) -> Address {
+        Address{ hash: hash.clone(), path: vec![] }
+    }
+
+    /// The protocol of the address, always `PROTOCOL`.
+    pub fn protocol(&self) -> &str {
+        PROTOCOL
     }
+
+    /// The identifier of the program.
+    pub fn hash(&self) -> &Cid {
+        &self.hash
+    }
+
+    /// The names of the ambients from the program to the addressed ambient, empty for the
+    /// program itself.
+    pub fn path(&self) -> &[String] {
+        &self.path
+    }
+
+    /// The address of the ambient `name` inside the addressed ambient.
+    pub fn join(&self, name: &str) -> Result<Address, AddressError> {
+        if !is_name(name) {
+            return Err(AddressError::BadName(name.to_string()));
+        }
+        let mut address = self.clone();
+        address.path.push(name.to_string());
+        Ok(address)
+    }
+
+    /// The address of the program the addressed ambient is in.
+    pub fn root(&self) -> Address {
+        Address::new(&self.hash)
+    }
+}
+
+/// Whether `name` can be the name of an ambient: the `ID` of the grammar, `[a-zA-Z0-0_\-]+`,
+/// whose only digit is `0`.
+fn is_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphabetic() || c == '0' || c == '_' || c == '-')
 }
 
-#[derive(Debug)]
-pub struct Creator<'a> {
-    id: &'a Cid,
-    // public_key: Option<&'a PublicKey>
+impl FromStr for Address {
+    type Err = AddressError;
+
+    /// Parses an address of the form `/amb/<cid>[/<name>]*`.
+    fn from_str(address: &str) -> Result<Address, AddressError> {
+        let mut parts = match address.strip_prefix('/') {
+            Some(rest) => rest.split('/'),
+            None => return Err(AddressError::NotAbsolute),
+        };
+        match parts.next() {
+            Some("") | None => return Err(AddressError::MissingProtocol),
+            Some(PROTOCOL) => {},
+            Some(protocol) => return Err(AddressError::UnsupportedProtocol(protocol.to_string())),
+        }
+        let hash = match parts.next() {
+            Some("") | None => return Err(AddressError::MissingCid),
+            Some(hash) => Cid::try_from(hash).map_err(AddressError::BadCid)?,
+        };
+        parts.try_fold(Address::new(&hash), |address, name| address.join(name))
+    }
 }
 
-impl<'a> Creator<'a> {
-    pub fn new (id: &'a Cid) -> Creator<'a> {
-        Creator{ id }
+impl Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "/{}/{}", PROTOCOL, self.hash)?;
+        for name in &self.path {
+            write!(f, "/{}", name)?;
+        }
+        Ok(())
     }
 }
 
-#[derive(Debug)]
-pub struct Manifest<'a> {
-    program_cid: &'a Cid,
-    name: &'a str,
-    keys: Option<Address<'a>>,
-    creator: Option<Creator<'a>>,
+/// The deployer of a program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Creator {
+    id: Cid,
+    public_key: Vec<u8>,
+}
+
+impl Creator {
+    /// The creator identified by `id`, signing with `public_key`.
+    pub fn new (id: &Cid, public_key: &[u8]) -> Creator {
+        Creator{ id: id.clone(), public_key: public_key.to_vec() }
+    }
+
+    /// The creator signing with `public_key`, identified by the CID of its encoding.
+    pub fn of(public_key: &PublicKey) -> Creator {
+        let public_key = public_key.encode();
+        Creator::new(&dag_cbor::cid(&dag_cbor::encode(&Ipld::Bytes(public_key.clone()))), &public_key)
+    }
+
+    /// The identifier of the creator.
+    pub fn id(&self) -> &Cid {
+        &self.id
+    }
+
+    /// The multicodec-prefixed public key the creator signs with.
+    pub fn public_key(&self) -> &[u8] {
+        &self.public_key
+    }
+}
+
+/// The root manifest of a deployed program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Manifest {
+    program_cid: Cid,
+    name: String,
+    keys: Option<Address>,
+    creator: Option<Creator>,
     signature: Option<Vec<u8>>,
 }
 
-impl<'a> Manifest<'a> {
-    pub fn new (program_cid: &'a Cid, name: &'a str, keys: Option<Address<'a>>, creator: Option<Creator<'a>>, signature: Option<Vec<u8>>) -> Manifest<'a> {
+/// Why a manifest couldn't be decoded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ManifestError {
+    /// The input isn't valid JSON.
+    Json(String),
+    /// The input isn't valid DAG-CBOR.
+    Cbor(dag_cbor::DecodeError),
+    /// A required field is missing.
+    Missing(&'static str),
+    /// A field doesn't have the type or format it should.
+    Invalid(&'static str),
+    /// The manifest isn't in the block store.
+    NotFound(Cid),
+    /// The block store failed to read the manifest.
+    Store(StoreError),
+}
+
+impl Display for ManifestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ManifestError::Json(e) => write!(f, "invalid JSON: {}", e),
+            ManifestError::Cbor(e) => write!(f, "invalid DAG-CBOR: {}", e),
+            ManifestError::Missing(field) => write!(f, "missing field `{}`", field),
+            ManifestError::Invalid(field) => write!(f, "invalid field `{}`", field),
+            ManifestError::NotFound(cid) => write!(f, "manifest {} isn't in the store", cid),
+            ManifestError::Store(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl Error for ManifestError {}
+
+/// Why a manifest failed verification.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VerifyError {
+    /// The manifest has no creator or no signature.
+    Unsigned,
+    /// The manifest is for another program.
+    ProgramMismatch,
+    /// The creator's id isn't the CID of their public key.
+    CreatorMismatch,
+    /// The creator's public key isn't a valid key of a known scheme.
+    BadPublicKey,
+    /// The signature isn't the creator's signature of the manifest.
+    BadSignature,
+}
+
+impl Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VerifyError::Unsigned => write!(f, "manifest isn't signed"),
+            VerifyError::ProgramMismatch => write!(f, "manifest is for another program"),
+            VerifyError::CreatorMismatch => write!(f, "creator id doesn't match its public key"),
+            VerifyError::BadPublicKey => write!(f, "invalid creator public key"),
+            VerifyError::BadSignature => write!(f, "signature doesn't match the manifest"),
+        }
+    }
+}
+
+impl Error for VerifyError {}
+
+impl Manifest {
+    /// The manifest of the program `program_cid` deployed as `name`.
+    pub fn new (program_cid: &Cid, name: &str, keys: Option<Address>, creator: Option<Creator>, signature: Option<Vec<u8>>) -> Manifest {
         Manifest{
-            program_cid: program_cid,
-            name: name,
-            keys: None,
-            creator: None,
-            signature: None,
+            program_cid: program_cid.clone(),
+            name: name.to_string(),
+            keys,
+            creator,
+            signature,
+        }
+    }
+
+    /// The CID of the program's bytecode.
+    pub fn program(&self) -> &Cid {
+        &self.program_cid
+    }
+
+    /// The name the program was deployed as.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The address of the keys allowed to write to the program.
+    pub fn keys(&self) -> Option<&Address> {
+        self.keys.as_ref()
+    }
+
+    /// The deployer of the program.
+    pub fn creator(&self) -> Option<&Creator> {
+        self.creator.as_ref()
+    }
+
+    /// The deployer's signature of the manifest.
+    pub fn signature(&self) -> Option<&[u8]> {
+        self.signature.as_deref()
+    }
+
+    /// The manifest as an IPLD map, to be encoded as DAG-CBOR. Fields that aren't set are left
+    /// out.
+    pub fn to_ipld(&self) -> Ipld {
+        let mut map = BTreeMap::new();
+        map.insert("program".to_string(), Ipld::Link(self.program_cid.clone()));
+        map.insert("name".to_string(), Ipld::String(self.name.clone()));
+        if let Some(keys) = &self.keys {
+            map.insert("keys".to_string(), Ipld::String(keys.to_string()));
         }
+        if let Some(creator) = &self.creator {
+            let mut fields = BTreeMap::new();
+            fields.insert("id".to_string(), Ipld::Link(creator.id.clone()));
+            fields.insert("publicKey".to_string(), Ipld::Bytes(creator.public_key.clone()));
+            map.insert("creator".to_string(), Ipld::Map(fields));
+        }
+        if let Some(signature) = &self.signature {
+            map.insert("signature".to_string(), Ipld::Bytes(signature.clone()));
+        }
+        Ipld::Map(map)
+    }
+
+    /// Reads a manifest from its IPLD map.
+    pub fn from_ipld(ipld: &Ipld) -> Result<Manifest, ManifestError> {
+        let map = match ipld {
+            Ipld::Map(map) => map,
+            _ => return Err(ManifestError::Invalid("manifest")),
+        };
+        if map.keys().any(|k| !FIELDS.contains(&k.as_str())) {
+            return Err(ManifestError::Invalid("manifest"));
+        }
+        let program_cid = match map.get("program") {
+            Some(Ipld::Link(cid)) => cid.clone(),
+            Some(_) => return Err(ManifestError::Invalid("program")),
+            None => return Err(ManifestError::Missing("program")),
+        };
+        let name = match map.get("name") {
+            Some(Ipld::String(name)) => name.clone(),
+            Some(_) => return Err(ManifestError::Invalid("name")),
+            None => return Err(ManifestError::Missing("name")),
+        };
+        let keys = match map.get("keys") {
+            Some(Ipld::String(keys)) => Some(keys.parse().map_err(|_| ManifestError::Invalid("keys"))?),
+            Some(_) => return Err(ManifestError::Invalid("keys")),
+            None => None,
+        };
+        let creator = match map.get("creator") {
+            Some(Ipld::Map(fields)) if fields.len() == 2 => match (fields.get("id"), fields.get("publicKey")) {
+                (Some(Ipld::Link(id)), Some(Ipld::Bytes(public_key))) => Some(Creator::new(id, public_key)),
+                _ => return Err(ManifestError::Invalid("creator")),
+            },
+            Some(_) => return Err(ManifestError::Invalid("creator")),
+            None => None,
+        };
+        let signature = match map.get("signature") {
+            Some(Ipld::Bytes(signature)) => Some(signature.clone()),
+            Some(_) => return Err(ManifestError::Invalid("signature")),
+            None => None,
+        };
+        Ok(Manifest { program_cid, name, keys, creator, signature })
+    }
+
+    /// Signs the manifest with `signer`, making its owner the creator of the program.
+    pub fn sign(&mut self, signer: &dyn Signer) -> Result<(), SigningError> {
+        self.creator = Some(Creator::of(&signer.public_key()));
+        self.signature = None;
+        self.signature = Some(signer.sign(&self.to_cbor())?);
+        Ok(())
+    }
+
+    /// Checks that the manifest is for `program` and signed by its creator.
+    ///
+    /// The signature is over the DAG-CBOR encoding of the manifest without the signature.
+    pub fn verify(&self, program: &Cid) -> Result<(), VerifyError> {
+        let (creator, signature) = match (&self.creator, &self.signature) {
+            (Some(creator), Some(signature)) => (creator, signature),
+            _ => return Err(VerifyError::Unsigned),
+        };
+        if self.program_cid != *program {
+            return Err(VerifyError::ProgramMismatch);
+        }
+        let public_key = PublicKey::decode(&creator.public_key).map_err(|_| VerifyError::BadPublicKey)?;
+        if *creator != Creator::of(&public_key) {
+            return Err(VerifyError::CreatorMismatch);
+        }
+        let unsigned = Manifest { signature: None, ..self.clone() };
+        if !public_key.verify(&unsigned.to_cbor(), signature) {
+            return Err(VerifyError::BadSignature);
+        }
+        Ok(())
+    }
+
+    /// Encodes the manifest as DAG-CBOR.
+    pub fn to_cbor(&self) -> Vec<u8> {
+        dag_cbor::encode(&self.to_ipld())
+    }
+
+    /// Decodes a manifest from DAG-CBOR.
+    pub fn from_cbor(bytes: &[u8]) -> Result<Manifest, ManifestError> {
+        Manifest::from_ipld(&dag_cbor::decode(bytes).map_err(ManifestError::Cbor)?)
+    }
+
+    /// The CID of the manifest, which identifies the program.
+    pub fn cid(&self) -> Cid {
+        dag_cbor::cid(&self.to_cbor())
+    }
+
+    /// Puts the manifest's block in `store`, returning its CID.
+    pub fn store(&self, store: &mut dyn BlockStore) -> Result<Cid, StoreError> {
+        store.put_cbor(&self.to_cbor())
+    }
+
+    /// Reads the manifest `cid` from `store`.
+    pub fn load(cid: &Cid, store: &dyn BlockStore) -> Result<Manifest, ManifestError> {
+        match store.get(cid).map_err(ManifestError::Store)? {
+            Some(bytes) => Manifest::from_cbor(&bytes),
+            None => Err(ManifestError::NotFound(cid.clone())),
+        }
+    }
+
+    /// Encodes the manifest as JSON, in the shape of the whitepaper.
+    pub fn to_json(&self) -> String {
+        let mut map = Map::new();
+        map.insert("program".to_string(), Value::String(self.program_cid.to_string()));
+        map.insert("name".to_string(), Value::String(self.name.clone()));
+        if let Some(keys) = &self.keys {
+            map.insert("keys".to_string(), Value::String(keys.to_string()));
+        }
+        if let Some(creator) = &self.creator {
+            let mut fields = Map::new();
+            fields.insert("id".to_string(), Value::String(creator.id.to_string()));
+            fields.insert("publicKey".to_string(), Value::String(hex::encode(&creator.public_key)));
+            map.insert("creator".to_string(), Value::Object(fields));
+        }
+        if let Some(signature) = &self.signature {
+            map.insert("signature".to_string(), Value::String(hex::encode(signature)));
+        }
+        Value::Object(map).to_string()
+    }
+
+    /// Decodes a manifest from JSON.
+    pub fn from_json(json: &str) -> Result<Manifest, ManifestError> {
+        let value: Value = serde_json::from_str(json).map_err(|e| ManifestError::Json(e.to_string()))?;
+        let map = match &value {
+            Value::Object(map) => map,
+            _ => return Err(ManifestError::Invalid("manifest")),
+        };
+        if map.keys().any(|k| !FIELDS.contains(&k.as_str())) {
+            return Err(ManifestError::Invalid("manifest"));
+        }
+        let string = |field: &'static str| match map.get(field) {
+            Some(Value::String(s)) => Ok(Some(s.as_str())),
+            Some(_) => Err(ManifestError::Invalid(field)),
+            None => Ok(None),
+        };
+        let cid = |s: &str, field| Cid::try_from(s).map_err(|_| ManifestError::Invalid(field));
+        let bytes = |s: &str, field| hex::decode(s).map_err(|_| ManifestError::Invalid(field));
+
+        let program_cid = cid(string("program")?.ok_or(ManifestError::Missing("program"))?, "program")?;
+        let name = string("name")?.ok_or(ManifestError::Missing("name"))?.to_string();
+        let keys = match string("keys")? {
+            Some(keys) => Some(keys.parse().map_err(|_| ManifestError::Invalid("keys"))?),
+            None => None,
+        };
+        let creator = match map.get("creator") {
+            Some(Value::Object(fields)) if fields.len() == 2 => match (fields.get("id"), fields.get("publicKey")) {
+                (Some(Value::String(id)), Some(Value::String(public_key))) =>
+                    Some(Creator::new(&cid(id, "creator")?, &bytes(public_key, "creator")?)),
+                _ => return Err(ManifestError::Invalid("creator")),
+            },
+            Some(_) => return Err(ManifestError::Invalid("creator")),
+            None => None,
+        };
+        let signature = match string("signature")? {
+            Some(signature) => Some(bytes(signature, "signature")?),
+            None => None,
+        };
+        Ok(Manifest { program_cid, name, keys, creator, signature })
     }
 }
 
-impl<'a> Display for Manifest<'a> {
+/// The fields a manifest can have.
+const FIELDS: [&str; 5] = ["program", "name", "keys", "creator", "signature"];
+
+impl Display for Manifest {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "manifest")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ ed25519, keypair::Keypair };
+    use crate::store::MemoryStore;
+
+    fn cid(data: &str) -> Cid {
+        dag_cbor::cid(data.as_bytes())
+    }
+
+    fn manifest() -> Manifest {
+        Manifest::new(
+            &cid("program"),
+            "hello-world",
+            Some(Address::new(&cid("keys"))),
+            Some(Creator::new(&cid("creator"), &[4, 0xc9, 0x68])),
+            Some(vec![0x30, 0x44, 0x02]),
+        )
+    }
+
+    #[test]
+    fn manifest_new_keeps_fields() {
+        let manifest = manifest();
+        assert_eq!(manifest.program(), &cid("program"));
+        assert_eq!(manifest.name(), "hello-world");
+        assert_eq!(manifest.keys(), Some(&Address::new(&cid("keys"))));
+        assert_eq!(manifest.creator().unwrap().id(), &cid("creator"));
+        assert_eq!(manifest.creator().unwrap().public_key(), &[4, 0xc9, 0x68]);
+        assert_eq!(manifest.signature(), Some(&[0x30, 0x44, 0x02][..]));
+    }
+
+    #[test]
+    fn manifest_json() {
+        let manifest = manifest();
+        let json: Value = serde_json::from_str(&manifest.to_json()).unwrap();
+        assert_eq!(json, serde_json::json!({
+            "program": cid("program").to_string(),
+            "name": "hello-world",
+            "keys": format!("/amb/{}", cid("keys")),
+            "creator": {
+                "id": cid("creator").to_string(),
+                "publicKey": "04c968",
+            },
+            "signature": "304402",
+        }));
+        assert_eq!(Manifest::from_json(&manifest.to_json()).unwrap(), manifest);
+
+        let bare = Manifest::new(&cid("program"), "hello-world", None, None, None);
+        assert_eq!(bare.to_json(), format!(r#"{{"name":"hello-world","program":"{}"}}"#, cid("program")));
+        assert_eq!(Manifest::from_json(&bare.to_json()).unwrap(), bare);
+    }
+
+    #[test]
+    fn manifest_cbor() {
+        let manifest = manifest();
+        let ipld = dag_cbor::decode(&manifest.to_cbor()).unwrap();
+        assert_eq!(ipld.get("program"), Some(&Ipld::Link(cid("program"))));
+        assert_eq!(ipld.get("keys"), Some(&Ipld::String(format!("/amb/{}", cid("keys")))));
+        assert_eq!(ipld.get("creator").and_then(|c| c.get("publicKey")), Some(&Ipld::Bytes(vec![4, 0xc9, 0x68])));
+        assert_eq!(ipld.get("signature"), Some(&Ipld::Bytes(vec![0x30, 0x44, 0x02])));
+        assert_eq!(Manifest::from_cbor(&manifest.to_cbor()).unwrap(), manifest);
+
+        let bare = Manifest::new(&cid("program"), "hello-world", None, None, None);
+        assert_eq!(Manifest::from_cbor(&bare.to_cbor()).unwrap(), bare);
+        assert_ne!(bare.cid(), manifest.cid());
+    }
+
+    #[test]
+    fn manifest_sign_verify() {
+        let keypair = Keypair::generate();
+        let mut manifest = Manifest::new(&cid("program"), "hello-world", None, None, None);
+        assert_eq!(manifest.verify(&cid("program")), Err(VerifyError::Unsigned));
+
+        manifest.sign(&keypair).unwrap();
+        let creator = manifest.creator().unwrap();
+        assert_eq!(creator.public_key(), &keypair.public_key().encode()[..]);
+        assert_eq!(creator, &Creator::of(&keypair.public_key()));
+        assert_eq!(manifest.verify(&cid("program")), Ok(()));
+        assert_eq!(Manifest::from_json(&manifest.to_json()).unwrap().verify(&cid("program")), Ok(()));
+        assert_eq!(Manifest::from_cbor(&manifest.to_cbor()).unwrap().verify(&cid("program")), Ok(()));
+    }
+
+    #[test]
+    fn manifest_verify_rejects() {
+        let keypair = Keypair::generate();
+        let mut manifest = Manifest::new(&cid("program"), "hello-world", None, None, None);
+        manifest.sign(&keypair).unwrap();
+
+        assert_eq!(manifest.verify(&cid("other")), Err(VerifyError::ProgramMismatch));
+
+        let mut renamed = manifest.clone();
+        renamed.name = "goodbye-world".to_string();
+        assert_eq!(renamed.verify(&cid("program")), Err(VerifyError::BadSignature));
+
+        let mut forged = manifest.clone();
+        forged.signature.as_mut().unwrap()[10] ^= 1;
+        assert_eq!(forged.verify(&cid("program")), Err(VerifyError::BadSignature));
+
+        // The other valid ECDSA signature, with `s` negated.
+        let mut malleated = manifest.clone();
+        let mut signature = secp256k1::Signature::parse_der(manifest.signature().unwrap()).unwrap();
+        signature.s = -signature.s;
+        malleated.signature = Some(signature.serialize_der().as_ref().to_vec());
+        assert_eq!(malleated.verify(&cid("program")), Err(VerifyError::BadSignature));
+
+        let mut impostor = manifest.clone();
+        impostor.creator.as_mut().unwrap().id = cid("someone else");
+        assert_eq!(impostor.verify(&cid("program")), Err(VerifyError::CreatorMismatch));
+
+        let mut other_key = manifest.clone();
+        other_key.creator = Some(Creator::of(&Keypair::generate().public_key()));
+        assert_eq!(other_key.verify(&cid("program")), Err(VerifyError::BadSignature));
+
+        let mut bad_key = manifest.clone();
+        bad_key.creator.as_mut().unwrap().public_key = vec![4; 10];
+        assert_eq!(bad_key.verify(&cid("program")), Err(VerifyError::BadPublicKey));
+
+        let mut resigned = manifest;
+        resigned.program_cid = cid("other");
+        resigned.sign(&Keypair::generate()).unwrap();
+        assert_eq!(resigned.verify(&cid("program")), Err(VerifyError::ProgramMismatch));
+        assert_eq!(resigned.verify(&cid("other")), Ok(()));
+    }
+
+    #[test]
+    fn manifest_sign_verify_ed25519() {
+        let keypair = ed25519::Keypair::generate();
+        let mut manifest = Manifest::new(&cid("program"), "hello-world", None, None, None);
+        manifest.sign(&keypair).unwrap();
+        assert_eq!(manifest.creator().unwrap().public_key()[..2], [0xed, 0x01]);
+        assert_eq!(manifest.signature().unwrap().len(), 64);
+        assert_eq!(manifest.verify(&cid("program")), Ok(()));
+        assert_eq!(Manifest::from_json(&manifest.to_json()).unwrap().verify(&cid("program")), Ok(()));
+    }
+
+    #[test]
+    fn manifest_verify_rejects_cross_scheme() {
+        let secp256k1 = Keypair::generate();
+        let ed25519 = ed25519::Keypair::generate();
+        let mut manifest = Manifest::new(&cid("program"), "hello-world", None, None, None);
+        manifest.sign(&secp256k1).unwrap();
+
+        // A secp256k1 signature under an Ed25519 creator, and the other way around.
+        let mut swapped = manifest.clone();
+        swapped.creator = Some(Creator::of(&ed25519.public_key()));
+        assert_eq!(swapped.verify(&cid("program")), Err(VerifyError::BadSignature));
+
+        let mut other = Manifest::new(&cid("program"), "hello-world", None, None, None);
+        other.sign(&ed25519).unwrap();
+        other.signature = manifest.signature.clone();
+        assert_eq!(other.verify(&cid("program")), Err(VerifyError::BadSignature));
+        other.creator = manifest.creator.clone();
+        other.signature = Some(Signer::sign(&ed25519, &Manifest { signature: None, ..other.clone() }.to_cbor()).unwrap());
+        assert_eq!(other.verify(&cid("program")), Err(VerifyError::BadSignature));
+
+        // The secp256k1 key relabelled as an Ed25519 key.
+        let mut relabelled = manifest;
+        let mut public_key = relabelled.creator.as_ref().unwrap().public_key.clone();
+        public_key[0] = 0xed;
+        relabelled.creator = Some(Creator::new(&dag_cbor::cid(&dag_cbor::encode(&Ipld::Bytes(public_key.clone()))), &public_key));
+        assert_eq!(relabelled.verify(&cid("program")), Err(VerifyError::BadPublicKey));
+    }
+
+    #[test]
+    fn manifest_store_load() {
+        let mut store = MemoryStore::new();
+        let mut manifest = Manifest::new(&cid("program"), "hello-world", None, None, None);
+        manifest.sign(&ed25519::Keypair::generate()).unwrap();
+        let stored = manifest.store(&mut store).unwrap();
+        assert_eq!(stored, manifest.cid());
+        assert_eq!(Manifest::load(&stored, &store), Ok(manifest));
+
+        assert_eq!(Manifest::load(&cid("program"), &store), Err(ManifestError::NotFound(cid("program"))));
+        let other = store.put_cbor(&dag_cbor::encode(&Ipld::Null)).unwrap();
+        assert_eq!(Manifest::load(&other, &store), Err(ManifestError::Invalid("manifest")));
+    }
+
+    #[test]
+    fn address_display_parse() {
+        let address = Address::new(&cid("program"));
+        assert_eq!(address.to_string(), format!("/amb/{}", cid("program")));
+        assert_eq!(address.to_string().parse(), Ok(address.clone()));
+
+        let nested = address.join("x").unwrap().join("call").unwrap();
+        assert_eq!(nested.to_string(), format!("/amb/{}/x/call", cid("program")));
+        assert_eq!(nested.to_string().parse(), Ok(nested.clone()));
+        assert_eq!(nested.path(), &["x".to_string(), "call".to_string()]);
+        assert_eq!(nested.root(), address);
+        assert_eq!(nested.hash(), &cid("program"));
+        assert_eq!(nested.protocol(), "amb");
+
+        // The base58 CIDs of the whitepaper.
+        let zdpu = "/amb/zdpuAwAdomEUPx54FZVLt33ZeGZ5VrJkTgLxQiUZNBwZ3kr7e";
+        let parsed: Address = zdpu.parse().unwrap();
+        assert_eq!(parsed.hash(), &Cid::try_from("zdpuAwAdomEUPx54FZVLt33ZeGZ5VrJkTgLxQiUZNBwZ3kr7e").unwrap());
+    }
+
+    #[test]
+    fn address_errors() {
+        let program = cid("program");
+        let parse = |s: String| s.parse::<Address>();
+        assert_eq!(parse(format!("amb/{}", program)), Err(AddressError::NotAbsolute));
+        assert_eq!(parse(String::new()), Err(AddressError::NotAbsolute));
+        assert_eq!(parse("/".to_string()), Err(AddressError::MissingProtocol));
+        assert_eq!(parse(format!("//{}", program)), Err(AddressError::MissingProtocol));
+        assert_eq!(parse(format!("/ipfs/{}", program)), Err(AddressError::UnsupportedProtocol("ipfs".to_string())));
+        assert_eq!(parse("/amb".to_string()), Err(AddressError::MissingCid));
+        assert_eq!(parse("/amb/".to_string()), Err(AddressError::MissingCid));
+        assert!(matches!(parse("/amb/nope".to_string()), Err(AddressError::BadCid(_))));
+        assert_eq!(parse(format!("/amb/{}/", program)), Err(AddressError::BadName(String::new())));
+        assert_eq!(parse(format!("/amb/{}/x//y", program)), Err(AddressError::BadName(String::new())));
+        assert_eq!(parse(format!("/amb/{}/x.y", program)), Err(AddressError::BadName("x.y".to_string())));
+        assert_eq!(parse(format!("/amb/{}/a1", program)), Err(AddressError::BadName("a1".to_string())));
+        assert_eq!(Address::new(&program).join("a[b]"), Err(AddressError::BadName("a[b]".to_string())));
+        assert_eq!(Address::new(&program).join("x9"), Err(AddressError::BadName("x9".to_string())));
+    }
+
+    #[test]
+    fn address_round_trip() {
+        let address = Address::new(&cid("program"));
+        assert_eq!(address.protocol(), PROTOCOL);
+        assert_eq!(address.to_string().parse(), Ok(address.clone()));
+
+        // Every name the grammar accepts, including `0`.
+        for name in &["a", "Hello_world", "x-0", "0", "_", "-"] {
+            assert!(ambients_parser::parse(&format!("{}[]", name)).is_ok(), "{}", name);
+            let nested = address.join(name).unwrap();
+            assert_eq!(nested.to_string().parse(), Ok(nested.clone()));
+        }
+        for name in &["a1", "9", "é"] {
+            assert!(ambients_parser::parse(&format!("{}[]", name)).is_err(), "{}", name);
+            assert_eq!(address.join(name), Err(AddressError::BadName(name.to_string())));
+        }
+    }
+
+    #[test]
+    fn manifest_decode_errors() {
+        let program = cid("program");
+        let json = |s: String| Manifest::from_json(&s);
+        assert!(matches!(json("[]".to_string()), Err(ManifestError::Invalid("manifest"))));
+        assert!(matches!(json("{".to_string()), Err(ManifestError::Json(_))));
+        assert_eq!(json(r#"{"name":"a"}"#.to_string()), Err(ManifestError::Missing("program")));
+        assert_eq!(json(format!(r#"{{"program":"{}"}}"#, program)), Err(ManifestError::Missing("name")));
+        assert_eq!(json(r#"{"program":"nope","name":"a"}"#.to_string()), Err(ManifestError::Invalid("program")));
+        assert_eq!(json(format!(r#"{{"program":"{}","name":1}}"#, program)), Err(ManifestError::Invalid("name")));
+        assert_eq!(json(format!(r#"{{"program":"{}","name":"a","keys":"amb/{}"}}"#, program, program)), Err(ManifestError::Invalid("keys")));
+        assert_eq!(json(format!(r#"{{"program":"{}","name":"a","creator":{{"id":"{}"}}}}"#, program, program)), Err(ManifestError::Invalid("creator")));
+        assert_eq!(json(format!(r#"{{"program":"{}","name":"a","signature":"xyz"}}"#, program)), Err(ManifestError::Invalid("signature")));
+        assert_eq!(json(format!(r#"{{"program":"{}","name":"a","extra":1}}"#, program)), Err(ManifestError::Invalid("manifest")));
+
+        assert_eq!(Manifest::from_cbor(&[0xff]), Err(ManifestError::Cbor(dag_cbor::DecodeError::Unsupported(0xff))));
+        let mut ipld = manifest().to_ipld();
+        if let Ipld::Map(map) = &mut ipld {
+            map.insert("program".to_string(), Ipld::String(program.to_string()));
+        }
+        assert_eq!(Manifest::from_ipld(&ipld), Err(ManifestError::Invalid("program")));
+    }
+}
@@ -16,8 +16,6 @@
 //! Next, we'll define what values are in Ambients as they define the ultimate result of all protocol primitives - to encode a distributed program as a function that reduces to a value. We will then continue to define the protocol primitives.
 use std::{ fmt, fmt::Debug as Debug, fmt::Display as Display };
 
-use crate::ambient::Ambient;
-
 /// A tuple containing an OpCode and a Target
 ///
 /// It is important to ensure that programs deployed to the network keep the information
@@ -36,10 +34,10 @@ use crate::ambient::Ambient;
 /// from the network, the bytecode hasn't been tampered with. By sharing the hash of the
 /// bytecode of the program, the program can be discovered in the network and
 /// included in other programs as a dependency.
-#[derive(Debug)]
-struct Instruction<'a, O, T> where O: OpCode, T: Target + 'a {
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Instruction<O, T> where O: OpCode, T: Target {
     opcode: O,
-    target: &'a T
+    target: T
 }
 
 /// Marker trait for the Capability, Computation, and Distribution enums, capturing the type of
@@ -59,6 +57,15 @@ struct Instruction<'a, O, T> where O: OpCode, T: Target + 'a {
 /// 7: open_
 /// ```
 ///
+/// followed by the opcodes that group the instructions of an ambient into steps taken one after
+/// another and processes run in parallel:
+///
+/// ```text
+///  8: seq
+///  9: par
+/// 10: end
+/// ```
+///
 /// We then define opcodes for the computation and distribution primitives of the protocol:
 ///
 /// ```text
@@ -67,7 +74,7 @@ struct Instruction<'a, O, T> where O: OpCode, T: Target + 'a {
 /// 2: arg
 /// 3: return
 /// ```
-trait OpCode {}
+pub trait OpCode {}
 
 /// Events specific to the execution model: `create`, `deploy`, `in`, `in_`, `out`, `out_`, `open`,
 /// `open_`.
@@ -76,16 +83,30 @@ trait OpCode {}
 /// define a set of opcodes for the events specfic to the execution model
 /// and the opcodes for the Robust Ambient calculus terms, the
 /// capabilities and co-capabilities:
-#[derive(Debug)]
-enum Capability {
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Capability {
+    /// Creates a nested ambient.
     create = 0,
+    /// Deploys a program to the network.
     deploy = 1,
+    /// Enters a sibling ambient.
     r#in = 2,
+    /// Allows an ambient to enter.
     in_ = 3,
+    /// Exits the parent ambient.
     out = 4,
+    /// Allows a nested ambient to exit.
     out_ = 5,
+    /// Dissolves an ambient, revealing its contents.
     open = 6,
-    open_ = 7
+    /// Allows the ambient to be opened.
+    open_ = 7,
+    /// Starts a block of steps taken one after another, e.g. `in a.open b`.
+    seq = 8,
+    /// Starts a block of processes run in parallel, as a step of a `seq` block.
+    par = 9,
+    /// Ends the innermost `seq` or `par` block.
+    end = 10,
 }
 
 impl OpCode for Capability {}
@@ -100,7 +121,10 @@ impl Display for Capability {
             Capability::out => write!(f, "4 out"),
             Capability::out_ => write!(f, "5 out_"),
             Capability::open => write!(f, "6 open"),
-            Capability::open_ => write!(f, "7 open_")
+            Capability::open_ => write!(f, "7 open_"),
+            Capability::seq => write!(f, "8 seq"),
+            Capability::par => write!(f, "9 par"),
+            Capability::end => write!(f, "10 end"),
         }
     }
 }
@@ -169,8 +193,8 @@ impl Display for Capability {
 /// ```
 ///
 /// Functions that expect more than zero parameters are generally ones that do more computation. Single-argument functions that return values are necessary for expressing transformations from input to output value. Single-argument functions that return functions enable [_currying_](https://en.wikipedia.org/wiki/Currying), which is how functions with more than one argument can be expressed.
-#[derive(Debug)]
-enum Computation {
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Computation {
     /// The `func` primitive defines a computational context for function evaluation. It
     /// establishes an evaluation scope and its behavior is similar to the widely established
     /// concept of function scoping.
@@ -244,8 +268,8 @@ enum Computation {
 /// functions. In addition to function definition and evaluation, distribution of the functions
 /// is crucial for the protocol. The Ambients protocol defines two primitives, `call` and
 /// `return`, for controlled, safe, and modular distribution of programs and data.
-#[derive(Debug)]
-enum Distribution {
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Distribution {
     /// The `call` primitive allows functions to call other functions which may be local or remote. Therefore, invoking a `call` can be seen as a starting point for distributing computational workload in any program.
     ///
     /// Informally, a function `x`, which calls function `y`, creates a `call` primitive defined as:
@@ -331,24 +355,89 @@ impl Display for Distribution {
 /// the name of the target ambient. For the co-capability open_ , the target
 /// is not used - instead, always use 0 as the target opcode. That is, open_
 /// compiles to (7, 0) .
-
 pub trait Target {
 }
 
 impl Target for Computation { }
 impl Target for Distribution { }
+impl<T: Target> Target for &T { }
+
+/// The target of a compiled capability: a protocol primitive, any other ambient by name, or no
+/// target at all.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operand<'a> {
+    /// The `func` or `arg` primitive.
+    Computation(Computation),
+    /// The `call` or `return` primitive.
+    Distribution(Distribution),
+    /// An ambient that isn't a protocol primitive.
+    Name(&'a str),
+    /// No target, as for co-capabilities written without one, e.g. `open_`. Compiles to `0`.
+    Any,
+}
+
+impl<'a> Operand<'a> {
+    /// The operand referring to the ambient `name`. The parser names the target of bare
+    /// co-capabilities `"*"`, which becomes `Operand::Any`.
+    pub fn from_name(name: &'a str) -> Operand<'a> {
+        match name {
+            "func" => Operand::Computation(Computation::func),
+            "arg" => Operand::Computation(Computation::arg),
+            "call" => Operand::Distribution(Distribution::call),
+            "return" => Operand::Distribution(Distribution::r#return),
+            "*" => Operand::Any,
+            name => Operand::Name(name),
+        }
+    }
+
+    /// The ambient name this operand refers to, the inverse of `from_name`.
+    pub fn name(&self) -> &'a str {
+        match self {
+            Operand::Computation(Computation::func) => "func",
+            Operand::Computation(Computation::arg) => "arg",
+            Operand::Distribution(Distribution::call) => "call",
+            Operand::Distribution(Distribution::r#return) => "return",
+            Operand::Name(name) => name,
+            Operand::Any => "*",
+        }
+    }
+}
+
+impl<'a> Target for Operand<'a> { }
+
+impl<'a> Display for Operand<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operand::Computation(c) => write!(f, "{}", c),
+            Operand::Distribution(d) => write!(f, "{}", d),
+            Operand::Name(name) => write!(f, "{:?}", name),
+            Operand::Any => write!(f, "0"),
+        }
+    }
+}
 
-impl<'a, O, T> Instruction<'a, O, T>
+impl<O, T> Instruction<O, T>
 where O: OpCode,
-      T: Target + 'a {
-    fn new (opcode: O, target: &T) -> Instruction<O, T> {
+      T: Target {
+    /// Pairs an opcode with its target.
+    pub fn new (opcode: O, target: T) -> Instruction<O, T> {
         Instruction{ opcode, target }
     }
+
+    /// The type of the instruction.
+    pub fn opcode(&self) -> &O {
+        &self.opcode
+    }
+
+    /// What the instruction acts on.
+    pub fn target(&self) -> &T {
+        &self.target
+    }
 }
 
-impl<'a, O, T> Display for Instruction<'a, O, T>
+impl<O, T> Display for Instruction<O, T>
 where O: OpCode + Display,
-      T: Target + Display + 'a {
+      T: Target + Display {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "({}, {})", &self.opcode, &self.target)
     }
@@ -360,6 +449,16 @@ mod tests {
     //! What happens if I document the tests module?
 
     use super::*;
+    use crate::ambient::Ambient;
+    use crate::reduction::{ reduce, trace };
+    use crate::congruence::congruent;
+    use ambients_parser::parse;
+
+    fn assert_reduces_to(program: &str, expected: &str) {
+        let reduced = reduce(parse(program).unwrap());
+        let expected = parse(expected).unwrap();
+        assert!(congruent(&reduced, &expected), "{} ≢ {}", reduced, expected);
+    }
 
     #[test]
     fn instruction_display() {
@@ -380,6 +479,12 @@ mod tests {
         assert_eq!(r#"(6 open, "ambient")"#, format!("{}", instruction));
         let instruction = Instruction::new(Capability::open_, &ambient);
         assert_eq!(r#"(7 open_, "ambient")"#, format!("{}", instruction));
+        let instruction = Instruction::new(Capability::seq, &Operand::Any);
+        assert_eq!(r#"(8 seq, 0)"#, format!("{}", instruction));
+        let instruction = Instruction::new(Capability::par, &Operand::Any);
+        assert_eq!(r#"(9 par, 0)"#, format!("{}", instruction));
+        let instruction = Instruction::new(Capability::end, &Operand::Any);
+        assert_eq!(r#"(10 end, 0)"#, format!("{}", instruction));
         let instruction = Instruction::new(Computation::func, &Computation::func);
         assert_eq!(r#"(0 func, 0 func)"#, format!("{}", instruction));
         let instruction = Instruction::new(Computation::arg, &Computation::arg);
@@ -389,4 +494,110 @@ mod tests {
         let instruction = Instruction::new(Distribution::call, &Distribution::call);
         assert_eq!(r#"(1 call, 1 call)"#, format!("{}", instruction));
     }
+
+    /// Asserts that the reduction of the first term in `chain` goes through terms congruent to
+    /// every following one.
+    fn assert_reduction_chain(chain: &[&str]) {
+        let steps: Vec<_> = trace(parse(chain[0]).unwrap()).map(|s| s.exec).collect();
+        assert_eq!(steps.len(), chain.len() - 1);
+        for (step, expected) in steps.iter().zip(&chain[1..]) {
+            let expected = parse(expected).unwrap();
+            assert!(congruent(step, &expected), "{} ≢ {}", step, expected);
+        }
+    }
+
+    #[test]
+    fn func_reduction() {
+        assert_reduces_to("func[in_ x.open x.open_] | x[in func.open_|result[]] | open func", "result[]");
+        assert_reduction_chain(&[
+            "func[in_ x.open x.open_] | x[in func.open_|result[]] | open func",
+            "func[x[open_|result[]] | open x.open_] | open func",
+            "func[result[] | open_]  | open func",
+            "result[]",
+        ]);
+    }
+
+    #[test]
+    fn arg_reduction() {
+        let program = "
+arg[in_ x.open x.in y.open_] | x[in arg.open_|input[]] |
+y[in_ arg.open arg.in func.open_] |
+func[in_ y.open y.open_]
+";
+        assert_reduces_to(program, "func[open_ | input[]]");
+        assert_reduction_chain(&[
+            program,
+            "arg[open x.in y.open_ | x[open_|input[]] ] |
+             y[in_ arg.open arg.in func.open_] |
+             func[in_ y.open y.open_]",
+            "arg[in y.open_|input[]] |
+             y[in_ arg.open arg.in func.open_] |
+             func[in_ y.open y.open_]",
+            "y[open arg.in func.open_|arg[open_|input[]]] |
+             func[in_ y.open y.open_]",
+            "y[in func.open_|input[]] |
+             func[in_ y.open y.open_]",
+            "func[open y.open_ | y[open_|input[]]]",
+            "func[open_ | input[]]",
+        ]);
+    }
+
+    #[test]
+    fn function_expression_reduction() {
+        let program = "
+message[
+  in func.open_|
+  func[
+    x[in_ arg.open arg.in message.open_]|
+    message[in_ x.open x]|
+    in_ arg.open_
+  ]
+] |
+func[
+  in_ message.open message.open func.open_|
+  arg[
+    in func.in x.open_|
+    string[hello[]]
+  ]
+]|
+open func
+";
+        assert_reduces_to(program, "message[string[hello[]]]");
+        assert_reduces_to("func[open_|string[hello[]]] | open func", "string[hello[]]");
+    }
+
+    #[test]
+    fn call_reduction() {
+        let program = "
+x[call[out x.in y.open_|payload[]] | out_ call] |
+y[in_ call.open call]
+";
+        assert_reduces_to(program, "x[] | y[payload[]]");
+        assert_reduction_chain(&[
+            program,
+            "x[] | call[in y.open_|payload[]] | y[in_ call.open call]",
+            "x[] | y[call[open_|payload[]] | open call]",
+            "x[] | y[payload[]]",
+        ]);
+    }
+
+    #[test]
+    fn return_reduction() {
+        let program = "
+x[
+  call[out x.in y.open_|return[open_.in x]]|
+  out_ call.in_ y
+] |
+y[in_ call.open call.open return]
+";
+        assert_reduces_to(program, "x[y[]]");
+        assert_reduction_chain(&[
+            program,
+            "x[in_ y] | call[in y.open_|return[open_.in x]] | y[in_ call.open call.open return]",
+            "x[in_ y] | y[call[open_|return[open_.in x]]|open call.open return]",
+            "x[in_ y] | y[return[open_.in x]|open return]",
+            "x[in_ y] | y[in x]",
+            "x[y[]]",
+        ]);
+    }
 }
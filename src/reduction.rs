@@ -0,0 +1,442 @@
+//! Reduction of parsed ambient expressions under the Robust Ambient (ROAM) calculus.
+//!
+//! Programs are reduced by repeatedly applying the three reduction rules of the calculus, each of
+//! which pairs a capability with its matching co-capability:
+//!
+//! ```text
+//! a[in b.P | Q] | b[in_ a.R | S]    → b[a[P | Q] | R | S]
+//! b[a[out b.P | Q] | out_ a.R | S]  → a[P | Q] | b[R | S]
+//! open a.P | a[open_ a.Q | R]       → P | Q | R
+//! ```
+//!
+//! A co-capability without a name, like the bare `in_` or `open_` in `func[in_ x.open x.open_]`,
+//! is parsed as the wildcard `"*"` and matches any ambient.
+//!
+//! Every reduction consumes at least one capability and ROAM has no replication, so every
+//! program reaches a normal form in a finite number of steps.
+
+use crate::prelude::*;
+use ambients_parser::ast::{ Exec, Span };
+
+/// The name the parser gives to co-capabilities written without a target, e.g. `open_`.
+const ANY: &str = "*";
+
+/// The reduction rules of the calculus, named after the capability that fires.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rule {
+    /// `a[in b.P | Q] | b[in_ a.R | S] → b[a[P | Q] | R | S]`
+    In,
+    /// `b[a[out b.P | Q] | out_ a.R | S] → a[P | Q] | b[R | S]`
+    Out,
+    /// `open a.P | a[open_ a.Q | R] → P | Q | R`
+    Open,
+}
+
+impl Rule {
+    /// The name of the capability that fires: `in`, `out` or `open`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Rule::In => "in",
+            Rule::Out => "out",
+            Rule::Open => "open",
+        }
+    }
+}
+
+/// The redex a reduction step fired.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Redex<'a> {
+    /// The rule that was applied.
+    pub rule: Rule,
+    /// The ambient the rule acted on: the ambient that moved for `in` and `out`, the ambient that
+    /// was dissolved for `open`.
+    pub ambient: &'a str,
+    /// The ambient that was entered for `in` or exited for `out`. `None` for `open`.
+    pub target: Option<&'a str>,
+    /// Names of the ambients enclosing the redex, outermost first. Empty at the top level.
+    pub path: Vec<&'a str>,
+}
+
+impl<'a> Display for Redex<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.path.is_empty() {
+            write!(f, "{}: ", self.path.join("/"))?;
+        }
+        match (self.rule, self.target) {
+            (Rule::In, Some(target)) => write!(f, "{} in {}", self.ambient, target),
+            (Rule::Out, Some(target)) => write!(f, "{} out {}", self.ambient, target),
+            _ => write!(f, "open {}", self.ambient),
+        }
+    }
+}
+
+/// A single reduction step: the redex that fired and the term it reduced to.
+#[derive(Debug, Clone)]
+pub struct Step<'a> {
+    /// The redex that fired.
+    pub redex: Redex<'a>,
+    /// The term after the step.
+    pub exec: Exec<'a>,
+}
+
+/// Iterator over the steps of a reduction, see [`trace`](fn.trace.html).
+#[derive(Debug, Clone)]
+pub struct Trace<'a> {
+    exec: Option<Exec<'a>>,
+}
+
+impl<'a> Iterator for Trace<'a> {
+    type Item = Step<'a>;
+
+    fn next(&mut self) -> Option<Step<'a>> {
+        let step = step(self.exec.as_ref()?);
+        self.exec = step.as_ref().map(|s| s.exec.clone());
+        step
+    }
+}
+
+/// Performs a single reduction step, returning `None` when `exec` is already in normal form.
+///
+/// Redexes are searched outermost first and left to right, so the same term always reduces the
+/// same way.
+///
+/// The nil process `0`, which is what remains after e.g. `open a | a[open_]` is reduced, is
+/// represented as an empty `Exec::Parallel`.
+pub fn step<'a>(exec: &Exec<'a>) -> Option<Step<'a>> {
+    let procs = components(exec.clone());
+    reduce_within(&procs).map(|(redex, procs)| Step { redex, exec: compose(procs) })
+}
+
+/// Reduces `exec` one step at a time, yielding every intermediate term along with the redex that
+/// produced it. The last term yielded is the normal form.
+///
+/// ```text
+///  func[in_ x.open x.open_] | x[in func.open_|result[]] | open func
+/// → func[x[open_|result[]] | open x.open_] | open func      x in func
+/// → func[result[] | open_] | open func                      func: open x
+/// → result[]                                                open func
+/// ```
+pub fn trace(exec: Exec) -> Trace {
+    Trace { exec: Some(exec) }
+}
+
+/// Reduces `exec` until no redex remains.
+///
+/// ```text
+///  func[in_ x.open x.open_] | x[in func.open_|result[]] | open func
+/// → result[]
+/// ```
+pub fn reduce(exec: Exec) -> Exec {
+    let mut exec = exec;
+    while let Some(next) = step(&exec) {
+        exec = next.exec;
+    }
+    exec
+}
+
+/// Tries every rule at this level first, then descends into the nested ambients.
+fn reduce_within<'a>(procs: &[Exec<'a>]) -> Option<(Redex<'a>, Vec<Exec<'a>>)> {
+    if let Some(reduced) = reduce_open(procs).or_else(|| reduce_in(procs)).or_else(|| reduce_out(procs)) {
+        return Some(reduced);
+    }
+
+    procs.iter().enumerate().find_map(|(i, p)| {
+        let (name, body) = as_ambient(p)?;
+        let (mut redex, body) = reduce_within(&body)?;
+        redex.path.insert(0, name);
+        Some((redex, splice(procs, i, vec![ambient(name, body, p.span())])))
+    })
+}
+
+/// `open a.P | a[open_ a.Q | R] → P | Q | R`
+fn reduce_open<'a>(procs: &[Exec<'a>]) -> Option<(Redex<'a>, Vec<Exec<'a>>)> {
+    for (i, p) in procs.iter().enumerate() {
+        let (target, rest) = match prefix(p) {
+            Some((Exec::Open(target, _), rest)) => (*target, rest),
+            _ => continue,
+        };
+
+        for (j, q) in procs.iter().enumerate() {
+            let body = match as_ambient(q) {
+                Some((name, body)) if i != j && name == target => body,
+                _ => continue,
+            };
+
+            let k = match body.iter().position(|b| matches!(prefix(b), Some((Exec::Open_(n, _), _)) if accepts(n, target))) {
+                Some(k) => k,
+                None => continue,
+            };
+            let (_, q_rest) = prefix(&body[k]).unwrap();
+            let opened = splice(&body, k, continuation(q_rest));
+
+            let mut reduced = Vec::new();
+            for (n, e) in procs.iter().enumerate() {
+                if n == i {
+                    reduced.extend(continuation(rest));
+                } else if n == j {
+                    reduced.extend(opened.iter().cloned());
+                } else {
+                    reduced.push(e.clone());
+                }
+            }
+            let redex = Redex { rule: Rule::Open, ambient: target, target: None, path: vec![] };
+            return Some((redex, reduced));
+        }
+    }
+    None
+}
+
+/// `a[in b.P | Q] | b[in_ a.R | S] → b[a[P | Q] | R | S]`
+fn reduce_in<'a>(procs: &[Exec<'a>]) -> Option<(Redex<'a>, Vec<Exec<'a>>)> {
+    for (i, p) in procs.iter().enumerate() {
+        let (name, body) = match as_ambient(p) {
+            Some(ambient) => ambient,
+            None => continue,
+        };
+        let capabilities = body.iter().enumerate().filter_map(|(k, b)| match prefix(b) {
+            Some((Exec::In(target, _), rest)) => Some((k, *target, rest)),
+            _ => None,
+        });
+
+        for (k, target, rest) in capabilities {
+            for (j, q) in procs.iter().enumerate() {
+                let (host, host_body) = match as_ambient(q) {
+                    Some((host, host_body)) if i != j && host == target => (host, host_body),
+                    _ => continue,
+                };
+
+                let l = match host_body.iter().position(|b| matches!(prefix(b), Some((Exec::In_(n, _), _)) if accepts(n, name))) {
+                    Some(l) => l,
+                    None => continue,
+                };
+                let (_, host_rest) = prefix(&host_body[l]).unwrap();
+
+                let mut entered = vec![ambient(name, splice(&body, k, continuation(rest)), p.span())];
+                entered.extend(continuation(host_rest));
+                let host = ambient(host, splice(&host_body, l, entered), q.span());
+
+                let reduced = procs.iter().enumerate()
+                    .filter(|(n, _)| *n != i)
+                    .map(|(n, e)| if n == j { host.clone() } else { e.clone() })
+                    .collect();
+                let redex = Redex { rule: Rule::In, ambient: name, target: Some(target), path: vec![] };
+                return Some((redex, reduced));
+            }
+        }
+    }
+    None
+}
+
+/// `b[a[out b.P | Q] | out_ a.R | S] → a[P | Q] | b[R | S]`
+fn reduce_out<'a>(procs: &[Exec<'a>]) -> Option<(Redex<'a>, Vec<Exec<'a>>)> {
+    for (i, p) in procs.iter().enumerate() {
+        let (parent, parent_body) = match as_ambient(p) {
+            Some(ambient) => ambient,
+            None => continue,
+        };
+
+        for (j, q) in parent_body.iter().enumerate() {
+            let (name, body) = match as_ambient(q) {
+                Some(ambient) => ambient,
+                None => continue,
+            };
+
+            let k = match body.iter().position(|b| matches!(prefix(b), Some((Exec::Out(n, _), _)) if *n == parent)) {
+                Some(k) => k,
+                None => continue,
+            };
+            let l = match parent_body.iter().position(|b| matches!(prefix(b), Some((Exec::Out_(n, _), _)) if accepts(n, name))) {
+                Some(l) => l,
+                None => continue,
+            };
+            let (_, rest) = prefix(&body[k]).unwrap();
+            let (_, parent_rest) = prefix(&parent_body[l]).unwrap();
+
+            let exited = ambient(name, splice(&body, k, continuation(rest)), q.span());
+            let remaining = parent_body.iter().enumerate()
+                .filter(|(n, _)| *n != j)
+                .flat_map(|(n, e)| if n == l { continuation(parent_rest) } else { vec![e.clone()] })
+                .collect();
+
+            let redex = Redex { rule: Rule::Out, ambient: name, target: Some(parent), path: vec![] };
+            return Some((redex, splice(procs, i, vec![ambient(parent, remaining, p.span()), exited])));
+        }
+    }
+    None
+}
+
+/// Whether a co-capability naming `accepted` allows the ambient `name` to act on it.
+fn accepts(accepted: &str, name: &str) -> bool {
+    accepted == ANY || accepted == name
+}
+
+/// Splits a prefixed process `M.P` into its capability `M` and the remaining sequence `P`.
+fn prefix<'a, 'e>(exec: &'e Exec<'a>) -> Option<(&'e Exec<'a>, &'e [Exec<'a>])> {
+    match exec {
+        Exec::Serial(seq, _) => seq.split_first().filter(|(head, _)| is_capability(head)),
+        e if is_capability(e) => Some((e, &[])),
+        _ => None,
+    }
+}
+
+fn is_capability(exec: &Exec) -> bool {
+    matches!(exec, Exec::In(..) | Exec::In_(..) | Exec::Out(..) | Exec::Out_(..) | Exec::Open(..) | Exec::Open_(..))
+}
+
+/// The parallel components left after the capability in front of `rest` has been consumed.
+fn continuation<'a>(rest: &[Exec<'a>]) -> Vec<Exec<'a>> {
+    match rest {
+        [] => vec![],
+        [last] => components(last.clone()),
+        _ => vec![Exec::Serial(rest.to_vec(), covering(rest))],
+    }
+}
+
+/// Views `exec` as an ambient, returning its name and the parallel components of its body.
+fn as_ambient<'a>(exec: &Exec<'a>) -> Option<(&'a str, Vec<Exec<'a>>)> {
+    match exec {
+        Exec::Noop(name, _) => Some((name, vec![])),
+        Exec::Ambient(name, body, _) => Some((name, components((**body).clone()))),
+        _ => None,
+    }
+}
+
+/// Flattens nested `Parallel` and `Group` nodes into a list of parallel components.
+pub(crate) fn components(exec: Exec) -> Vec<Exec> {
+    match exec {
+        Exec::Parallel(procs, _) => procs.into_iter().flat_map(components).collect(),
+        Exec::Group(inner, _) => components(*inner),
+        e => vec![e],
+    }
+}
+
+/// The inverse of `components`. An empty list composes to the nil process.
+pub(crate) fn compose(mut procs: Vec<Exec>) -> Exec {
+    if procs.len() == 1 {
+        procs.pop().unwrap()
+    } else {
+        let span = covering(&procs);
+        Exec::Parallel(procs, span)
+    }
+}
+
+/// Rebuilds an ambient around `body`, keeping the `span` of the ambient it was taken from.
+fn ambient<'a>(name: &'a str, body: Vec<Exec<'a>>, span: Span) -> Exec<'a> {
+    if body.is_empty() {
+        Exec::Noop(name, span)
+    } else {
+        Exec::Ambient(name, Box::new(compose(body)), span)
+    }
+}
+
+/// The smallest span containing every one of `procs`, which may have moved since they were
+/// parsed.
+fn covering(procs: &[Exec]) -> Span {
+    procs.iter().map(Exec::span).fold(None, |acc: Option<Span>, s| Some(acc.map_or(s, |a| a.to(s))))
+        .unwrap_or_default()
+}
+
+/// Replaces `procs[index]` with `with`.
+fn splice<'a>(procs: &[Exec<'a>], index: usize, with: Vec<Exec<'a>>) -> Vec<Exec<'a>> {
+    let mut spliced = procs[..index].to_vec();
+    spliced.extend(with);
+    spliced.extend_from_slice(&procs[index + 1..]);
+    spliced
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ambients_parser::ambients::ExecutionParser as Parser;
+
+    fn assert_reduces_to(program: &str, expected: &str) {
+        let exec = Parser::new().parse(program).unwrap();
+        let expected = Parser::new().parse(expected).unwrap();
+        assert_eq!(format!("{:?}", reduce(exec)), format!("{:?}", expected));
+    }
+
+    #[test]
+    fn reduce_in() {
+        assert_reduces_to("a[in b] | b[in_ a]", "b[a[]]");
+        assert_reduces_to("a[in b.c[]] | b[in_ a.d[]|e[]]", "b[a[c[]]|d[]|e[]]");
+        assert_reduces_to("a[in b] | b[in_]", "b[a[]]");
+        assert_reduces_to("a[in b] | b[in_ c]", "a[in b] | b[in_ c]");
+    }
+
+    #[test]
+    fn reduce_out() {
+        assert_reduces_to("b[a[out b]|out_ a]", "b[] | a[]");
+        assert_reduces_to("b[a[out b.c[]]|out_ a.d[]]", "b[d[]] | a[c[]]");
+        assert_reduces_to("b[a[out b]|out_]", "b[] | a[]");
+        assert_reduces_to("b[a[out b]|out_ c]", "b[a[out b]|out_ c]");
+    }
+
+    #[test]
+    fn reduce_open() {
+        assert_reduces_to("a[b[open_|c[]]|open b]", "a[c[]]");
+        assert_reduces_to("open a.b[] | a[open_ a.c[]|d[]]", "b[] | c[] | d[]");
+        assert_reduces_to("open a | a[open_ b]", "open a | a[open_ b]");
+        assert_reduces_to("open a | a[]", "open a | a[]");
+    }
+
+    #[test]
+    fn reduce_to_nil() {
+        let exec = Parser::new().parse("open a | a[open_]").unwrap();
+        assert_eq!(format!("{:?}", reduce(exec)), format!("{:?}", Exec::Parallel(vec![], Span::default())));
+    }
+
+    #[test]
+    fn reduce_guarded() {
+        // `open a` only becomes available once `in_ b` has been consumed
+        assert_reduces_to("c[in_ b.open a | a[open_]] | b[in c]", "c[b[]]");
+        assert_reduces_to("c[in_ b.(open a | a[open_])] | b[in c]", "c[b[]]");
+    }
+
+    #[test]
+    fn reduce_keeps_spans() {
+        let exec = Parser::new().parse("a[in b] | b[in_ a]").unwrap();
+        match reduce(exec) {
+            Exec::Ambient("b", a, b_span) => {
+                assert_eq!(b_span, Span::new(10, 18));
+                assert_eq!(a.span(), Span::new(0, 7));
+            },
+            e => panic!("unexpected {:?}", e),
+        }
+    }
+
+    #[test]
+    fn step_normal_form() {
+        let exec = Parser::new().parse("a[] | b[in_ a]").unwrap();
+        assert!(step(&exec).is_none());
+        assert_eq!(trace(exec).count(), 0);
+    }
+
+    #[test]
+    fn step_redex() {
+        let exec = Parser::new().parse("a[in b] | b[in_ a]").unwrap();
+        let redex = step(&exec).unwrap().redex;
+        assert_eq!(redex, Redex { rule: Rule::In, ambient: "a", target: Some("b"), path: vec![] });
+        assert_eq!(redex.to_string(), "a in b");
+
+        let exec = Parser::new().parse("c[b[a[out b]|out_ a]]").unwrap();
+        let redex = step(&exec).unwrap().redex;
+        assert_eq!(redex, Redex { rule: Rule::Out, ambient: "a", target: Some("b"), path: vec!["c"] });
+        assert_eq!(redex.to_string(), "c: a out b");
+
+        let exec = Parser::new().parse("c[d[open a | a[open_]]]").unwrap();
+        let redex = step(&exec).unwrap().redex;
+        assert_eq!(redex, Redex { rule: Rule::Open, ambient: "a", target: None, path: vec!["c", "d"] });
+        assert_eq!(redex.to_string(), "c/d: open a");
+    }
+
+    #[test]
+    fn trace_steps() {
+        let exec = Parser::new().parse("c[in_ b.open a | a[open_]] | b[in c]").unwrap();
+        let steps: Vec<_> = trace(exec).collect();
+        let redexes: Vec<_> = steps.iter().map(|s| s.redex.to_string()).collect();
+        assert_eq!(redexes, vec!["b in c", "c: open a"]);
+        let last = &steps.last().unwrap().exec;
+        let expected = Parser::new().parse("c[b[]]").unwrap();
+        assert_eq!(format!("{:?}", last), format!("{:?}", expected));
+    }
+}
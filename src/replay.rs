@@ -0,0 +1,274 @@
+//! Deterministic verification of execution logs.
+//!
+//! Reduction is deterministic, so the log of an execution can be checked by running the program
+//! again with nothing but the events of the log. Replaying a log reads the program from a block
+//! store through its manifest, which has to be signed by its creator, checks the `deploy` and
+//! `create` entries against the program, and then applies the logged events one reduction step
+//! at a time. Each event has to be signed by a key the program's `keys` allow, has to be the step
+//! the program takes next, and has to link to the entries it causally depends on, see `log`.
+//!
+//! The log's order doesn't have to be the order the events fired in, as long as every entry is
+//! after the entries it links to: concurrent events can be logged in any order.
+
+use std::error::Error;
+
+use ambients_parser::ast::OwnedExec;
+use cid::Cid;
+
+use crate::access::AccessError;
+use crate::log::{ Log, Recorder };
+use crate::manifest::{ Manifest, ManifestError, VerifyError };
+use crate::prelude::*;
+use crate::reduction;
+use crate::runtime::{ self, Event, EventError, LoadError, Runtime };
+use crate::store::{ BlockStore, StoreError };
+
+/// Why a log didn't replay.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReplayError {
+    /// The manifest couldn't be read from the store.
+    Manifest(ManifestError),
+    /// The manifest's signature doesn't check out.
+    Verify(VerifyError),
+    /// The program's key list couldn't be resolved.
+    Access(AccessError),
+    /// The store failed to read the program.
+    Store(StoreError),
+    /// The program isn't in the store, or isn't the source of a program.
+    BadProgram(Cid),
+    /// The log is of another program.
+    ProgramMismatch(Cid),
+    /// A `deploy` or `create` entry isn't one the program has.
+    UnexpectedEntry(Cid),
+    /// A `deploy` or `create` entry the program has isn't in the log.
+    MissingEntry(Cid),
+    /// The event of an entry is forged, unauthorized, or isn't a step the program takes.
+    Event(Cid, EventError),
+    /// An entry doesn't link to the entries its event causally depends on.
+    BadLinks(Cid),
+}
+
+impl Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayError::Manifest(e) => write!(f, "invalid manifest: {}", e),
+            ReplayError::Verify(e) => write!(f, "{}", e),
+            ReplayError::Access(e) => write!(f, "{}", e),
+            ReplayError::Store(e) => write!(f, "{}", e),
+            ReplayError::BadProgram(cid) => write!(f, "{} isn't a program", cid),
+            ReplayError::ProgramMismatch(cid) => write!(f, "log is of program {}", cid),
+            ReplayError::UnexpectedEntry(cid) => write!(f, "entry {} isn't in the program", cid),
+            ReplayError::MissingEntry(cid) => write!(f, "missing entry {}", cid),
+            ReplayError::Event(cid, e) => write!(f, "entry {}: {}", cid, e),
+            ReplayError::BadLinks(cid) => write!(f, "entry {} doesn't link to the entries it depends on", cid),
+        }
+    }
+}
+
+impl Error for ReplayError {}
+
+impl From<LoadError> for ReplayError {
+    fn from(e: LoadError) -> ReplayError {
+        match e {
+            LoadError::Verify(e) => ReplayError::Verify(e),
+            LoadError::Access(e) => ReplayError::Access(e),
+            LoadError::Store(e) => ReplayError::Store(e),
+            LoadError::BadProgram(cid) => ReplayError::BadProgram(cid),
+        }
+    }
+}
+
+/// Replays `log` on the program whose manifest is `program`, read from `store`, returning the
+/// term the program is in after the last event. The first entry that doesn't check out is
+/// reported, forged and unauthorized events before any other.
+pub fn replay(program: &Cid, log: &Log, store: &dyn BlockStore) -> Result<OwnedExec, ReplayError> {
+    let manifest = Manifest::load(program, store).map_err(ReplayError::Manifest)?;
+    let source = runtime::source(&manifest, store)?;
+    let mut runtime = Runtime::load(&manifest, &source, store)?;
+    if log.program() != program {
+        return Err(ReplayError::ProgramMismatch(log.program().clone()));
+    }
+
+    let events: Vec<(&Cid, &Event)> = log.iter().filter_map(|(cid, entry)| Some((cid, entry.event()?))).collect();
+    for (cid, event) in &events {
+        let key = event.verify(program).map_err(|e| ReplayError::Event((*cid).clone(), e))?;
+        if !runtime.access().allows(&key) {
+            return Err(ReplayError::Event((*cid).clone(), EventError::Unauthorized));
+        }
+    }
+
+    let mut recorder = Recorder::new(program, runtime.exec());
+    if let Some((cid, _)) = log.iter().find(|(cid, entry)| entry.event().is_none() && recorder.log().get(cid).is_none()) {
+        return Err(ReplayError::UnexpectedEntry(cid.clone()));
+    }
+    if let Some((cid, _)) = recorder.log().iter().find(|(cid, _)| log.get(cid).is_none()) {
+        return Err(ReplayError::MissingEntry(cid.clone()));
+    }
+
+    let mut remaining = events;
+    while let Some(&(first, _)) = remaining.first() {
+        let not_enabled = || ReplayError::Event(first.clone(), EventError::NotEnabled);
+        let step = reduction::step(runtime.exec()).ok_or_else(not_enabled)?;
+        let candidates: Vec<_> = remaining.iter().enumerate().filter(|(_, (_, event))| event.is(runtime.steps(), &step.redex)).collect();
+        let &(i, &(cid, event)) = match candidates.iter().find(|(_, (cid, event))| recorder.entry((*event).clone()).cid() == **cid) {
+            Some(found) => found,
+            None => return Err(candidates.first().map_or_else(not_enabled, |(_, (cid, _))| ReplayError::BadLinks((*cid).clone()))),
+        };
+        runtime.apply(event).map_err(|e| ReplayError::Event(cid.clone(), e))?;
+        recorder.record(event.clone());
+        remaining.remove(i);
+    }
+    Ok(OwnedExec::from(runtime.exec()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::access::Access;
+    use crate::dag_cbor::Ipld;
+    use ambients_parser::parse;
+    use crate::ambient::Ambient;
+    use crate::log::{ self, Entry, Op };
+    use crate::signing::{ Keypair, Scheme, Signer };
+    use crate::store::MemoryStore;
+
+    const PROGRAM: &str = "c[in_ b.open a | a[open_]] | b[in c] | x[in_ y] | y[in x]";
+
+    /// A store with the program deployed by `deployer`, and the log of its execution by `signer`.
+    fn executed(deployer: &Keypair, signer: &Keypair) -> (Cid, MemoryStore, Log) {
+        let mut store = MemoryStore::new();
+        let ambient = Ambient::deploy("program", PROGRAM, deployer).unwrap();
+        ambient.store(&mut store).unwrap();
+        Access::Keys(vec![deployer.public_key()]).store(&mut store).unwrap();
+        let mut runtime = Runtime::load(ambient.manifest(), PROGRAM, &store).unwrap();
+        let log = log::execute(&mut runtime, signer).unwrap();
+        (ambient.cid().clone(), store, log)
+    }
+
+    /// The entries of `log`, with `f` applied to the event `text`, and the CID of its new entry.
+    fn with_event(log: &Log, text: &str, f: impl Fn(&mut Event)) -> (Vec<Entry>, Cid) {
+        let cid = named(log, text);
+        let entry = log.get(&cid).unwrap();
+        let mut event = entry.event().unwrap().clone();
+        f(&mut event);
+        let changed = Entry::new(entry.program(), Op::Fire(event), entry.next().to_vec(), entry.clock());
+        let entries = log.iter().filter(|(other, _)| **other != cid).map(|(_, entry)| entry.clone());
+        (entries.chain(Some(changed.clone())).collect(), changed.cid())
+    }
+
+    fn named(log: &Log, text: &str) -> Cid {
+        log.iter().find(|(_, entry)| entry.to_string() == text).unwrap().0.clone()
+    }
+
+    #[test]
+    fn replay_log() {
+        let keypair = Keypair::generate(Scheme::Ed25519);
+        let (program, store, log) = executed(&keypair, &keypair);
+        let normal_form = OwnedExec::from(&reduction::reduce(parse(PROGRAM).unwrap()));
+        assert_eq!(replay(&program, &log, &store), Ok(normal_form.clone()));
+
+        // From the store, in the order the log is loaded in.
+        let mut log_store = MemoryStore::new();
+        log.store(&mut log_store).unwrap();
+        let loaded = Log::load(&log.heads(), &log_store).unwrap();
+        assert_eq!(replay(&program, &loaded, &store), Ok(normal_form));
+
+        // A log that stops early replays to where it stopped.
+        let partial = Log::from_entries(log.iter().map(|(_, entry)| entry.clone()).take(log.len() - 1)).unwrap();
+        let mut first_steps = reduction::trace(parse(PROGRAM).unwrap());
+        let expected = first_steps.nth(1).unwrap().exec;
+        assert_eq!(replay(&program, &partial, &store), Ok(OwnedExec::from(&expected)));
+    }
+
+    #[test]
+    fn replay_forged() {
+        let keypair = Keypair::generate(Scheme::Ed25519);
+        let (program, store, log) = executed(&keypair, &keypair);
+        // A different ambient under the original signature.
+        let (tampered, changed) = with_event(&log, "y in x", |event| {
+            let mut ipld = event.to_ipld();
+            if let Ipld::Map(map) = &mut ipld {
+                map.insert("ambient".to_string(), Ipld::String("x".to_string()));
+            }
+            *event = Event::from_ipld(&ipld).unwrap();
+        });
+        let tampered = Log::from_entries(tampered).unwrap();
+        assert_eq!(replay(&program, &tampered, &store), Err(ReplayError::Event(changed, EventError::BadSignature)));
+
+        let stranger = Keypair::generate(Scheme::Ed25519);
+        let (resigned, changed) = with_event(&log, "c: open a", |event| event.sign(&stranger).unwrap());
+        let resigned = Log::from_entries(resigned).unwrap();
+        assert_eq!(replay(&program, &resigned, &store), Err(ReplayError::Event(changed, EventError::Unauthorized)));
+
+        // Signed by the deployer, but the links of the entry are forged.
+        let y_in_x = named(&log, "y in x");
+        let relinked: Vec<_> = log.iter().map(|(cid, entry)| match entry.event() {
+            Some(event) if *cid == y_in_x => Entry::new(entry.program(), Op::Fire(event.clone()), vec![log.root().clone()], 1),
+            _ => entry.clone(),
+        }).collect();
+        let relinked = Log::from_entries(relinked).unwrap();
+        assert_eq!(replay(&program, &relinked, &store), Err(ReplayError::BadLinks(named(&relinked, "y in x"))));
+
+        let (_, _, foreign) = executed(&stranger, &stranger);
+        assert_eq!(replay(&program, &foreign, &store), Err(ReplayError::ProgramMismatch(foreign.program().clone())));
+    }
+
+    #[test]
+    fn replay_illegal_steps() {
+        let keypair = Keypair::generate(Scheme::Ed25519);
+        let (program, store, log) = executed(&keypair, &keypair);
+
+        // A signed event for a step the program doesn't take.
+        let mut recorder = Recorder::new(&program, &parse(PROGRAM).unwrap());
+        let mut ipld = log.events().next().unwrap().to_ipld();
+        if let Ipld::Map(map) = &mut ipld {
+            map.insert("ambient".to_string(), Ipld::String("c".to_string()));
+            map.insert("target".to_string(), Ipld::String("b".to_string()));
+        }
+        let mut extra = Event::from_ipld(&ipld).unwrap();
+        extra.sign(&keypair).unwrap();
+        let illegal = recorder.record(extra);
+        let mut entries: Vec<_> = log.iter().map(|(_, entry)| entry.clone()).collect();
+        entries.push(recorder.log().get(&illegal).unwrap().clone());
+        let with_illegal = Log::from_entries(entries).unwrap();
+        assert_eq!(replay(&program, &with_illegal, &store), Err(ReplayError::Event(illegal, EventError::NotEnabled)));
+
+        // Without `b in c`, none of the remaining events is the step the program takes next.
+        let mut recorder = Recorder::new(&program, &parse(PROGRAM).unwrap());
+        let b_in_c = log.get(&named(&log, "b in c")).unwrap().event().unwrap();
+        log.events().filter(|event| *event != b_in_c).for_each(|event| { recorder.record(event.clone()); });
+        let skipped = recorder.finish();
+        let first = skipped.iter().find(|(_, entry)| entry.event().is_some()).unwrap().0.clone();
+        assert_eq!(replay(&program, &skipped, &store), Err(ReplayError::Event(first, EventError::NotEnabled)));
+
+        // A create the program doesn't have.
+        let mut entries: Vec<_> = log.iter().map(|(_, entry)| entry.clone()).collect();
+        let create = Entry::new(&program, Op::Create { ambient: "z".to_string(), path: vec![], index: 0 }, vec![log.root().clone()], 1);
+        entries.insert(1, create.clone());
+        let with_create = Log::from_entries(entries).unwrap();
+        assert_eq!(replay(&program, &with_create, &store), Err(ReplayError::UnexpectedEntry(create.cid())));
+    }
+
+    #[test]
+    fn replay_program_errors() {
+        let keypair = Keypair::generate(Scheme::Secp256k1);
+        let (program, store, log) = executed(&keypair, &keypair);
+        let manifest = Manifest::load(&program, &store).unwrap();
+
+        let mut no_program = store.clone();
+        no_program.delete(manifest.program()).unwrap();
+        assert_eq!(replay(&program, &log, &no_program), Err(ReplayError::BadProgram(manifest.program().clone())));
+
+        let mut no_keys = store.clone();
+        let keys = manifest.keys().unwrap().hash().clone();
+        no_keys.delete(&keys).unwrap();
+        assert_eq!(replay(&program, &log, &no_keys), Err(ReplayError::Access(AccessError::Missing(keys))));
+
+        assert_eq!(replay(log.root(), &log, &store), Err(ReplayError::Manifest(ManifestError::NotFound(log.root().clone()))));
+
+        // The same program, deployed without a signature.
+        let mut unsigned = store.clone();
+        let unsigned_program = Ambient::new("program", PROGRAM).store(&mut unsigned).unwrap();
+        assert_eq!(replay(&unsigned_program, &log, &unsigned), Err(ReplayError::Verify(VerifyError::Unsigned)));
+    }
+}
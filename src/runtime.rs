@@ -0,0 +1,584 @@
+//! Execution of deployed programs under their write access.
+//!
+//! Every reduction step of a program is a capability event: the redex that fired and the number
+//! of the step, signed by the key that fired it. A runtime only applies events that match the
+//! program's next redex and step and are signed by a key the program's `keys` allow, see
+//! `access`. An event can't be applied twice, even when the program has identical redexes.
+//! `Runtime::load` runs a program read from a block store, under the keys its manifest resolves
+//! to there, once it has checked that the manifest is signed by its creator.
+//!
+//! Events are signed over their DAG-CBOR encoding without the signature:
+//!
+//! ```text
+//! {
+//!   program: <cid of the manifest>,
+//!   step: 3,                     (the events applied before it)
+//!   rule: "in" | "out" | "open",
+//!   ambient: "a",
+//!   target: "b",                 (no target for open)
+//!   path: ["c"],
+//!   key: <multicodec public key>,
+//!   signature: <signature>
+//! }
+//! ```
+
+use std::collections::BTreeMap;
+use std::error::Error;
+
+use ambients_parser::ast::Exec;
+use ambients_parser::parse;
+use cid::Cid;
+
+use crate::access::{ Access, AccessError };
+use crate::ambient::program_cid;
+use crate::dag_cbor::{ self, Ipld };
+use crate::keypair::SigningError;
+use crate::manifest::{ Manifest, VerifyError };
+use crate::prelude::*;
+use crate::reduction::{ self, Redex, Rule };
+use crate::signing::{ PublicKey, Signer, Verifier };
+use crate::store::{ BlockStore, StoreError };
+
+/// A capability event: a redex fired on a program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Event {
+    program: Cid,
+    step: u64,
+    rule: Rule,
+    ambient: String,
+    target: Option<String>,
+    path: Vec<String>,
+    key: Option<Vec<u8>>,
+    signature: Option<Vec<u8>>,
+}
+
+/// Why an event was rejected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventError {
+    /// The event has no key or no signature.
+    Unsigned,
+    /// The event is for another program.
+    ProgramMismatch,
+    /// The event's key isn't a valid key of a known scheme.
+    BadPublicKey,
+    /// The signature isn't the key's signature of the event.
+    BadSignature,
+    /// The key isn't allowed to write to the program.
+    Unauthorized,
+    /// The event isn't the program's next redex.
+    NotEnabled,
+}
+
+impl Display for EventError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EventError::Unsigned => write!(f, "event isn't signed"),
+            EventError::ProgramMismatch => write!(f, "event is for another program"),
+            EventError::BadPublicKey => write!(f, "invalid event key"),
+            EventError::BadSignature => write!(f, "signature doesn't match the event"),
+            EventError::Unauthorized => write!(f, "key isn't allowed to write to the program"),
+            EventError::NotEnabled => write!(f, "event isn't the program's next redex"),
+        }
+    }
+}
+
+impl Error for EventError {}
+
+/// Why an event couldn't be decoded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EventDecodeError {
+    /// The input isn't valid DAG-CBOR.
+    Cbor(dag_cbor::DecodeError),
+    /// A required field is missing.
+    Missing(&'static str),
+    /// A field doesn't have the type or format it should, or the event has a field it can't have.
+    Invalid(&'static str),
+}
+
+impl Display for EventDecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EventDecodeError::Cbor(e) => write!(f, "invalid DAG-CBOR: {}", e),
+            EventDecodeError::Missing(field) => write!(f, "missing event field `{}`", field),
+            EventDecodeError::Invalid(field) => write!(f, "invalid event field `{}`", field),
+        }
+    }
+}
+
+impl Error for EventDecodeError {}
+
+impl Event {
+    /// The unsigned event of `redex` firing on the program `program` after `step` other events.
+    pub fn new(program: &Cid, step: u64, redex: &Redex) -> Event {
+        Event {
+            program: program.clone(),
+            step,
+            rule: redex.rule,
+            ambient: redex.ambient.to_string(),
+            target: redex.target.map(str::to_string),
+            path: redex.path.iter().map(|name| name.to_string()).collect(),
+            key: None,
+            signature: None,
+        }
+    }
+
+    /// The CID of the manifest of the program.
+    pub fn program(&self) -> &Cid {
+        &self.program
+    }
+
+    /// The number of events applied before this one. Signing it ties the event to its place in
+    /// the execution, so that it can't be applied again to fire an identical redex.
+    pub fn step(&self) -> u64 {
+        self.step
+    }
+
+    /// The rule that fired.
+    pub fn rule(&self) -> Rule {
+        self.rule
+    }
+
+    /// The ambient the rule acted on.
+    pub fn ambient(&self) -> &str {
+        &self.ambient
+    }
+
+    /// The ambient that was entered or exited, `None` for `open`.
+    pub fn target(&self) -> Option<&str> {
+        self.target.as_deref()
+    }
+
+    /// Names of the ambients enclosing the redex, outermost first.
+    pub fn path(&self) -> &[String] {
+        &self.path
+    }
+
+    /// The multicodec-prefixed key that signed the event.
+    pub fn key(&self) -> Option<&[u8]> {
+        self.key.as_deref()
+    }
+
+    /// The signature of the event.
+    pub fn signature(&self) -> Option<&[u8]> {
+        self.signature.as_deref()
+    }
+
+    /// Whether the event is `redex` firing after `step` other events.
+    pub fn is(&self, step: u64, redex: &Redex) -> bool {
+        self.step == step
+            && self.rule == redex.rule
+            && self.ambient == redex.ambient
+            && self.target.as_deref() == redex.target
+            && self.path.iter().map(String::as_str).eq(redex.path.iter().copied())
+    }
+
+    /// Signs the event with `signer`.
+    pub fn sign(&mut self, signer: &dyn Signer) -> Result<(), SigningError> {
+        self.key = Some(signer.public_key().encode());
+        self.signature = None;
+        self.signature = Some(signer.sign(&self.to_cbor())?);
+        Ok(())
+    }
+
+    /// Checks that the event is for `program` and signed, returning the key that signed it.
+    pub fn verify(&self, program: &Cid) -> Result<PublicKey, EventError> {
+        let (key, signature) = match (&self.key, &self.signature) {
+            (Some(key), Some(signature)) => (key, signature),
+            _ => return Err(EventError::Unsigned),
+        };
+        if self.program != *program {
+            return Err(EventError::ProgramMismatch);
+        }
+        let key = PublicKey::decode(key).map_err(|_| EventError::BadPublicKey)?;
+        let unsigned = Event { signature: None, ..self.clone() };
+        if !key.verify(&unsigned.to_cbor(), signature) {
+            return Err(EventError::BadSignature);
+        }
+        Ok(key)
+    }
+
+    /// The event as an IPLD map, to be encoded as DAG-CBOR.
+    pub fn to_ipld(&self) -> Ipld {
+        let mut map = BTreeMap::new();
+        map.insert("program".to_string(), Ipld::Link(self.program.clone()));
+        map.insert("step".to_string(), Ipld::Integer(self.step as i64));
+        map.insert("rule".to_string(), Ipld::String(self.rule.as_str().to_string()));
+        map.insert("ambient".to_string(), Ipld::String(self.ambient.clone()));
+        if let Some(target) = &self.target {
+            map.insert("target".to_string(), Ipld::String(target.clone()));
+        }
+        map.insert("path".to_string(), Ipld::List(self.path.iter().cloned().map(Ipld::String).collect()));
+        if let Some(key) = &self.key {
+            map.insert("key".to_string(), Ipld::Bytes(key.clone()));
+        }
+        if let Some(signature) = &self.signature {
+            map.insert("signature".to_string(), Ipld::Bytes(signature.clone()));
+        }
+        Ipld::Map(map)
+    }
+
+    /// Reads an event from its IPLD map.
+    pub fn from_ipld(ipld: &Ipld) -> Result<Event, EventDecodeError> {
+        let map = match ipld {
+            Ipld::Map(map) if map.keys().all(|k| EVENT_FIELDS.contains(&k.as_str())) => map,
+            _ => return Err(EventDecodeError::Invalid("event")),
+        };
+        let string = |field: &'static str| match map.get(field) {
+            Some(Ipld::String(s)) => Ok(Some(s.clone())),
+            Some(_) => Err(EventDecodeError::Invalid(field)),
+            None => Ok(None),
+        };
+        let bytes = |field: &'static str| match map.get(field) {
+            Some(Ipld::Bytes(b)) => Ok(Some(b.clone())),
+            Some(_) => Err(EventDecodeError::Invalid(field)),
+            None => Ok(None),
+        };
+        let program = match map.get("program") {
+            Some(Ipld::Link(cid)) => cid.clone(),
+            Some(_) => return Err(EventDecodeError::Invalid("program")),
+            None => return Err(EventDecodeError::Missing("program")),
+        };
+        let step = match map.get("step") {
+            Some(Ipld::Integer(step)) if *step >= 0 => *step as u64,
+            Some(_) => return Err(EventDecodeError::Invalid("step")),
+            None => return Err(EventDecodeError::Missing("step")),
+        };
+        let rule = string("rule")?.ok_or(EventDecodeError::Missing("rule"))?;
+        let rule = rule_from_name(&rule).ok_or(EventDecodeError::Invalid("rule"))?;
+        let ambient = string("ambient")?.ok_or(EventDecodeError::Missing("ambient"))?;
+        let target = string("target")?;
+        if target.is_some() == (rule == Rule::Open) {
+            return Err(EventDecodeError::Invalid("target"));
+        }
+        let path = match map.get("path") {
+            Some(Ipld::List(path)) => path.iter().map(|name| match name {
+                Ipld::String(name) => Ok(name.clone()),
+                _ => Err(EventDecodeError::Invalid("path")),
+            }).collect::<Result<_, _>>()?,
+            Some(_) => return Err(EventDecodeError::Invalid("path")),
+            None => return Err(EventDecodeError::Missing("path")),
+        };
+        Ok(Event { program, step, rule, ambient, target, path, key: bytes("key")?, signature: bytes("signature")? })
+    }
+
+    /// Encodes the event as DAG-CBOR.
+    pub fn to_cbor(&self) -> Vec<u8> {
+        dag_cbor::encode(&self.to_ipld())
+    }
+
+    /// Decodes an event from DAG-CBOR.
+    pub fn from_cbor(bytes: &[u8]) -> Result<Event, EventDecodeError> {
+        Event::from_ipld(&dag_cbor::decode(bytes).map_err(EventDecodeError::Cbor)?)
+    }
+
+    /// The CID of the event.
+    pub fn cid(&self) -> Cid {
+        dag_cbor::cid(&self.to_cbor())
+    }
+}
+
+impl Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let redex = Redex {
+            rule: self.rule,
+            ambient: &self.ambient,
+            target: self.target.as_deref(),
+            path: self.path.iter().map(String::as_str).collect(),
+        };
+        write!(f, "{}", redex)
+    }
+}
+
+/// The fields an event can have.
+const EVENT_FIELDS: [&str; 8] = ["program", "step", "rule", "ambient", "target", "path", "key", "signature"];
+
+fn rule_from_name(name: &str) -> Option<Rule> {
+    [Rule::In, Rule::Out, Rule::Open].iter().copied().find(|rule| rule.as_str() == name)
+}
+
+/// Why a deployed program couldn't be loaded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LoadError {
+    /// The manifest's signature doesn't check out.
+    Verify(VerifyError),
+    /// The program's key list couldn't be resolved.
+    Access(AccessError),
+    /// The store failed to read the program.
+    Store(StoreError),
+    /// The program isn't in the store, or isn't the source of a program.
+    BadProgram(Cid),
+}
+
+impl Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::Verify(e) => write!(f, "{}", e),
+            LoadError::Access(e) => write!(f, "{}", e),
+            LoadError::Store(e) => write!(f, "{}", e),
+            LoadError::BadProgram(cid) => write!(f, "{} isn't a program", cid),
+        }
+    }
+}
+
+impl Error for LoadError {}
+
+/// The source of the program of `manifest`, read from its block in `store`.
+pub fn source(manifest: &Manifest, store: &dyn BlockStore) -> Result<String, LoadError> {
+    let program = manifest.program();
+    match store.get(program).map_err(LoadError::Store)?.map(|bytes| dag_cbor::decode(&bytes)) {
+        Some(Ok(Ipld::String(source))) if program_cid(&source) == *program => Ok(source),
+        _ => Err(LoadError::BadProgram(program.clone())),
+    }
+}
+
+/// A deployed program being reduced, one authorized event at a time.
+#[derive(Debug, Clone)]
+pub struct Runtime<'a> {
+    program: Cid,
+    access: Access,
+    exec: Exec<'a>,
+    steps: u64,
+}
+
+impl<'a> Runtime<'a> {
+    /// Runs `source`, the program of `manifest`, under the keys the manifest's `keys` resolve to
+    /// in `store`. The manifest has to be signed by its creator. See `source` to read the program
+    /// from the store.
+    pub fn load(manifest: &Manifest, source: &'a str, store: &dyn BlockStore) -> Result<Runtime<'a>, LoadError> {
+        manifest.verify(manifest.program()).map_err(LoadError::Verify)?;
+        let access = Access::resolve(manifest, store).map_err(LoadError::Access)?;
+        let bad_program = || LoadError::BadProgram(manifest.program().clone());
+        if program_cid(source) != *manifest.program() {
+            return Err(bad_program());
+        }
+        let exec = parse(source).map_err(|_| bad_program())?;
+        Ok(Runtime::new(manifest, access, exec))
+    }
+
+    /// Runs `exec`, the program of `manifest`, which `access` can write to. Nothing checks that
+    /// `access` is what the manifest allows.
+    pub(crate) fn new(manifest: &Manifest, access: Access, exec: Exec<'a>) -> Runtime<'a> {
+        Runtime { program: manifest.cid(), access, exec, steps: 0 }
+    }
+
+    /// The CID of the program's manifest.
+    pub fn program(&self) -> &Cid {
+        &self.program
+    }
+
+    /// Who can write to the program.
+    pub fn access(&self) -> &Access {
+        &self.access
+    }
+
+    /// The program's current term.
+    pub fn exec(&self) -> &Exec<'a> {
+        &self.exec
+    }
+
+    /// The number of events applied so far.
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    /// The event of the program's next redex, signed by `signer`. `None` when the program is in
+    /// normal form.
+    pub fn next_event(&self, signer: &dyn Signer) -> Result<Option<Event>, SigningError> {
+        let step = match reduction::step(&self.exec) {
+            Some(step) => step,
+            None => return Ok(None),
+        };
+        let mut event = Event::new(&self.program, self.steps, &step.redex);
+        event.sign(signer)?;
+        Ok(Some(event))
+    }
+
+    /// Applies `event` if it is the program's next redex, signed by a key that can write to the
+    /// program, and hasn't been applied already. The program is left as it was otherwise.
+    pub fn apply(&mut self, event: &Event) -> Result<(), EventError> {
+        let key = event.verify(&self.program)?;
+        if !self.access.allows(&key) {
+            return Err(EventError::Unauthorized);
+        }
+        match reduction::step(&self.exec) {
+            Some(step) if event.is(self.steps, &step.redex) => {
+                self.exec = step.exec;
+                self.steps += 1;
+                Ok(())
+            },
+            _ => Err(EventError::NotEnabled),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ambient::{ program_cid, Ambient };
+    use crate::signing::{ Keypair, Scheme };
+    use crate::store::MemoryStore;
+
+    const PROGRAM: &str = "c[in_ b.open a | a[open_]] | b[in c]";
+
+    fn runtime<'a>(exec: Exec<'a>, access: Access) -> Runtime<'a> {
+        let manifest = Manifest::new(&program_cid(PROGRAM), "program", Some(access.address()), None, None);
+        Runtime::new(&manifest, access, exec)
+    }
+
+    /// Fires every step of the program as `signer`, returning the events applied.
+    fn run(runtime: &mut Runtime, signer: &dyn Signer) -> Result<Vec<Event>, EventError> {
+        let mut events = vec![];
+        while let Some(event) = runtime.next_event(signer).unwrap() {
+            runtime.apply(&event)?;
+            events.push(event);
+        }
+        Ok(events)
+    }
+
+    #[test]
+    fn runtime_wildcard() {
+        let exec = parse(PROGRAM).unwrap();
+        let mut runtime = runtime(exec.clone(), Access::Any);
+        let events = run(&mut runtime, &Keypair::generate(Scheme::Ed25519)).unwrap();
+        assert_eq!(events.iter().map(|e| e.to_string()).collect::<Vec<_>>(), vec!["b in c", "c: open a"]);
+        assert_eq!(format!("{:?}", runtime.exec()), format!("{:?}", reduction::reduce(exec)));
+    }
+
+    #[test]
+    fn runtime_single_key() {
+        let deployer = Keypair::generate(Scheme::Secp256k1);
+        let ambient = Ambient::deploy("program", PROGRAM, &deployer).unwrap();
+        let mut store = MemoryStore::new();
+        Access::Keys(vec![deployer.public_key()]).store(&mut store).unwrap();
+        let access = Access::resolve(ambient.manifest(), &store).unwrap();
+        let exec = parse(PROGRAM).unwrap();
+
+        let mut runtime = Runtime::new(ambient.manifest(), access.clone(), exec.clone());
+        assert_eq!(runtime.program(), ambient.cid());
+        assert_eq!(run(&mut runtime, &deployer).unwrap().len(), 2);
+
+        let mut runtime = Runtime::new(ambient.manifest(), access, exec.clone());
+        assert_eq!(run(&mut runtime, &Keypair::generate(Scheme::Secp256k1)), Err(EventError::Unauthorized));
+        assert_eq!(format!("{:?}", runtime.exec()), format!("{:?}", exec));
+    }
+
+    #[test]
+    fn runtime_load() {
+        let deployer = Keypair::generate(Scheme::Ed25519);
+        let access = Access::Keys(vec![deployer.public_key()]);
+        let ambient = Ambient::deploy_with_access("program", PROGRAM, &deployer, &access).unwrap();
+        let mut store = MemoryStore::new();
+        ambient.store(&mut store).unwrap();
+        let manifest = ambient.manifest();
+        assert!(matches!(Runtime::load(manifest, PROGRAM, &store), Err(LoadError::Access(_))));
+
+        access.store(&mut store).unwrap();
+        let program = source(manifest, &store).unwrap();
+        let mut runtime = Runtime::load(manifest, &program, &store).unwrap();
+        assert_eq!(runtime.access(), &access);
+        assert_eq!(run(&mut runtime, &Keypair::generate(Scheme::Ed25519)), Err(EventError::Unauthorized));
+        assert_eq!(run(&mut runtime, &deployer).unwrap().len(), 2);
+
+        let program = manifest.program().clone();
+        assert_eq!(Runtime::load(manifest, "a[]", &store).err(), Some(LoadError::BadProgram(program.clone())));
+        assert_eq!(source(manifest, &MemoryStore::new()), Err(LoadError::BadProgram(program)));
+        let renamed = Manifest::new(manifest.program(), "other", manifest.keys().cloned(), manifest.creator().cloned(), manifest.signature().map(<[u8]>::to_vec));
+        assert!(matches!(Runtime::load(&renamed, PROGRAM, &store), Err(LoadError::Verify(_))));
+        let unsigned = Manifest::new(manifest.program(), "program", manifest.keys().cloned(), None, None);
+        assert_eq!(Runtime::load(&unsigned, PROGRAM, &store).err(), Some(LoadError::Verify(VerifyError::Unsigned)));
+    }
+
+    #[test]
+    fn runtime_multiple_keys() {
+        let (a, b, c) = (Keypair::generate(Scheme::Ed25519), Keypair::generate(Scheme::Secp256k1), Keypair::generate(Scheme::Ed25519));
+        let exec = parse(PROGRAM).unwrap();
+        let mut runtime = runtime(exec, Access::Keys(vec![a.public_key(), b.public_key()]));
+
+        let first = runtime.next_event(&a).unwrap().unwrap();
+        runtime.apply(&first).unwrap();
+        let rejected = runtime.next_event(&c).unwrap().unwrap();
+        assert_eq!(runtime.apply(&rejected), Err(EventError::Unauthorized));
+        let second = runtime.next_event(&b).unwrap().unwrap();
+        runtime.apply(&second).unwrap();
+        assert!(runtime.next_event(&a).unwrap().is_none());
+    }
+
+    #[test]
+    fn runtime_rejects_forged_events() {
+        let keypair = Keypair::generate(Scheme::Ed25519);
+        let exec = parse(PROGRAM).unwrap();
+        let mut runtime = runtime(exec, Access::Keys(vec![keypair.public_key()]));
+        let event = runtime.next_event(&keypair).unwrap().unwrap();
+
+        let unsigned = Event { signature: None, ..event.clone() };
+        assert_eq!(runtime.apply(&unsigned), Err(EventError::Unsigned));
+
+        let mut tampered = event.clone();
+        tampered.ambient = "c".to_string();
+        assert_eq!(runtime.apply(&tampered), Err(EventError::BadSignature));
+
+        // Claiming the key of someone allowed, with a signature of someone who isn't.
+        let mut impostor = event.clone();
+        impostor.sign(&Keypair::generate(Scheme::Ed25519)).unwrap();
+        impostor.key = event.key.clone();
+        assert_eq!(runtime.apply(&impostor), Err(EventError::BadSignature));
+
+        let mut other_program = Event::new(&program_cid("other"), 0, &reduction::step(runtime.exec()).unwrap().redex);
+        other_program.sign(&keypair).unwrap();
+        assert_eq!(runtime.apply(&other_program), Err(EventError::ProgramMismatch));
+
+        // Signed and authorized, but not what the program does next.
+        let mut out_of_order = event.clone();
+        out_of_order.rule = Rule::Open;
+        out_of_order.ambient = "a".to_string();
+        out_of_order.target = None;
+        out_of_order.path = vec!["c".to_string()];
+        out_of_order.sign(&keypair).unwrap();
+        assert_eq!(runtime.apply(&out_of_order), Err(EventError::NotEnabled));
+
+        runtime.apply(&event).unwrap();
+        assert_eq!(runtime.apply(&event), Err(EventError::NotEnabled));
+    }
+
+    #[test]
+    fn runtime_rejects_replayed_events() {
+        // Both `a`s can enter `b` the same way, so the events only differ by their step.
+        let keypair = Keypair::generate(Scheme::Ed25519);
+        let mut runtime = runtime(parse("a[in b] | a[in b] | b[in_ a | in_ a]").unwrap(), Access::Keys(vec![keypair.public_key()]));
+        let first = runtime.next_event(&keypair).unwrap().unwrap();
+        runtime.apply(&first).unwrap();
+        assert_eq!(runtime.steps(), 1);
+        assert_eq!(runtime.apply(&first), Err(EventError::NotEnabled));
+
+        let second = runtime.next_event(&keypair).unwrap().unwrap();
+        assert_eq!(second.to_string(), first.to_string());
+        assert_eq!(second.step(), 1);
+        runtime.apply(&second).unwrap();
+        assert!(runtime.next_event(&keypair).unwrap().is_none());
+    }
+
+    #[test]
+    fn event_cbor() {
+        let keypair = Keypair::generate(Scheme::Secp256k1);
+        let exec = parse(PROGRAM).unwrap();
+        let runtime = runtime(exec, Access::Any);
+        let event = runtime.next_event(&keypair).unwrap().unwrap();
+        assert_eq!(Event::from_cbor(&event.to_cbor()).unwrap(), event);
+        assert_eq!(event.verify(runtime.program()).unwrap(), keypair.public_key());
+
+        let ipld = event.to_ipld();
+        assert_eq!(ipld.get("rule"), Some(&Ipld::String("in".to_string())));
+        assert_eq!(ipld.get("target"), Some(&Ipld::String("c".to_string())));
+
+        let mut open = ipld.clone();
+        if let Ipld::Map(map) = &mut open {
+            map.insert("rule".to_string(), Ipld::String("open".to_string()));
+        }
+        assert_eq!(Event::from_ipld(&open), Err(EventDecodeError::Invalid("target")));
+        let mut bad_rule = ipld;
+        if let Ipld::Map(map) = &mut bad_rule {
+            map.insert("rule".to_string(), Ipld::String("enter".to_string()));
+        }
+        assert_eq!(Event::from_ipld(&bad_rule), Err(EventDecodeError::Invalid("rule")));
+    }
+}
@@ -0,0 +1,296 @@
+//! Signature schemes deployers can sign manifests with.
+//!
+//! A public key is encoded with the multicodec of its scheme in front, so a verifier can tell
+//! which scheme a key, and a signature by it, belongs to:
+//!
+//! ```text
+//! public-key = 0xe7 0x01 key      (secp256k1-pub, 33-byte compressed key)
+//!            | 0xed 0x01 key      (ed25519-pub, 32-byte key)
+//! ```
+//!
+//! Secp256k1 signatures are DER-encoded ECDSA signatures of the SHA2-256 of the message,
+//! Ed25519 signatures are the 64 bytes of RFC8032.
+
+use unsigned_varint::{ decode as varint_decode, encode as varint_encode };
+
+use crate::ed25519;
+use crate::keypair::{ self, DecodingError, SigningError };
+use crate::prelude::*;
+
+/// A signature scheme.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Scheme {
+    /// ECDSA over secp256k1, with SHA2-256 digests.
+    Secp256k1,
+    /// EdDSA over Curve25519.
+    Ed25519,
+}
+
+impl Scheme {
+    /// The multicodec of the scheme's public keys.
+    pub fn code(self) -> u64 {
+        match self {
+            Scheme::Secp256k1 => 0xe7,
+            Scheme::Ed25519 => 0xed,
+        }
+    }
+
+    fn from_code(code: u64) -> Option<Scheme> {
+        match code {
+            0xe7 => Some(Scheme::Secp256k1),
+            0xed => Some(Scheme::Ed25519),
+            _ => None,
+        }
+    }
+}
+
+impl Display for Scheme {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Scheme::Secp256k1 => write!(f, "secp256k1"),
+            Scheme::Ed25519 => write!(f, "ed25519"),
+        }
+    }
+}
+
+/// Something that can sign messages, such as a keypair of any of the schemes.
+pub trait Signer {
+    /// The public key signatures verify against.
+    fn public_key(&self) -> PublicKey;
+
+    /// Signs `msg`.
+    fn sign(&self, msg: &[u8]) -> Result<Vec<u8>, SigningError>;
+}
+
+/// Something that can verify signatures, such as a public key of any of the schemes.
+pub trait Verifier {
+    /// Whether `sig` is a valid signature of `msg`.
+    fn verify(&self, msg: &[u8], sig: &[u8]) -> bool;
+}
+
+/// A public key of any of the schemes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PublicKey {
+    /// A secp256k1 public key.
+    Secp256k1(keypair::PublicKey),
+    /// An Ed25519 public key.
+    Ed25519(ed25519::PublicKey),
+}
+
+impl PublicKey {
+    /// The scheme of the key.
+    pub fn scheme(&self) -> Scheme {
+        match self {
+            PublicKey::Secp256k1(_) => Scheme::Secp256k1,
+            PublicKey::Ed25519(_) => Scheme::Ed25519,
+        }
+    }
+
+    /// Encodes the key, prefixed with the multicodec of its scheme.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = varint_encode::u64(self.scheme().code(), &mut varint_encode::u64_buffer()).to_vec();
+        match self {
+            PublicKey::Secp256k1(key) => bytes.extend(&key.encode()),
+            PublicKey::Ed25519(key) => bytes.extend(&key.encode()),
+        }
+        bytes
+    }
+
+    /// Decodes a key encoded by `encode`. Secp256k1 keys must be compressed, so that every key
+    /// has one encoding.
+    pub fn decode(bytes: &[u8]) -> Result<PublicKey, DecodingError> {
+        let (code, key) = varint_decode::u64(bytes)
+            .map_err(|_| DecodingError::new("invalid public key multicodec"))?;
+        match Scheme::from_code(code) {
+            Some(Scheme::Secp256k1) if key.len() == 33 => keypair::PublicKey::decode(key).map(PublicKey::Secp256k1),
+            Some(Scheme::Secp256k1) => Err(DecodingError::new("secp256k1 public key isn't compressed")),
+            Some(Scheme::Ed25519) => ed25519::PublicKey::decode(key).map(PublicKey::Ed25519),
+            None => Err(DecodingError::new(format!("unknown public key multicodec {:#x}", code))),
+        }
+    }
+}
+
+/// A keypair of any of the schemes.
+#[derive(Debug, Clone)]
+pub enum Keypair {
+    /// A secp256k1 keypair.
+    Secp256k1(keypair::Keypair),
+    /// An Ed25519 keypair.
+    Ed25519(ed25519::Keypair),
+}
+
+impl Keypair {
+    /// Generates a new keypair of `scheme`.
+    pub fn generate(scheme: Scheme) -> Keypair {
+        match scheme {
+            Scheme::Secp256k1 => Keypair::Secp256k1(keypair::Keypair::generate()),
+            Scheme::Ed25519 => Keypair::Ed25519(ed25519::Keypair::generate()),
+        }
+    }
+
+    /// Creates a keypair of `scheme` from the 32 bytes of its secret key, zeroing them on
+    /// success.
+    pub fn from_secret_bytes(scheme: Scheme, sk: impl AsMut<[u8]>) -> Result<Keypair, DecodingError> {
+        Ok(match scheme {
+            Scheme::Secp256k1 => Keypair::Secp256k1(keypair::SecretKey::from_bytes(sk)?.into()),
+            Scheme::Ed25519 => Keypair::Ed25519(ed25519::SecretKey::from_bytes(sk)?.into()),
+        })
+    }
+
+    /// The scheme of the keypair.
+    pub fn scheme(&self) -> Scheme {
+        match self {
+            Keypair::Secp256k1(_) => Scheme::Secp256k1,
+            Keypair::Ed25519(_) => Scheme::Ed25519,
+        }
+    }
+
+    /// Returns the raw bytes of the secret key. Zeroing them is up to the caller.
+    pub fn secret_to_bytes(&self) -> [u8; 32] {
+        match self {
+            Keypair::Secp256k1(keypair) => keypair.secret().to_bytes(),
+            Keypair::Ed25519(keypair) => keypair.secret().to_bytes(),
+        }
+    }
+}
+
+impl Signer for Keypair {
+    fn public_key(&self) -> PublicKey {
+        match self {
+            Keypair::Secp256k1(keypair) => keypair.public_key(),
+            Keypair::Ed25519(keypair) => keypair.public_key(),
+        }
+    }
+
+    fn sign(&self, msg: &[u8]) -> Result<Vec<u8>, SigningError> {
+        match self {
+            Keypair::Secp256k1(keypair) => Signer::sign(keypair, msg),
+            Keypair::Ed25519(keypair) => Signer::sign(keypair, msg),
+        }
+    }
+}
+
+impl Verifier for PublicKey {
+    fn verify(&self, msg: &[u8], sig: &[u8]) -> bool {
+        match self {
+            PublicKey::Secp256k1(key) => key.verify(msg, sig),
+            PublicKey::Ed25519(key) => key.verify(msg, sig),
+        }
+    }
+}
+
+impl Verifier for keypair::PublicKey {
+    fn verify(&self, msg: &[u8], sig: &[u8]) -> bool {
+        keypair::PublicKey::verify(self, msg, sig)
+    }
+}
+
+impl Verifier for ed25519::PublicKey {
+    fn verify(&self, msg: &[u8], sig: &[u8]) -> bool {
+        ed25519::PublicKey::verify(self, msg, sig)
+    }
+}
+
+impl Signer for keypair::Keypair {
+    fn public_key(&self) -> PublicKey {
+        PublicKey::Secp256k1(self.public().clone())
+    }
+
+    fn sign(&self, msg: &[u8]) -> Result<Vec<u8>, SigningError> {
+        self.secret().sign(msg)
+    }
+}
+
+impl Signer for ed25519::Keypair {
+    fn public_key(&self) -> PublicKey {
+        PublicKey::Ed25519(self.public())
+    }
+
+    fn sign(&self, msg: &[u8]) -> Result<Vec<u8>, SigningError> {
+        Ok(ed25519::Keypair::sign(self, msg))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signers() -> Vec<Box<dyn Signer>> {
+        vec![
+            Box::new(keypair::Keypair::generate()),
+            Box::new(ed25519::Keypair::generate()),
+            Box::new(Keypair::generate(Scheme::Secp256k1)),
+            Box::new(Keypair::generate(Scheme::Ed25519)),
+        ]
+    }
+
+    #[test]
+    fn keypair_secret_bytes() {
+        for scheme in [Scheme::Secp256k1, Scheme::Ed25519].iter().copied() {
+            let keypair = Keypair::generate(scheme);
+            assert_eq!(keypair.scheme(), scheme);
+            assert_eq!(keypair.public_key().scheme(), scheme);
+            let mut bytes = keypair.secret_to_bytes();
+            let restored = Keypair::from_secret_bytes(scheme, &mut bytes).unwrap();
+            assert_eq!(restored.public_key(), keypair.public_key());
+            assert_eq!(bytes, [0; 32]);
+        }
+    }
+
+    #[test]
+    fn public_key_multicodec() {
+        let secp256k1 = keypair::Keypair::generate();
+        let encoded = Signer::public_key(&secp256k1).encode();
+        assert_eq!(encoded[..2], [0xe7, 0x01]);
+        assert_eq!(encoded[2..], secp256k1.public().encode()[..]);
+
+        let ed25519 = ed25519::Keypair::generate();
+        let encoded = Signer::public_key(&ed25519).encode();
+        assert_eq!(encoded[..2], [0xed, 0x01]);
+        assert_eq!(encoded[2..], ed25519.public().encode()[..]);
+
+        for signer in signers() {
+            let public = signer.public_key();
+            assert_eq!(PublicKey::decode(&public.encode()).unwrap(), public);
+        }
+    }
+
+    #[test]
+    fn public_key_decode_errors() {
+        let secp256k1 = keypair::Keypair::generate();
+        let mut uncompressed = vec![0xe7, 0x01];
+        uncompressed.extend(&secp256k1.public().encode_uncompressed()[..]);
+        assert!(PublicKey::decode(&uncompressed).is_err());
+        assert!(PublicKey::decode(&secp256k1.public().encode()).is_err());
+        assert!(PublicKey::decode(&[0xed]).is_err());
+        assert!(PublicKey::decode(&[0xed, 0x01]).is_err());
+        assert!(PublicKey::decode(&[]).is_err());
+    }
+
+    #[test]
+    fn sign_verify() {
+        for signer in signers() {
+            let signature = signer.sign(b"hello").unwrap();
+            assert!(signer.public_key().verify(b"hello", &signature));
+            assert!(!signer.public_key().verify(b"hello!", &signature));
+        }
+    }
+
+    #[test]
+    fn cross_scheme_rejected() {
+        let secp256k1 = keypair::Keypair::generate();
+        let ed25519 = ed25519::Keypair::generate();
+        let secp256k1_signature = Signer::sign(&secp256k1, b"hello").unwrap();
+        let ed25519_signature = Signer::sign(&ed25519, b"hello").unwrap();
+        assert!(!ed25519.public_key().verify(b"hello", &secp256k1_signature));
+        assert!(!secp256k1.public_key().verify(b"hello", &ed25519_signature));
+
+        // A key relabelled as the other scheme isn't a key of that scheme.
+        let mut relabelled = secp256k1.public_key().encode();
+        relabelled[0] = 0xed;
+        assert!(PublicKey::decode(&relabelled).is_err());
+        let mut relabelled = ed25519.public_key().encode();
+        relabelled[0] = 0xe7;
+        assert!(PublicKey::decode(&relabelled).is_err());
+    }
+}
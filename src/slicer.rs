@@ -0,0 +1,468 @@
+//! Slicing of compiled programs into content-addressed Merkle-DAG nodes.
+//!
+//! Every ambient of a program becomes a DAG-CBOR node holding its name, its instruction stream
+//! and links to the nodes of the ambients it creates, in the order of its `create`
+//! instructions:
+//!
+//! ```text
+//! {
+//!   "name": "x",
+//!   "links": [<cid of call>],
+//!   "bytecode": <(0 create, 1 call) (8 seq, 0) (5 out_, 1 call) (3 in_, "y") (10 end, 0)>
+//! }
+//! ```
+//!
+//! A node is addressed by the CID of its encoding, so ambients that are the same everywhere in
+//! the program, name and contents, are stored once. The CID of the program's own node is the
+//! root of the program: with it, a participant can fetch exactly the slices it needs, and each
+//! slice fetched can be checked against the CID it was linked by.
+
+use std::collections::BTreeMap;
+use std::error::Error;
+
+use cid::Cid;
+
+use crate::bytecode;
+use crate::compiler::{ Bytecode, Compiled };
+use crate::dag_cbor::{ self, Ipld };
+use crate::prelude::*;
+use crate::primitives::Capability;
+use crate::store::{ BlockStore, StoreError };
+
+/// The deepest ambients can be nested in a reassembled program.
+pub const MAX_DEPTH: usize = 256;
+
+/// The most ambients a reassembled program can have.
+pub const MAX_AMBIENTS: usize = 1 << 16;
+
+/// A program sliced into nodes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Slices {
+    /// The CID of the program's node.
+    pub root: Cid,
+    /// The encoded nodes of the program, by CID.
+    pub blocks: BTreeMap<Cid, Vec<u8>>,
+}
+
+/// Why a program couldn't be reassembled from its slices.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SliceError {
+    /// A linked node isn't in the blocks.
+    Missing(Cid),
+    /// A node's contents don't hash to the CID it is stored and linked by.
+    HashMismatch(Cid),
+    /// A node isn't a DAG-CBOR map with `name`, `links` and `bytecode`.
+    BadNode(Cid),
+    /// A node's instruction stream couldn't be decoded.
+    Bytecode(Cid, bytecode::DecodeError),
+    /// A node's links don't match its `create` instructions, or the name of a node doesn't
+    /// match the instruction that creates it.
+    BadLinks(Cid),
+    /// The store failed to read a node.
+    Store(StoreError),
+    /// The ambients under a node are nested more than `MAX_DEPTH` deep.
+    TooDeep(Cid),
+    /// A node has more than `MAX_AMBIENTS` ambients under it.
+    TooLarge(Cid),
+}
+
+impl Display for SliceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SliceError::Missing(cid) => write!(f, "missing slice {}", cid),
+            SliceError::HashMismatch(cid) => write!(f, "slice {} doesn't match its hash", cid),
+            SliceError::BadNode(cid) => write!(f, "slice {} isn't an ambient node", cid),
+            SliceError::Bytecode(cid, e) => write!(f, "slice {}: {}", cid, e),
+            SliceError::BadLinks(cid) => write!(f, "links of slice {} don't match its bytecode", cid),
+            SliceError::Store(e) => write!(f, "{}", e),
+            SliceError::TooDeep(cid) => write!(f, "ambients under slice {} are nested too deep", cid),
+            SliceError::TooLarge(cid) => write!(f, "slice {} has too many ambients under it", cid),
+        }
+    }
+}
+
+impl Error for SliceError {}
+
+/// Slices `program` into one node per unique ambient.
+pub fn slice(program: &Compiled) -> Slices {
+    let mut blocks = BTreeMap::new();
+    let root = put(program, &mut blocks);
+    Slices { root, blocks }
+}
+
+/// Encodes the node of `ambient` and those of the ambients it creates, returning its CID.
+fn put(ambient: &Compiled, blocks: &mut BTreeMap<Cid, Vec<u8>>) -> Cid {
+    let links = ambient.children.iter().map(|child| Ipld::Link(put(child, blocks))).collect();
+    let mut node = BTreeMap::new();
+    node.insert("name".to_string(), Ipld::String(ambient.name.to_string()));
+    node.insert("links".to_string(), Ipld::List(links));
+    node.insert("bytecode".to_string(), Ipld::Bytes(bytecode::encode(&ambient.instructions)));
+    let bytes = dag_cbor::encode(&Ipld::Map(node));
+    let cid = dag_cbor::cid(&bytes);
+    blocks.insert(cid.clone(), bytes);
+    cid
+}
+
+impl Slices {
+    /// Reassembles the program from `root`, checking every node against its CID.
+    pub fn reassemble(&self) -> Result<Compiled<'_>, SliceError> {
+        reassemble(&self.root, &self.blocks)
+    }
+
+    /// Puts every node in `store`.
+    pub fn store(&self, store: &mut dyn BlockStore) -> Result<(), StoreError> {
+        self.blocks.iter().try_for_each(|(cid, bytes)| store.put(cid, bytes))
+    }
+}
+
+/// Reads the nodes of the program whose node is `root` from `store`, following the links of
+/// every node. The nodes read can then be reassembled.
+pub fn load(root: &Cid, store: &dyn BlockStore) -> Result<Slices, SliceError> {
+    let mut blocks = BTreeMap::new();
+    let mut pending = vec![root.clone()];
+    while let Some(cid) = pending.pop() {
+        if blocks.contains_key(&cid) {
+            continue;
+        }
+        let bytes = match store.get(&cid) {
+            Ok(Some(bytes)) => bytes,
+            Ok(None) => return Err(SliceError::Missing(cid)),
+            Err(StoreError::HashMismatch(cid)) => return Err(SliceError::HashMismatch(cid)),
+            Err(e) => return Err(SliceError::Store(e)),
+        };
+        match dag_cbor::decode(&bytes) {
+            Ok(Ipld::Map(node)) => match node.get("links") {
+                Some(Ipld::List(links)) => for link in links {
+                    match link {
+                        Ipld::Link(link) => pending.push(link.clone()),
+                        _ => return Err(SliceError::BadNode(cid)),
+                    }
+                },
+                _ => return Err(SliceError::BadNode(cid)),
+            },
+            _ => return Err(SliceError::BadNode(cid)),
+        }
+        blocks.insert(cid, bytes);
+    }
+    Ok(Slices { root: root.clone(), blocks })
+}
+
+/// Reassembles the program whose node is `root` from `blocks`, checking every node against the
+/// CID it is linked by.
+///
+/// Nodes are decoded once however many nodes link to them, without recursion. Programs nested
+/// more than `MAX_DEPTH` ambients deep, or of more than `MAX_AMBIENTS` ambients once shared nodes
+/// are copied, are rejected before any node is copied.
+pub fn reassemble<'a>(root: &Cid, blocks: &'a BTreeMap<Cid, Vec<u8>>) -> Result<Compiled<'a>, SliceError> {
+    get(root, None, blocks)
+}
+
+/// Reassembles the node `root` and the nodes it links to. `name` is the name the node is
+/// created with, `None` for the root node of a program, which is named by its `deploy`
+/// instruction.
+fn get<'a>(root: &Cid, name: Option<&'a str>, blocks: &'a BTreeMap<Cid, Vec<u8>>) -> Result<Compiled<'a>, SliceError> {
+    let mut nodes: BTreeMap<Cid, Node<'a>> = BTreeMap::new();
+    // Whether the links of a node are on the stack, above it, and will be measured first.
+    let mut pending = vec![(root.clone(), false)];
+    while let Some((cid, linked)) = pending.pop() {
+        if !linked {
+            if nodes.contains_key(&cid) {
+                continue;
+            }
+            let node = decode_node(&cid, blocks)?;
+            pending.push((cid.clone(), true));
+            pending.extend(node.links.iter().filter(|link| !nodes.contains_key(*link)).map(|link| (link.clone(), false)));
+            nodes.insert(cid, node);
+            continue;
+        }
+
+        let node = &nodes[&cid];
+        let (mut depth, mut size) = (1, 1usize);
+        for (name, link) in node.created.iter().zip(&node.links) {
+            let child = &nodes[link];
+            if child.name != *name {
+                return Err(SliceError::BadLinks(link.clone()));
+            }
+            let (child_depth, child_size) = child.measured.expect("links are measured first");
+            depth = depth.max(child_depth + 1);
+            size = size.saturating_add(child_size);
+            if depth > MAX_DEPTH {
+                return Err(SliceError::TooDeep(cid));
+            }
+            if size > MAX_AMBIENTS {
+                return Err(SliceError::TooLarge(cid));
+            }
+        }
+        nodes.get_mut(&cid).expect("decoded above").measured = Some((depth, size));
+    }
+
+    let node = &nodes[root];
+    let name = match (name, node.instructions.first()) {
+        (Some(name), _) => name,
+        (None, Some(i)) if *i.opcode() == Capability::deploy => i.target().name(),
+        (None, _) => return Err(SliceError::BadLinks(root.clone())),
+    };
+    if node.name != name {
+        return Err(SliceError::BadLinks(root.clone()));
+    }
+    Ok(assemble(root, name, &nodes))
+}
+
+/// Copies the node `cid` and the nodes it links to into the ambient `name`. The nodes have been
+/// measured, so the recursion is at most `MAX_DEPTH` deep and copies at most `MAX_AMBIENTS`
+/// ambients.
+fn assemble<'a>(cid: &Cid, name: &'a str, nodes: &BTreeMap<Cid, Node<'a>>) -> Compiled<'a> {
+    let node = &nodes[cid];
+    let children = node.created.iter().zip(&node.links).map(|(name, link)| assemble(link, name, nodes)).collect();
+    Compiled { name, instructions: node.instructions.clone(), children }
+}
+
+/// A decoded node.
+struct Node<'a> {
+    name: String,
+    instructions: Vec<Bytecode<'a>>,
+    /// The names of the `create` instructions, one per link.
+    created: Vec<&'a str>,
+    links: Vec<Cid>,
+    /// Once the nodes it links to are checked, the number of ambients nested in each other and
+    /// the number of ambients, both counting the node's own.
+    measured: Option<(usize, usize)>,
+}
+
+/// Decodes the node `cid`, checking it against its CID and its links against its `create`
+/// instructions.
+fn decode_node<'a>(cid: &Cid, blocks: &'a BTreeMap<Cid, Vec<u8>>) -> Result<Node<'a>, SliceError> {
+    let bytes = blocks.get(cid).ok_or_else(|| SliceError::Missing(cid.clone()))?;
+    if dag_cbor::cid(bytes) != *cid {
+        return Err(SliceError::HashMismatch(cid.clone()));
+    }
+    let bad_node = || SliceError::BadNode(cid.clone());
+
+    let node = match dag_cbor::decode(bytes) {
+        Ok(Ipld::Map(node)) if node.len() == 3 => node,
+        _ => return Err(bad_node()),
+    };
+    let (name, links, code_len) = match (node.get("name"), node.get("links"), node.get("bytecode")) {
+        (Some(Ipld::String(name)), Some(Ipld::List(links)), Some(Ipld::Bytes(code))) => (name, links, code.len()),
+        _ => return Err(bad_node()),
+    };
+    // `bytecode` is the longest key, so it is encoded last and its value is the tail of the
+    // block. Borrowing it from there lets the instructions borrow their names from `blocks`.
+    let code = &bytes[bytes.len() - code_len..];
+    let instructions = bytecode::decode(code).map_err(|e| SliceError::Bytecode(cid.clone(), e))?;
+    let links = links.iter()
+        .map(|link| match link {
+            Ipld::Link(link) => Ok(link.clone()),
+            _ => Err(bad_node()),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let created: Vec<_> = instructions.iter()
+        .filter(|i| *i.opcode() == Capability::create)
+        .map(|i| i.target().name())
+        .collect();
+    if created.len() != links.len() {
+        return Err(SliceError::BadLinks(cid.clone()));
+    }
+    Ok(Node { name: name.clone(), instructions, created, links, measured: None })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::compile;
+    use crate::primitives::{ Instruction, Operand };
+    use crate::store::MemoryStore;
+    use ambients_parser::parse;
+
+    const PROGRAM: &str = "
+x[
+    call[out x.in y.open_|return[open_.in x]]|
+    out_ call.in_ y
+] |
+y[in_ call.open call.open return] |
+z[in_ call.open call.open return]
+";
+
+    fn node(slices: &Slices, cid: &Cid) -> Ipld {
+        dag_cbor::decode(&slices.blocks[cid]).unwrap()
+    }
+
+    fn links(node: &Ipld) -> Vec<Cid> {
+        match node.get("links") {
+            Some(Ipld::List(links)) => links.iter().map(|l| match l {
+                Ipld::Link(cid) => cid.clone(),
+                _ => panic!("not a link"),
+            }).collect(),
+            _ => panic!("no links"),
+        }
+    }
+
+    #[test]
+    fn slice_nodes() {
+        let exec = parse(PROGRAM).unwrap();
+        let compiled = compile("program", &exec);
+        let slices = slice(&compiled);
+        assert_eq!(slices.blocks.len(), 6);
+
+        let root = node(&slices, &slices.root);
+        assert_eq!(root.get("name"), Some(&Ipld::String("program".to_string())));
+        assert_eq!(root.get("bytecode"), Some(&Ipld::Bytes(bytecode::encode(&compiled.instructions))));
+
+        let children = links(&root);
+        assert_eq!(children.len(), 3);
+        let x = node(&slices, &children[0]);
+        assert_eq!(x.get("name"), Some(&Ipld::String("x".to_string())));
+        assert_eq!(links(&x).len(), 1);
+        let return_ = links(&node(&slices, &links(&x)[0]))[0].clone();
+        assert_eq!(node(&slices, &return_).get("name"), Some(&Ipld::String("return".to_string())));
+        assert!(links(&node(&slices, &return_)).is_empty());
+    }
+
+    #[test]
+    fn slice_unique_ambients() {
+        // Same name and contents, one node.
+        let exec = parse("a[b[in c]] | d[b[in c]]").unwrap();
+        let slices = slice(&compile("program", &exec));
+        assert_eq!(slices.blocks.len(), 4);
+        let children = links(&node(&slices, &slices.root));
+        assert_eq!(links(&node(&slices, &children[0])), links(&node(&slices, &children[1])));
+
+        // Same contents under another name, another node.
+        let exec = parse("a[b[in c]] | d[e[in c]]").unwrap();
+        assert_eq!(slice(&compile("program", &exec)).blocks.len(), 5);
+    }
+
+    #[test]
+    fn slice_deterministic() {
+        let exec = parse(PROGRAM).unwrap();
+        let a = slice(&compile("program", &exec));
+        let b = slice(&compile("program", &parse(&exec.to_string()).unwrap()));
+        assert_eq!(a, b);
+        assert_ne!(a.root, slice(&compile("other", &exec)).root);
+    }
+
+    #[test]
+    fn reassemble_program() {
+        let exec = parse(PROGRAM).unwrap();
+        let compiled = compile("program", &exec);
+        let slices = slice(&compiled);
+        assert_eq!(slices.reassemble().unwrap(), compiled);
+        assert_eq!(slices.reassemble().unwrap().to_string(), compiled.to_string());
+
+        // Any slice can be reassembled on its own, given its name.
+        let x = links(&node(&slices, &slices.root))[0].clone();
+        assert_eq!(get(&x, Some("x"), &slices.blocks).unwrap(), compiled.children[0]);
+    }
+
+    #[test]
+    fn store_load() {
+        let exec = parse(PROGRAM).unwrap();
+        let compiled = compile("program", &exec);
+        let slices = slice(&compiled);
+        let mut store = MemoryStore::new();
+        slices.store(&mut store).unwrap();
+        assert_eq!(store.len(), 6);
+        let loaded = load(&slices.root, &store).unwrap();
+        assert_eq!(loaded, slices);
+        assert_eq!(loaded.reassemble().unwrap(), compiled);
+
+        // Only the nodes reachable from the root are loaded.
+        let x = links(&node(&slices, &slices.root))[0].clone();
+        let call = links(&node(&slices, &x))[0].clone();
+        assert_eq!(load(&x, &store).unwrap().blocks.len(), 3);
+        store.delete(&call).unwrap();
+        assert_eq!(load(&slices.root, &store), Err(SliceError::Missing(call)));
+
+        let tampered: MemoryStore = vec![(x.clone(), vec![0])].into_iter().collect();
+        assert_eq!(load(&x, &tampered), Err(SliceError::HashMismatch(x)));
+    }
+
+    #[test]
+    fn reassemble_errors() {
+        let exec = parse(PROGRAM).unwrap();
+        let slices = slice(&compile("program", &exec));
+        let x = links(&node(&slices, &slices.root))[0].clone();
+
+        let mut missing = slices.clone();
+        missing.blocks.remove(&x);
+        assert_eq!(missing.reassemble(), Err(SliceError::Missing(x.clone())));
+
+        let mut tampered = slices.clone();
+        tampered.blocks.get_mut(&x).unwrap().push(0);
+        assert_eq!(tampered.reassemble(), Err(SliceError::HashMismatch(x.clone())));
+
+        // Well-formed nodes that don't fit together.
+        let mut swapped = slices.clone();
+        let y = links(&node(&slices, &slices.root))[1].clone();
+        let mut root = node(&slices, &slices.root);
+        if let Ipld::Map(map) = &mut root {
+            map.insert("links".to_string(), Ipld::List(vec![Ipld::Link(y.clone()); 3]));
+        }
+        let root_bytes = dag_cbor::encode(&root);
+        swapped.root = dag_cbor::cid(&root_bytes);
+        swapped.blocks.insert(swapped.root.clone(), root_bytes);
+        assert_eq!(swapped.reassemble(), Err(SliceError::BadLinks(y)));
+
+        let mut not_a_node = slices;
+        let bytes = dag_cbor::encode(&Ipld::String("program".to_string()));
+        not_a_node.root = dag_cbor::cid(&bytes);
+        not_a_node.blocks.insert(not_a_node.root.clone(), bytes);
+        assert_eq!(not_a_node.reassemble(), Err(SliceError::BadNode(not_a_node.root.clone())));
+    }
+
+    /// A program of `levels` nodes under its root, each creating `width` copies of the next.
+    /// A program of `levels` ambients nested in each other, each linking `width` times to the
+    /// one below, and linked `links` times by the program.
+    fn nested(levels: usize, width: usize, links: usize) -> Slices {
+        let mut blocks = BTreeMap::new();
+        let create = Instruction::new(Capability::create, Operand::Name("a"));
+        let mut put = |name: &str, mut instructions: Vec<Bytecode>, links: Vec<Ipld>| {
+            instructions.extend(std::iter::repeat_n(create.clone(), links.len()));
+            let mut node = BTreeMap::new();
+            node.insert("name".to_string(), Ipld::String(name.to_string()));
+            node.insert("links".to_string(), Ipld::List(links));
+            node.insert("bytecode".to_string(), Ipld::Bytes(bytecode::encode(&instructions)));
+            let bytes = dag_cbor::encode(&Ipld::Map(node));
+            let cid = dag_cbor::cid(&bytes);
+            blocks.insert(cid.clone(), bytes);
+            cid
+        };
+        let mut cid = put("a", vec![], vec![]);
+        for _ in 1..levels {
+            cid = put("a", vec![], vec![Ipld::Link(cid); width]);
+        }
+        let deploy = Instruction::new(Capability::deploy, Operand::Name("program"));
+        let root = put("program", vec![deploy], vec![Ipld::Link(cid); links]);
+        Slices { root, blocks }
+    }
+
+    #[test]
+    fn reassemble_limits() {
+        let deep = nested(MAX_DEPTH - 1, 1, 1);
+        assert_eq!(deep.blocks.len(), MAX_DEPTH);
+        assert_eq!(deep.reassemble().unwrap().children.len(), 1);
+        let too_deep = nested(MAX_DEPTH, 1, 1);
+        assert_eq!(too_deep.reassemble(), Err(SliceError::TooDeep(too_deep.root.clone())));
+        let far_too_deep = nested(20_000, 1, 1);
+        assert!(matches!(far_too_deep.reassemble(), Err(SliceError::TooDeep(_))));
+
+        // A node shared by both ambients of the node above it is stored once, but copied into
+        // both once reassembled.
+        let shared = nested(10, 2, 2);
+        assert_eq!(shared.blocks.len(), 11);
+        assert_eq!(shared.reassemble().unwrap().children.len(), 2);
+        let too_large = nested(16, 2, 2);
+        assert!(matches!(too_large.reassemble(), Err(SliceError::TooLarge(_))));
+        let far_too_large = nested(64, 2, 2);
+        assert!(matches!(far_too_large.reassemble(), Err(SliceError::TooLarge(_))));
+
+        // A single wide node of links to a subtree just within the limit is rejected at its
+        // second link, rather than after copying the subtree a thousand times.
+        let wide = nested(16, 2, 1000);
+        assert_eq!(wide.blocks.len(), 17);
+        assert_eq!(wide.reassemble(), Err(SliceError::TooLarge(wide.root.clone())));
+        let within = nested(15, 2, 2);
+        assert_eq!(within.reassemble().unwrap().children.len(), 2);
+    }
+}
@@ -0,0 +1,299 @@
+//! Content-addressed block storage.
+//!
+//! Programs, manifests, key lists and slices are all blocks: bytes addressed by the CID of their
+//! hash. A store checks every block against its CID when it is put and again when it is read, so
+//! a block read from a store is always the block that was asked for.
+//!
+//! `MemoryStore` keeps blocks in a map. `FsStore` keeps each block in a file named after its
+//! CID, sharded into directories by the next-to-last two characters of the CID, as IPFS does:
+//!
+//! ```text
+//! <root>/aw/bafyreihiyk3afmeqyfzwtbxlill3j4h3hwejzinrwrobqsaen5g4dj7awy.data
+//! ```
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::iter::FromIterator;
+use std::io::{ self, Write };
+use std::path::{ Path, PathBuf };
+
+use cid::Cid;
+
+use crate::dag_cbor;
+use crate::prelude::*;
+
+/// Why a store operation failed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StoreError {
+    /// The block doesn't hash to its CID.
+    HashMismatch(Cid),
+    /// The CID's hash function isn't one blocks can be checked with.
+    UnsupportedHash(Cid),
+    /// The backend failed to read or write the block.
+    Io(String),
+}
+
+impl Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StoreError::HashMismatch(cid) => write!(f, "block doesn't match its CID {}", cid),
+            StoreError::UnsupportedHash(cid) => write!(f, "unsupported hash function in CID {}", cid),
+            StoreError::Io(e) => write!(f, "block store I/O error: {}", e),
+        }
+    }
+}
+
+impl Error for StoreError {}
+
+impl From<io::Error> for StoreError {
+    fn from(e: io::Error) -> StoreError {
+        StoreError::Io(e.to_string())
+    }
+}
+
+/// A store of blocks addressed by CID.
+pub trait BlockStore {
+    /// Stores `bytes` as the block `cid`, unless they don't hash to `cid`.
+    fn put(&mut self, cid: &Cid, bytes: &[u8]) -> Result<(), StoreError>;
+
+    /// The block `cid`, `None` if the store doesn't have it.
+    fn get(&self, cid: &Cid) -> Result<Option<Vec<u8>>, StoreError>;
+
+    /// Whether the store has the block `cid`.
+    fn has(&self, cid: &Cid) -> Result<bool, StoreError>;
+
+    /// Removes the block `cid`, returning whether the store had it.
+    fn delete(&mut self, cid: &Cid) -> Result<bool, StoreError>;
+
+    /// Stores DAG-CBOR encoded `bytes`, returning their CID.
+    fn put_cbor(&mut self, bytes: &[u8]) -> Result<Cid, StoreError> {
+        let cid = dag_cbor::cid(bytes);
+        self.put(&cid, bytes)?;
+        Ok(cid)
+    }
+}
+
+/// Checks that `bytes` hash to `cid`, with the hash function of `cid`.
+pub fn verify(cid: &Cid, bytes: &[u8]) -> Result<(), StoreError> {
+    let hash = cid.hash();
+    let hasher = hash.algorithm().hasher().ok_or_else(|| StoreError::UnsupportedHash(cid.clone()))?;
+    if hasher.digest(bytes).as_bytes() != hash.as_bytes() {
+        return Err(StoreError::HashMismatch(cid.clone()));
+    }
+    Ok(())
+}
+
+/// A store that keeps its blocks in memory.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MemoryStore {
+    blocks: BTreeMap<Cid, Vec<u8>>,
+}
+
+impl MemoryStore {
+    /// An empty store.
+    pub fn new() -> MemoryStore {
+        MemoryStore::default()
+    }
+
+    /// The number of blocks in the store.
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    /// Whether the store has no blocks.
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    /// The CIDs of the blocks in the store, in order.
+    pub fn cids(&self) -> impl Iterator<Item = &Cid> {
+        self.blocks.keys()
+    }
+}
+
+/// A store of `blocks`, which are checked against their CIDs when they are read.
+impl From<BTreeMap<Cid, Vec<u8>>> for MemoryStore {
+    fn from(blocks: BTreeMap<Cid, Vec<u8>>) -> MemoryStore {
+        MemoryStore { blocks }
+    }
+}
+
+impl FromIterator<(Cid, Vec<u8>)> for MemoryStore {
+    fn from_iter<I: IntoIterator<Item = (Cid, Vec<u8>)>>(blocks: I) -> MemoryStore {
+        MemoryStore { blocks: blocks.into_iter().collect() }
+    }
+}
+
+impl BlockStore for MemoryStore {
+    fn put(&mut self, cid: &Cid, bytes: &[u8]) -> Result<(), StoreError> {
+        verify(cid, bytes)?;
+        self.blocks.insert(cid.clone(), bytes.to_vec());
+        Ok(())
+    }
+
+    fn get(&self, cid: &Cid) -> Result<Option<Vec<u8>>, StoreError> {
+        match self.blocks.get(cid) {
+            Some(bytes) => verify(cid, bytes).map(|_| Some(bytes.clone())),
+            None => Ok(None),
+        }
+    }
+
+    fn has(&self, cid: &Cid) -> Result<bool, StoreError> {
+        Ok(self.blocks.contains_key(cid))
+    }
+
+    fn delete(&mut self, cid: &Cid) -> Result<bool, StoreError> {
+        Ok(self.blocks.remove(cid).is_some())
+    }
+}
+
+/// A store that keeps each block in a file under a root directory.
+#[derive(Debug, Clone)]
+pub struct FsStore {
+    root: PathBuf,
+}
+
+impl FsStore {
+    /// Opens the store under `root`, creating the directory if needed.
+    pub fn open(root: impl AsRef<Path>) -> Result<FsStore, StoreError> {
+        fs::create_dir_all(root.as_ref())?;
+        Ok(FsStore { root: root.as_ref().to_path_buf() })
+    }
+
+    /// The root directory of the store.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// The path of the file of the block `cid`.
+    fn path(&self, cid: &Cid) -> PathBuf {
+        let name = cid.to_string();
+        let shard = &name[name.len().saturating_sub(3)..name.len().saturating_sub(1)];
+        self.root.join(shard).join(format!("{}.data", name))
+    }
+}
+
+impl BlockStore for FsStore {
+    fn put(&mut self, cid: &Cid, bytes: &[u8]) -> Result<(), StoreError> {
+        verify(cid, bytes)?;
+        let path = self.path(cid);
+        // A file that doesn't verify, e.g. one left truncated by a crash, is replaced.
+        match fs::read(&path) {
+            Ok(existing) if verify(cid, &existing).is_ok() => return Ok(()),
+            Ok(_) => {},
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {},
+            Err(e) => return Err(e.into()),
+        }
+        fs::create_dir_all(path.parent().expect("block paths are in a shard"))?;
+        // Written aside and renamed into place, so a block file is either complete or absent.
+        let tmp = path.with_extension("tmp");
+        {
+            let mut file = fs::File::create(&tmp)?;
+            file.write_all(bytes)?;
+            file.sync_all()?;
+        }
+        fs::rename(&tmp, &path)?;
+        Ok(())
+    }
+
+    fn get(&self, cid: &Cid) -> Result<Option<Vec<u8>>, StoreError> {
+        match fs::read(self.path(cid)) {
+            Ok(bytes) => verify(cid, &bytes).map(|_| Some(bytes)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn has(&self, cid: &Cid) -> Result<bool, StoreError> {
+        Ok(self.path(cid).is_file())
+    }
+
+    fn delete(&mut self, cid: &Cid) -> Result<bool, StoreError> {
+        match fs::remove_file(self.path(cid)) {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bytecode::name_cid;
+    use crate::dag_cbor::Ipld;
+    use cid::Codec;
+
+    fn block(s: &str) -> (Cid, Vec<u8>) {
+        let bytes = dag_cbor::encode(&Ipld::String(s.to_string()));
+        (dag_cbor::cid(&bytes), bytes)
+    }
+
+    /// The behaviour every store has.
+    fn exercise(store: &mut dyn BlockStore) {
+        let (cid, bytes) = block("hello");
+        assert_eq!(store.has(&cid), Ok(false));
+        assert_eq!(store.get(&cid), Ok(None));
+        store.put(&cid, &bytes).unwrap();
+        assert_eq!(store.has(&cid), Ok(true));
+        assert_eq!(store.get(&cid), Ok(Some(bytes.clone())));
+        store.put(&cid, &bytes).unwrap();
+
+        let (other, other_bytes) = block("world");
+        assert_eq!(store.put(&other, &bytes), Err(StoreError::HashMismatch(other.clone())));
+        assert_eq!(store.has(&other), Ok(false));
+        assert_eq!(store.put_cbor(&other_bytes), Ok(other.clone()));
+
+        // Identity CIDs, like the names in bytecode, hold their block.
+        let name = name_cid("x");
+        store.put(&name, b"x").unwrap();
+        assert_eq!(store.get(&name), Ok(Some(b"x".to_vec())));
+
+        let custom = Cid::new_v1(Codec::Raw, multihash::wrap(multihash::Code::Custom(0x300000), b"x"));
+        assert_eq!(store.put(&custom, b"x"), Err(StoreError::UnsupportedHash(custom.clone())));
+
+        assert_eq!(store.delete(&cid), Ok(true));
+        assert_eq!(store.delete(&cid), Ok(false));
+        assert_eq!(store.get(&cid), Ok(None));
+        assert_eq!(store.get(&other), Ok(Some(other_bytes)));
+    }
+
+    #[test]
+    fn memory_store() {
+        let mut store = MemoryStore::new();
+        exercise(&mut store);
+        assert_eq!(store.len(), 2);
+
+        let (cid, _) = block("hello");
+        let tampered: MemoryStore = vec![(cid.clone(), b"tampered".to_vec())].into_iter().collect();
+        assert_eq!(tampered.has(&cid), Ok(true));
+        assert_eq!(tampered.get(&cid), Err(StoreError::HashMismatch(cid)));
+    }
+
+    #[test]
+    fn fs_store() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = FsStore::open(dir.path().join("blocks")).unwrap();
+        exercise(&mut store);
+
+        let (cid, bytes) = block("hello");
+        store.put(&cid, &bytes).unwrap();
+        let name = cid.to_string();
+        let path = store.root().join(&name[name.len() - 3..name.len() - 1]).join(format!("{}.data", name));
+        assert_eq!(fs::read(&path).unwrap(), bytes);
+
+        // Blocks persist, and are checked when they are read back.
+        let reopened = FsStore::open(store.root()).unwrap();
+        assert_eq!(reopened.get(&cid), Ok(Some(bytes.clone())));
+        fs::write(&path, b"tampered").unwrap();
+        assert_eq!(reopened.get(&cid), Err(StoreError::HashMismatch(cid.clone())));
+
+        // Putting the block again repairs it.
+        store.put(&cid, &bytes).unwrap();
+        assert_eq!(reopened.get(&cid), Ok(Some(bytes.clone())));
+        fs::write(&path, &bytes[..bytes.len() - 1]).unwrap();
+        store.put(&cid, &bytes).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), bytes);
+    }
+}